@@ -1,9 +1,14 @@
 use std::env;
 use std::path::Path;
 use dotenv::dotenv;
-use std::sync::Once;
+use std::sync::{Once, OnceLock};
 use url::Url;
+use chrono_tz::Tz;
 use ollama_rs::Ollama;
+use ollama_rs::generation::options::GenerationOptions;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use crate::models::session_store::SessionStoreKind;
+use crate::models::persona::Persona;
 
 // A global initializer to ensure the `.env` file is loaded only once
 static INIT: Once = Once::new();
@@ -15,109 +20,628 @@ pub fn setup() {
     });
 }
 
+/// How log lines are written: `LOG_FORMAT=text` (default) is env_logger's human format, `json`
+/// writes one JSON object per line with the record's structured fields (session_id, intent, ...)
+/// for log aggregators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+pub fn log_format() -> Result<LogFormat, String> {
+    match env::var("LOG_FORMAT") {
+        Err(_) => Ok(LogFormat::Text),
+        Ok(format) => parse_log_format(&format),
+    }
+}
+
+pub fn parse_log_format(format: &str) -> Result<LogFormat, String> {
+    match format.trim().to_lowercase().as_str() {
+        "" | "text" => Ok(LogFormat::Text),
+        "json" => Ok(LogFormat::Json),
+        other => Err(format!("unknown LOG_FORMAT \"{}\" (expected text or json)", other)),
+    }
+}
+
 pub fn init_logging() {
-    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    let mut builder = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    let format = log_format();
+    if format == Ok(LogFormat::Json) {
+        builder.format(|buf, record| {
+            use std::io::Write;
+            writeln!(buf, "{}", json_log_line(record, &chrono::Utc::now().to_rfc3339()))
+        });
+    }
+    builder.init();
+
+    if let Err(e) = format {
+        log::warn!("{}; using text logs", e);
+    }
+}
+
+/// Formats a record as a single-line JSON object: timestamp, level, target and message, plus
+/// any key-values attached with `info!(session_id = id; "...")`.
+pub fn json_log_line(record: &log::Record, timestamp: &str) -> String {
+    struct Fields(serde_json::Map<String, serde_json::Value>);
+
+    impl<'kvs> log::kv::VisitSource<'kvs> for Fields {
+        fn visit_pair(&mut self, key: log::kv::Key<'kvs>, value: log::kv::Value<'kvs>) -> Result<(), log::kv::Error> {
+            self.0.insert(key.to_string(), serde_json::Value::String(value.to_string()));
+            Ok(())
+        }
+    }
+
+    let mut fields = Fields(serde_json::Map::new());
+    fields.0.insert("timestamp".to_string(), timestamp.into());
+    fields.0.insert("level".to_string(), record.level().as_str().into());
+    fields.0.insert("target".to_string(), record.target().into());
+    fields.0.insert("message".to_string(), record.args().to_string().into());
+    // A field can't fail to visit, so there's no error to handle
+    let _ = record.key_values().visit(&mut fields);
+    serde_json::Value::Object(fields.0).to_string()
 }
 
 pub const MODEL_NAME: &str = "llama3.2";
 pub const SYSTEM_PROMPT: &str = "You are a helpful assistant for writing emails";
 
-pub fn ollama_port() -> u16 {
+pub fn ollama_port() -> Result<u16, ConfigError> {
     // Extract port from URL
-    let url = ollama_host();
+    let url = ollama_host()?;
     match Url::parse(&url) {
-        Ok(parsed_url) => parsed_url.port().unwrap_or(11434),
-        Err(_) => 11434
+        Ok(parsed_url) => Ok(parsed_url.port().unwrap_or(11434)),
+        Err(_) => Ok(11434)
+    }
+}
+
+pub fn ollama_host() -> Result<String, ConfigError> {
+    Ok(load()?.ollama_url)
+}
+
+/// The minimum length, in bytes, of the key used to sign session cookies.
+pub const MIN_SESSION_SECRET_BYTES: usize = 64;
+
+/// Returns true when running in development mode (`APP_ENV=development`).
+pub fn is_dev_mode() -> bool {
+    env::var("APP_ENV")
+        .map(|mode| mode.eq_ignore_ascii_case("development") || mode.eq_ignore_ascii_case("dev"))
+        .unwrap_or(false)
+}
+
+/// Reads the session cookie signing secret from `SESSION_SECRET`.
+///
+/// Returns `Ok(None)` when the variable is not set, so the caller can decide whether
+/// an ephemeral key is acceptable (development) or startup should fail (production).
+pub fn session_secret() -> Result<Option<Vec<u8>>, String> {
+    match env::var("SESSION_SECRET") {
+        Ok(secret) if !secret.trim().is_empty() => decode_session_secret(&secret).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Decodes a session secret given as hex or base64 and checks it is long enough.
+pub fn decode_session_secret(secret: &str) -> Result<Vec<u8>, String> {
+    let secret = secret.trim();
+    let is_hex = secret.len().is_multiple_of(2) && secret.chars().all(|c| c.is_ascii_hexdigit());
+
+    let bytes = if is_hex {
+        (0..secret.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&secret[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|e| format!("SESSION_SECRET is not valid hex: {}", e))?
+    } else {
+        STANDARD.decode(secret)
+            .map_err(|e| format!("SESSION_SECRET is neither valid hex nor base64: {}", e))?
+    };
+
+    if bytes.len() < MIN_SESSION_SECRET_BYTES {
+        return Err(format!(
+            "SESSION_SECRET must decode to at least {} bytes, got {}",
+            MIN_SESSION_SECRET_BYTES,
+            bytes.len()
+        ));
+    }
+
+    Ok(bytes)
+}
+
+/// Which session store to use: `SESSION_STORE=cookie` (default) keeps session state in the signed
+/// cookie, `memory` keeps it on the server and only puts a session key in the cookie.
+pub fn session_store_kind() -> Result<SessionStoreKind, String> {
+    match env::var("SESSION_STORE") {
+        Err(_) => Ok(SessionStoreKind::Cookie),
+        Ok(kind) => parse_session_store_kind(&kind),
+    }
+}
+
+pub fn parse_session_store_kind(kind: &str) -> Result<SessionStoreKind, String> {
+    match kind.trim().to_lowercase().as_str() {
+        "" | "cookie" => Ok(SessionStoreKind::Cookie),
+        "memory" => Ok(SessionStoreKind::Memory),
+        other => Err(format!("unknown SESSION_STORE \"{}\" (expected cookie or memory)", other)),
+    }
+}
+
+/// How long, in seconds, to wait for in-flight requests to finish on shutdown.
+pub fn shutdown_timeout_secs() -> u64 {
+    env::var("SHUTDOWN_TIMEOUT_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(30)
+}
+
+/// Path of the JSON file holding named compose templates.
+pub fn templates_path() -> String {
+    env::var("TEMPLATES_PATH").unwrap_or_else(|_| "./cfg/templates.json".to_string())
+}
+
+/// Path of the TOML file of per-intent prompt overrides, keyed by intent name.
+pub fn intent_prompts_path() -> String {
+    env::var("INTENT_PROMPTS_PATH").unwrap_or_else(|_| "./cfg/intent_prompts.toml".to_string())
+}
+
+/// Maximum chat requests per minute for a single session; 0 disables the limit.
+pub fn chat_rate_limit_per_minute() -> u32 {
+    env::var("CHAT_RATE_LIMIT_PER_MINUTE")
+        .ok()
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(20)
+}
+
+/// How many sessions may load their inboxes in the background at once
+/// (`SESSION_LOAD_CONCURRENCY`, default 2); others wait their turn. At least 1.
+pub fn session_load_concurrency() -> usize {
+    env::var("SESSION_LOAD_CONCURRENCY")
+        .ok()
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(2)
+        .max(1)
+}
+
+/// Whether List summaries tag each email with its urgency (`LIST_URGENCY_TAGS`, on by default).
+pub fn list_urgency_tags() -> bool {
+    env::var("LIST_URGENCY_TAGS")
+        .map(|flag| !matches!(flag.to_lowercase().as_str(), "0" | "false" | "off" | "no"))
+        .unwrap_or(true)
+}
+
+/// Longest chat message, in characters, accepted from the user (`MAX_MESSAGE_CHARS`, default 2000).
+/// Longer messages are rejected; 0 disables the limit.
+pub fn max_message_chars() -> usize {
+    env::var("MAX_MESSAGE_CHARS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(2000)
+}
+
+/// Longest email body, in characters, included in LLM context (`MAX_BODY_CHARS`, default 4000).
+/// 0 disables truncation; the Display intent always shows full bodies.
+pub fn max_body_chars() -> usize {
+    env::var("MAX_BODY_CHARS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(4000)
+}
+
+/// Body length, in characters, above which Explain questions about a specific topic
+/// ("what did Sarah say about the Singapore office?") are answered from the matching
+/// passages only (`EXPLAIN_SNIPPET_THRESHOLD`, default 2000).
+pub fn explain_snippet_threshold() -> usize {
+    env::var("EXPLAIN_SNIPPET_THRESHOLD")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(2000)
+}
+
+/// How many of the passages most similar to a question are retrieved from an email to answer
+/// it (`SEMANTIC_TOP_N`, default 3). Values below 1 fall back to the default.
+pub fn semantic_top_n() -> usize {
+    parse_semantic_top_n(env::var("SEMANTIC_TOP_N").ok().as_deref())
+}
+
+fn parse_semantic_top_n(value: Option<&str>) -> usize {
+    value
+        .and_then(|n| n.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(3)
+}
+
+/// Column width HTML email bodies are wrapped to when converted to plain text
+/// (`HTML_WRAP_WIDTH`, default 80).
+pub fn html_wrap_width() -> usize {
+    env::var("HTML_WRAP_WIDTH")
+        .ok()
+        .and_then(|width| width.parse().ok())
+        .filter(|width: &usize| *width > 0)
+        .unwrap_or(80)
+}
+
+/// How many recent emails a General question is answered over when no email matches it
+/// (`GENERAL_FALLBACK_EMAILS`, default 20).
+pub fn general_fallback_emails() -> usize {
+    env::var("GENERAL_FALLBACK_EMAILS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(20)
+}
+
+/// How many of the best matches a vague search (see `QueryCriteria::is_weak`) passes to the LLM
+/// (`WEAK_QUERY_CONTEXT_EMAILS`, default 5).
+pub fn weak_query_context_emails() -> usize {
+    env::var("WEAK_QUERY_CONTEXT_EMAILS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(5)
+}
+
+/// Most emails an Explain passes to the LLM (`EXPLAIN_CONTEXT_EMAILS`, default 3), kept within 1 to 3.
+pub fn explain_context_emails() -> usize {
+    env::var("EXPLAIN_CONTEXT_EMAILS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(3)
+        .clamp(1, 3)
+}
+
+/// Most related emails a Compose passes to the LLM (`COMPOSE_CONTEXT_EMAILS`, default 5).
+pub fn compose_context_emails() -> usize {
+    env::var("COMPOSE_CONTEXT_EMAILS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(5)
+}
+
+/// Most emails a General question passes to the LLM (`GENERAL_CONTEXT_EMAILS`, default 20).
+/// At least 1.
+pub fn general_context_emails() -> usize {
+    env::var("GENERAL_CONTEXT_EMAILS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(20)
+        .max(1)
+}
+
+/// How many times the MeiliSearch health check is tried before giving up
+/// (`MEILISEARCH_HEALTH_ATTEMPTS`, default 10), so startup can wait for MeiliSearch to come up.
+pub fn meilisearch_health_attempts() -> u32 {
+    env::var("MEILISEARCH_HEALTH_ATTEMPTS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(10)
+}
+
+/// Wait before the second MeiliSearch health check, in milliseconds
+/// (`MEILISEARCH_HEALTH_INTERVAL_MS`, default 500). Later waits double, up to 10 seconds.
+pub fn meilisearch_health_interval_ms() -> u64 {
+    env::var("MEILISEARCH_HEALTH_INTERVAL_MS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(500)
+}
+
+/// Gmail search query selecting which messages are loaded (`GMAIL_QUERY`, default "is:inbox"),
+/// e.g. "is:unread" or "label:work newer_than:7d".
+pub fn gmail_query() -> String {
+    env::var("GMAIL_QUERY")
+        .ok()
+        .filter(|query| !query.trim().is_empty())
+        .unwrap_or_else(|| "is:inbox".to_string())
+}
+
+/// Scope requested when `GMAIL_SCOPES` isn't set: reading mail, nothing else.
+pub const GMAIL_READONLY_SCOPE: &str = "https://www.googleapis.com/auth/gmail.readonly";
+
+/// OAuth scopes requested when signing in to Gmail (`GMAIL_SCOPES`, comma or space separated,
+/// default read-only). Short names like "gmail.send" are expanded to full scope URLs.
+pub fn gmail_scopes() -> Vec<String> {
+    env::var("GMAIL_SCOPES")
+        .map(|scopes| parse_gmail_scopes(&scopes))
+        .ok()
+        .filter(|scopes| !scopes.is_empty())
+        .unwrap_or_else(|| vec![GMAIL_READONLY_SCOPE.to_string()])
+}
+
+/// Splits a `GMAIL_SCOPES` value into full scope URLs, dropping duplicates and keeping the order.
+pub fn parse_gmail_scopes(scopes: &str) -> Vec<String> {
+    let mut parsed: Vec<String> = Vec::new();
+    for scope in scopes.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty()) {
+        let scope = if scope.contains("://") {
+            scope.to_string()
+        } else {
+            format!("https://www.googleapis.com/auth/{}", scope)
+        };
+        if !parsed.contains(&scope) {
+            parsed.push(scope);
+        }
     }
+    parsed
 }
 
-pub fn ollama_host() -> String {
-    Config::from_env().unwrap().ollama_url
+/// Time zone that relative dates like "today" and "last week" are resolved in (`DEFAULT_TZ`, an
+/// IANA name such as "Australia/Sydney", default UTC).
+pub fn default_tz() -> Tz {
+    match env::var("DEFAULT_TZ") {
+        Err(_) => Tz::UTC,
+        Ok(name) if name.trim().is_empty() => Tz::UTC,
+        Ok(name) => name.trim().parse().unwrap_or_else(|_| {
+            log::warn!("Unknown DEFAULT_TZ \"{}\", using UTC", name);
+            Tz::UTC
+        }),
+    }
 }
 
+/// The tone the assistant answers in when a session hasn't chosen one (`PERSONA`: terse,
+/// friendly or professional; unset by default). Unknown names are ignored with a warning.
+pub fn persona() -> Option<Persona> {
+    let name = env::var("PERSONA").ok().filter(|name| !name.trim().is_empty())?;
+    let persona = Persona::from_name(&name);
+    if persona.is_none() {
+        log::warn!("Unknown PERSONA \"{}\", using the default tone", name);
+    }
+    persona
+}
 
+/// How many times a rate-limited Gmail request is retried (`GMAIL_MAX_RETRIES`, default 5).
+pub fn gmail_max_retries() -> u32 {
+    env::var("GMAIL_MAX_RETRIES")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(5)
+}
 
-pub fn meilisearch_url() -> String {
-   Config::from_env().unwrap().meilisearch_url
+/// Whether an inbox load keeps the messages it did fetch when others fail
+/// (`GMAIL_PARTIAL_RESULTS`, on by default).
+pub fn gmail_partial_results() -> bool {
+    env::var("GMAIL_PARTIAL_RESULTS")
+        .map(|flag| !matches!(flag.to_lowercase().as_str(), "0" | "false" | "off" | "no"))
+        .unwrap_or(true)
 }
 
-pub fn meilisearch_admin_key() -> String {
-   Config::from_env().unwrap().meilisearch_admin_key
+/// Upper bound on concurrent Gmail message fetches, to stay inside Gmail's per-user rate limits.
+pub const MAX_GMAIL_FETCH_CONCURRENCY: usize = 25;
+
+/// How many Gmail message details to fetch at once (`GMAIL_FETCH_CONCURRENCY`, default 10).
+pub fn gmail_fetch_concurrency() -> usize {
+    env::var("GMAIL_FETCH_CONCURRENCY")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(10)
+        .clamp(1, MAX_GMAIL_FETCH_CONCURRENCY)
 }
 
+/// Most messages Gmail will list in one page, and so the most `MAX_EMAILS` can be.
+pub const MAX_GMAIL_LIST_RESULTS: usize = 500;
 
+/// How many of the most recent matching messages a session loads (`MAX_EMAILS`, default 100).
+pub fn max_emails() -> usize {
+    env::var("MAX_EMAILS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(100)
+        .clamp(1, MAX_GMAIL_LIST_RESULTS)
+}
+
+/// How many emails are written to the search index per request when a session loads
+/// (`EMAIL_BATCH_SIZE`, default 50).
+pub fn email_batch_size() -> usize {
+    env::var("EMAIL_BATCH_SIZE")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(50)
+        .max(1)
+}
+
+/// The user's own email address, used to leave them out of reply-all recipients.
+pub fn user_email() -> Option<String> {
+    env::var("USER_EMAIL").ok().filter(|email| !email.is_empty())
+}
+
+/// Index emails are stored in when `MEILI_INDEX` isn't set.
+pub const DEFAULT_MEILI_INDEX: &str = "emails";
+
+/// The MeiliSearch and Ollama settings, read from the environment once by `load`.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Config {
     pub meilisearch_url: String,
     pub meilisearch_search_key: String,
     pub meilisearch_admin_key: String,
+    /// Name of the email index (`MEILI_INDEX`, default "emails")
+    pub meilisearch_index: String,
     pub ollama_url: String,
 }
 
+/// Why the required settings (`MEILI_URL`, `MEILI_SEARCH_KEY`, `MEILI_ADMIN_KEY`, `OLLAMA_URL`)
+/// couldn't be loaded, naming the variable at fault.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ConfigError {
+    #[error("{0} not found in environment")]
+    Missing(&'static str),
+
+    #[error("{0} cannot be empty")]
+    Empty(&'static str),
+
+    #[error("{name} is not a valid URL: \"{value}\"")]
+    InvalidUrl { name: &'static str, value: String },
+
+    #[error("the LLM is disabled (DISABLE_LLM)")]
+    LlmDisabled,
+}
+
 impl Config {
-    pub fn from_env() -> Result<Self, String> {
+    pub fn from_env() -> Result<Self, ConfigError> {
         // Try to load from .env file if it exists
         if Path::new(".env").exists() {
             dotenv().ok();
         }
+        Self::from_vars(|name| env::var(name).ok())
+    }
 
-        // Get the search key, fail if empty
-        let search_key = env::var("MEILI_SEARCH_KEY")
-            .map_err(|_| "MEILI_SEARCH_KEY not found in environment".to_string())?;
-        if search_key.is_empty() {
-            return Err("MEILI_SEARCH_KEY cannot be empty".to_string());
-        }
-
-        // Get the admin key, fail if empty
-        let admin_key = env::var("MEILI_ADMIN_KEY")
-            .map_err(|_| "MEILI_ADMIN_KEY not found in environment".to_string())?;
-        if admin_key.is_empty() {
-            return Err("MEILI_ADMIN_KEY cannot be empty".to_string());
-        }
-
-        let config = Config {
-            meilisearch_url: env::var("MEILI_URL").unwrap(),
-            meilisearch_search_key: search_key,
-            meilisearch_admin_key: admin_key,
-            ollama_url: env::var("OLLAMA_URL").unwrap(),
+    /// Reads and checks every required setting, looking each variable up with `var`.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let required = |name: &'static str| match var(name) {
+            None => Err(ConfigError::Missing(name)),
+            Some(value) if value.trim().is_empty() => Err(ConfigError::Empty(name)),
+            Some(value) => Ok(value),
+        };
+        let url = |name: &'static str| {
+            let value = required(name)?;
+            match Url::parse(&value) {
+                Ok(_) => Ok(value),
+                Err(_) => Err(ConfigError::InvalidUrl { name, value }),
+            }
         };
 
-        Ok(config)
+        Ok(Config {
+            meilisearch_url: url("MEILI_URL")?,
+            meilisearch_search_key: required("MEILI_SEARCH_KEY")?,
+            meilisearch_admin_key: required("MEILI_ADMIN_KEY")?,
+            meilisearch_index: var("MEILI_INDEX")
+                .map(|index| index.trim().to_string())
+                .filter(|index| !index.is_empty())
+                .unwrap_or_else(|| DEFAULT_MEILI_INDEX.to_string()),
+            ollama_url: url("OLLAMA_URL")?,
+        })
     }
 
     // Create a method for testing that takes a custom environment
     #[cfg(test)]
-    fn from_test_env(test_env: &std::collections::HashMap<String, String>) -> Result<Self, String> {
-        // Handle search key
-        let search_key = test_env.get("MEILI_SEARCH_KEY")
-            .ok_or_else(|| "MEILI_SEARCH_KEY not found in test environment".to_string())?;
-        if search_key.is_empty() {
-            return Err("MEILI_SEARCH_KEY cannot be empty".to_string());
-        }
+    fn from_test_env(test_env: &std::collections::HashMap<String, String>) -> Result<Self, ConfigError> {
+        Self::from_vars(|name| test_env.get(name).cloned())
+    }
+}
 
-        // Handle admin key
-        let admin_key = test_env.get("MEILI_ADMIN_KEY")
-            .ok_or_else(|| "MEILI_ADMIN_KEY not found in test environment".to_string())?;
-        if admin_key.is_empty() {
-            return Err("MEILI_ADMIN_KEY cannot be empty".to_string());
-        }
+static LOADED: OnceLock<Config> = OnceLock::new();
 
-        let config = Config {
-            meilisearch_url: test_env.get("MEILI_URL")
-                .cloned()
-                .unwrap_or_else(|| "http://localhost:7700".to_string()),
-            meilisearch_search_key: search_key.clone(),
-            meilisearch_admin_key: admin_key.clone(),
-            ollama_url: test_env.get("OLLAMA_URL")
-                .cloned()
-                .unwrap_or_else(|| "http://localhost:11434".to_string()),
-        };
+/// The settings, read from the environment (and `.env`) on the first call and kept for the rest
+/// of the run, so every caller sees the same values. `main` calls this at startup and puts the
+/// result in `AppState`. A failed read isn't kept, so the next call tries again.
+pub fn load() -> Result<Config, ConfigError> {
+    load_once(&LOADED, Config::from_env).cloned()
+}
+
+// `load` with the cell and loader passed in, so tests can count the reads
+fn load_once(cell: &OnceLock<Config>, read: impl FnOnce() -> Result<Config, ConfigError>) -> Result<&Config, ConfigError> {
+    if let Some(config) = cell.get() {
+        return Ok(config);
+    }
+    let config = read()?;
+    Ok(cell.get_or_init(|| config))
+}
 
-        Ok(config)
+/// The Ollama client every LLM request goes through. Fails with `ConfigError::LlmDisabled`
+/// when `DISABLE_LLM` is on, so nothing can reach Ollama in that mode.
+pub fn create_ollama() -> Result<Ollama, ConfigError> {
+    ollama_client(llm_disabled())
+}
+
+fn ollama_client(disabled: bool) -> Result<Ollama, ConfigError> {
+    if disabled {
+        return Err(ConfigError::LlmDisabled);
     }
+    Ok(Ollama::new(ollama_host()?, ollama_port()?))
 }
 
-pub fn create_ollama() -> Ollama {
-    Ollama::new(ollama_host(), ollama_port())
+/// Whether the app runs as a plain searchable mail client without Ollama (`DISABLE_LLM`, off by
+/// default). Listing, showing, searching and counting still work; explaining and drafting don't.
+pub fn llm_disabled() -> bool {
+    env::var("DISABLE_LLM")
+        .map(|flag| matches!(flag.to_lowercase().as_str(), "1" | "true" | "on" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Whether startup fails when the Ollama model isn't available (`OLLAMA_STRICT_STARTUP`, off by
+/// default, in which case only a warning is logged).
+pub fn ollama_strict_startup() -> bool {
+    env::var("OLLAMA_STRICT_STARTUP")
+        .map(|flag| matches!(flag.to_lowercase().as_str(), "1" | "true" | "on" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Whether Reply and Compose drafts get a second, grammar-and-spelling-only pass through the
+/// model before they're shown (`POLISH_DRAFTS`, off by default to save a round-trip).
+pub fn polish_drafts() -> bool {
+    env::var("POLISH_DRAFTS")
+        .map(|flag| matches!(flag.to_lowercase().as_str(), "1" | "true" | "on" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Whether HTML bodies have scripts, tracking pixels and external resources stripped before
+/// they are converted to text (`SANITIZE_HTML`, on by default).
+pub fn sanitize_html() -> bool {
+    env::var("SANITIZE_HTML")
+        .map(|flag| !matches!(flag.to_lowercase().as_str(), "0" | "false" | "off" | "no"))
+        .unwrap_or(true)
+}
+
+/// Whether logs mask email addresses and truncate subjects, bodies and messages
+/// (`REDACT_LOGS`, off by default), for running on real mail with verbose logging.
+pub fn redact_logs() -> bool {
+    env::var("REDACT_LOGS")
+        .map(|flag| matches!(flag.to_lowercase().as_str(), "1" | "true" | "on" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Model used to classify intents (`CLASSIFIER_MODEL`, default `MODEL_NAME`). A small, fast
+/// model is usually enough here.
+pub fn classifier_model() -> String {
+    model_from_env("CLASSIFIER_MODEL")
+}
+
+/// Model used to draft, explain and answer (`GENERATION_MODEL`, default `MODEL_NAME`).
+pub fn generation_model() -> String {
+    model_from_env("GENERATION_MODEL")
+}
+
+fn model_from_env(name: &str) -> String {
+    model_or_default(env::var(name).ok())
+}
+
+fn model_or_default(model: Option<String>) -> String {
+    model
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty())
+        .unwrap_or_else(|| MODEL_NAME.to_string())
+}
+
+/// Sampling temperature for drafting and answering (`OLLAMA_TEMPERATURE`, default 0.7).
+pub fn ollama_temperature() -> f32 {
+    env::var("OLLAMA_TEMPERATURE")
+        .ok()
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(0.7)
+}
+
+/// Sampling temperature for intent classification (`OLLAMA_CLASSIFY_TEMPERATURE`, default 0.0),
+/// kept low so the model's JSON answer is deterministic.
+pub fn ollama_classify_temperature() -> f32 {
+    env::var("OLLAMA_CLASSIFY_TEMPERATURE")
+        .ok()
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(0.0)
+}
+
+/// Context window, in tokens, requested from Ollama (`OLLAMA_NUM_CTX`). Unset keeps the model's default.
+pub fn ollama_num_ctx() -> Option<u32> {
+    env::var("OLLAMA_NUM_CTX").ok().and_then(|n| n.parse().ok())
+}
+
+/// Options for requests that draft or answer, where some variety is welcome.
+pub fn generation_options() -> GenerationOptions {
+    with_num_ctx(GenerationOptions::default().temperature(ollama_temperature()))
+}
+
+/// Options for intent classification.
+pub fn classification_options() -> GenerationOptions {
+    with_num_ctx(GenerationOptions::default().temperature(ollama_classify_temperature()))
+}
+
+fn with_num_ctx(options: GenerationOptions) -> GenerationOptions {
+    match ollama_num_ctx() {
+        Some(num_ctx) => options.num_ctx(num_ctx),
+        None => options,
+    }
 }
 
 
@@ -136,13 +660,78 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_log_format() {
+        assert_eq!(parse_log_format("text"), Ok(LogFormat::Text));
+        assert_eq!(parse_log_format(" JSON "), Ok(LogFormat::Json));
+        assert!(parse_log_format("xml").is_err());
+    }
+
+    #[test]
+    fn test_json_log_line_includes_structured_fields() {
+        let fields = [("session_id", "abc-123"), ("intent", "list")];
+        let record = log::Record::builder()
+            .args(format_args!("Processing message"))
+            .level(log::Level::Info)
+            .target("AdukiChatAgent::handlers::chat_handler")
+            .key_values(&fields)
+            .build();
+
+        let line = json_log_line(&record, "2025-05-12T09:00:00+00:00");
+        assert!(!line.contains('\n'));
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["timestamp"], "2025-05-12T09:00:00+00:00");
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["target"], "AdukiChatAgent::handlers::chat_handler");
+        assert_eq!(json["message"], "Processing message");
+        assert_eq!(json["session_id"], "abc-123");
+        assert_eq!(json["intent"], "list");
+    }
+
+    #[test]
+    fn test_parse_semantic_top_n() {
+        assert_eq!(parse_semantic_top_n(Some("5")), 5);
+        assert_eq!(parse_semantic_top_n(Some("0")), 3);
+        assert_eq!(parse_semantic_top_n(Some("many")), 3);
+        assert_eq!(parse_semantic_top_n(None), 3);
+    }
+
+    #[test]
+    fn test_model_or_default() {
+        assert_eq!(model_or_default(Some(" tiny-classifier ".to_string())), "tiny-classifier");
+        assert_eq!(model_or_default(Some("  ".to_string())), MODEL_NAME);
+        assert_eq!(model_or_default(None), MODEL_NAME);
+    }
+
+    #[test]
+    fn test_disabled_llm_never_builds_a_client() {
+        assert_eq!(ollama_client(true).unwrap_err(), ConfigError::LlmDisabled);
+    }
+
+    #[test]
+    fn test_parse_gmail_scopes() {
+        assert_eq!(parse_gmail_scopes("gmail.readonly, gmail.send gmail.readonly"), vec![
+            GMAIL_READONLY_SCOPE.to_string(),
+            "https://www.googleapis.com/auth/gmail.send".to_string(),
+        ]);
+        assert_eq!(parse_gmail_scopes("https://mail.google.com/"), vec!["https://mail.google.com/".to_string()]);
+        assert!(parse_gmail_scopes(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_parse_session_store_kind() {
+        assert_eq!(parse_session_store_kind("cookie"), Ok(SessionStoreKind::Cookie));
+        assert_eq!(parse_session_store_kind(" Memory "), Ok(SessionStoreKind::Memory));
+        assert!(parse_session_store_kind("redis").is_err());
+    }
+
     #[test]
     fn test_with_env_vars() {
         // Ensure environment variables are loaded
         setup();
 
         // Just verify that we can access the variables and they're not empty
-        let meili_url = meilisearch_url();
+        let meili_url = load().unwrap().meilisearch_url;
         assert!(is_valid_url(&meili_url), "MEILI_URL should be a valid URL");
         println!("MeiliSearch URL: {}", meili_url);
     }
@@ -223,9 +812,25 @@ mod tests {
             assert_eq!(config.meilisearch_url, "http://localhost:7700");
             assert_eq!(config.meilisearch_search_key, "test_search_key");
             assert_eq!(config.meilisearch_admin_key, "test_admin_key");
+            assert_eq!(config.meilisearch_index, "emails");
             assert_eq!(config.ollama_url, "http://localhost:11434");
         }
 
+        #[test]
+        fn test_config_index_name_from_env() {
+            let mut test_env = HashMap::new();
+            test_env.insert("MEILI_URL".to_string(), "http://localhost:7700".to_string());
+            test_env.insert("MEILI_SEARCH_KEY".to_string(), "test_search_key".to_string());
+            test_env.insert("MEILI_ADMIN_KEY".to_string(), "test_admin_key".to_string());
+            test_env.insert("OLLAMA_URL".to_string(), "http://localhost:11434".to_string());
+            test_env.insert("MEILI_INDEX".to_string(), " staging_emails ".to_string());
+            assert_eq!(Config::from_test_env(&test_env).unwrap().meilisearch_index, "staging_emails");
+
+            // A blank name keeps the default rather than an unusable index
+            test_env.insert("MEILI_INDEX".to_string(), "  ".to_string());
+            assert_eq!(Config::from_test_env(&test_env).unwrap().meilisearch_index, DEFAULT_MEILI_INDEX);
+        }
+
         #[test]
         fn test_config_from_test_env_missing_keys() {
             // Test with missing search key
@@ -254,6 +859,69 @@ mod tests {
             let result = Config::from_test_env(&test_env);
             assert!(result.is_err());
         }
+
+        #[test]
+        fn test_config_is_read_once() {
+            let mut test_env = HashMap::new();
+            test_env.insert("MEILI_URL".to_string(), "http://localhost:7700".to_string());
+            test_env.insert("MEILI_SEARCH_KEY".to_string(), "test_search_key".to_string());
+            test_env.insert("MEILI_ADMIN_KEY".to_string(), "test_admin_key".to_string());
+            test_env.insert("OLLAMA_URL".to_string(), "http://localhost:11434".to_string());
+
+            let cell = OnceLock::new();
+            let reads = std::cell::Cell::new(0);
+            let read = || {
+                reads.set(reads.get() + 1);
+                Config::from_test_env(&test_env)
+            };
+
+            // A failed read isn't cached
+            assert!(load_once(&cell, || Err(ConfigError::Missing("MEILI_URL"))).is_err());
+            let first = load_once(&cell, read).unwrap().clone();
+            let second = load_once(&cell, read).unwrap();
+            assert_eq!(reads.get(), 1);
+            assert_eq!(&first, second);
+            assert_eq!(second.meilisearch_url, "http://localhost:7700");
+        }
+
+        #[test]
+        fn test_config_missing_meili_url_is_a_descriptive_error() {
+            let mut test_env = HashMap::new();
+            test_env.insert("MEILI_SEARCH_KEY".to_string(), "test_search_key".to_string());
+            test_env.insert("MEILI_ADMIN_KEY".to_string(), "test_admin_key".to_string());
+            test_env.insert("OLLAMA_URL".to_string(), "http://localhost:11434".to_string());
+
+            let error = Config::from_test_env(&test_env).err().unwrap();
+            assert_eq!(error, ConfigError::Missing("MEILI_URL"));
+            assert_eq!(error.to_string(), "MEILI_URL not found in environment");
+
+            test_env.insert("MEILI_URL".to_string(), "localhost 7700".to_string());
+            let error = Config::from_test_env(&test_env).err().unwrap();
+            assert_eq!(error.to_string(), "MEILI_URL is not a valid URL: \"localhost 7700\"");
+
+            test_env.insert("MEILI_URL".to_string(), " ".to_string());
+            assert_eq!(Config::from_test_env(&test_env).err(), Some(ConfigError::Empty("MEILI_URL")));
+        }
+    }
+
+    #[test]
+    fn test_decode_session_secret_hex_and_base64() {
+        let raw: Vec<u8> = (0..64).collect();
+
+        let hex: String = raw.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(decode_session_secret(&hex).unwrap(), raw);
+
+        let base64 = STANDARD.encode(&raw);
+        assert_eq!(decode_session_secret(&base64).unwrap(), raw);
+    }
+
+    #[test]
+    fn test_decode_session_secret_rejects_short_or_invalid() {
+        // 32 bytes of hex is too short
+        let short_hex = "ab".repeat(32);
+        assert!(decode_session_secret(&short_hex).is_err());
+
+        assert!(decode_session_secret("not a valid secret!").is_err());
     }
 
     // Test real functions with the current environment (non-strict tests)
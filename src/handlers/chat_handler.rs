@@ -1,41 +1,423 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{error::InternalError, web, HttpResponse};
 use actix_session::Session;
-use serde_json::Value;
+use serde::Deserialize;
 use log::{info, warn, error};
+use crate::config;
 use crate::routes::app_state::AppState;
+use crate::models::email::{DraftEmail, Email};
+use crate::models::email_db::EmailDBError;
+use crate::models::redaction::redact_text;
+use crate::handlers::email_handler::mailbox_error_response;
 use crate::services::chat_service;
 
+/// Body of a chat or classify request.
+#[derive(Debug, Deserialize)]
+pub struct ChatRequest {
+    pub message: String,
+    /// Only used when the session cookie is missing
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+const MESSAGE_REQUIRED: &str = "A non-empty \"message\" is required.";
+
+/// Body of a draft request: the email to reply to and what the reply should say.
+#[derive(Debug, Deserialize)]
+pub struct DraftRequest {
+    pub message_id: String,
+    #[serde(default)]
+    pub instruction: String,
+    /// Only used when the session cookie is missing
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+// Used when a draft request doesn't say what the reply should say
+const DEFAULT_DRAFT_INSTRUCTION: &str = "Write a reply to this email.";
+
+/// Rejects bodies that aren't a valid `ChatRequest` (missing or non-string `message`, bad JSON)
+/// with a 400 that says what was wrong.
+pub fn chat_request_json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|err, _req| {
+        let response = HttpResponse::BadRequest().body(format!("Invalid chat request: {}. {}", err, MESSAGE_REQUIRED));
+        InternalError::from_response(err, response).into()
+    })
+}
+
+// Oversized messages are turned away whole rather than cut short without the user knowing
+fn message_too_long_response() -> HttpResponse {
+    HttpResponse::PayloadTooLarge().body(format!(
+        "Messages can be at most {} characters. Please shorten yours and try again.",
+        config::max_message_chars()
+    ))
+}
+
 pub async fn handle_chat_request(
     data: web::Data<AppState>,
     session: Session,
-    req_body: web::Json<Value>
+    req_body: web::Json<ChatRequest>
 ) -> HttpResponse {
+    let req_body = req_body.into_inner();
+    if req_body.message.trim().is_empty() {
+        return HttpResponse::BadRequest().body(MESSAGE_REQUIRED);
+    }
+    if chat_service::message_too_long(&req_body.message, config::max_message_chars()) {
+        return message_too_long_response();
+    }
+
     // Retrieve session_id from cookie (or fallback)
     let session_id = if let Ok(Some(id)) = session.get::<String>("session_id") {
         id
     } else {
         warn!("No valid session_id found in cookie; falling back to request body");
-        req_body["session_id"].as_str().unwrap_or_default().to_string()
+        req_body.session_id.unwrap_or_default()
     };
 
+    // Unknown ids are turned away before they can get a rate limit bucket or a request lock
+    if !data.session_manager.contains(&session_id) {
+        error!(session_id = session_id.as_str(); "Session \"{}\" not found!", session_id);
+        return HttpResponse::InternalServerError().body("Session not initialized. Please refresh the page.");
+    }
+    if !data.rate_limiter.check(&session_id) {
+        warn!(session_id = session_id.as_str(); "Rate limit exceeded for session {}", session_id);
+        return HttpResponse::TooManyRequests().body("You're sending messages too quickly. Please wait a moment and try again.");
+    }
+
+    // Requests for the same session run one at a time so none of their updates are lost
+    let _request_guard = data.session_manager.lock_session(&session_id).await;
     if let Some(mut user_session) = data.session_manager.get(&session_id) {
-        let user_input = req_body["message"].as_str().unwrap_or_default().to_string();
-        info!("Processing message for session {}: {}", session_id, user_input);
+        let user_input = req_body.message;
+        info!(session_id = session_id.as_str(); "Processing message for session {}: {}", session_id, redact_text(&user_input));
 
+        let history_len = user_session.history.len();
         match chat_service::process_chat(&user_input, &mut user_session).await {
-            Ok(response_content) => {
-                // Update the session after processing
-                data.session_manager.insert(session_id.clone(), user_session);
-                // Return the raw response content without JSON wrapping
-                HttpResponse::Ok().content_type("text/plain").body(response_content)
+            Ok(response) => {
+                // Write this request's changes into the stored session in one step, appending
+                // to its history rather than replacing it with our possibly stale copy
+                data.session_manager.with_session(&session_id, |stored| {
+                    stored.history.extend(user_session.history.drain(history_len..));
+                    stored.last_deleted = user_session.last_deleted;
+                    stored.priority_senders = user_session.priority_senders;
+                    stored.last_draft = user_session.last_draft;
+                    stored.pending_bulk_delete = user_session.pending_bulk_delete;
+                    stored.reminders = user_session.reminders;
+                    stored.persona = user_session.persona;
+                    stored.last_email = user_session.last_email;
+                });
+                // Return the message along with any structured data (e.g. reply recipients)
+                HttpResponse::Ok().json(response)
             },
             Err(e) => {
-                error!("Error processing chat for session {}: {:?}", session_id, e);
-                HttpResponse::InternalServerError().body("Sorry, I encountered an error processing your request.")
+                error!(session_id = session_id.as_str(); "Error processing chat for session {}: {:?}", session_id, e);
+                match e.downcast_ref::<EmailDBError>() {
+                    // e.g. an email deleted by another tab between being found and acted on
+                    Some(EmailDBError::NotFound(_)) => HttpResponse::NotFound().body("That email is no longer in your mailbox."),
+                    _ => HttpResponse::InternalServerError().body("Sorry, I encountered an error processing your request."),
+                }
             }
         }
     } else {
-        error!("Session \"{}\" not found!", session_id);
+        error!(session_id = session_id.as_str(); "Session \"{}\" not found!", session_id);
         HttpResponse::InternalServerError().body("Session not initialized. Please refresh the page.")
     }
-}
\ No newline at end of file
+}
+
+/// Classifies a message's intent without acting on it, so the frontend can show what the bot
+/// thinks the user wants.
+pub async fn handle_classify_request(
+    data: web::Data<AppState>,
+    session: Session,
+    req_body: web::Json<ChatRequest>
+) -> HttpResponse {
+    let req_body = req_body.into_inner();
+    let message = req_body.message.trim();
+    if message.is_empty() {
+        return HttpResponse::BadRequest().body(MESSAGE_REQUIRED);
+    }
+    if chat_service::message_too_long(message, config::max_message_chars()) {
+        return message_too_long_response();
+    }
+
+    let session_id = if let Ok(Some(id)) = session.get::<String>("session_id") {
+        id
+    } else {
+        warn!("No valid session_id found in cookie; falling back to request body");
+        req_body.session_id.clone().unwrap_or_default()
+    };
+
+    // Classifying may call the LLM, so it's limited the same way as chatting
+    if !data.session_manager.contains(&session_id) {
+        error!(session_id = session_id.as_str(); "Session \"{}\" not found!", session_id);
+        return HttpResponse::InternalServerError().body("Session not initialized. Please refresh the page.");
+    }
+    if !data.rate_limiter.check(&session_id) {
+        warn!(session_id = session_id.as_str(); "Rate limit exceeded for session {}", session_id);
+        return HttpResponse::TooManyRequests().body("You're sending messages too quickly. Please wait a moment and try again.");
+    }
+
+    match chat_service::classify_intent(message).await {
+        Ok(classification) => HttpResponse::Ok().json(classification),
+        Err(e) => {
+            error!("Error classifying message '{}': {:?}", redact_text(message), e);
+            HttpResponse::BadGateway().body("Sorry, I couldn't classify that message right now.")
+        }
+    }
+}
+
+/// Drafts a reply to one stored email for a "suggest a reply" button. Unlike a Reply in chat,
+/// this skips intent classification and leaves the session's history and last draft untouched.
+pub async fn handle_draft_request(
+    data: web::Data<AppState>,
+    session: Session,
+    req_body: web::Json<DraftRequest>
+) -> HttpResponse {
+    let req_body = req_body.into_inner();
+    if req_body.message_id.trim().is_empty() {
+        return HttpResponse::BadRequest().body("A non-empty \"message_id\" is required.");
+    }
+
+    let session_id = if let Ok(Some(id)) = session.get::<String>("session_id") {
+        id
+    } else {
+        warn!("No valid session_id found in cookie; falling back to request body");
+        req_body.session_id.unwrap_or_default()
+    };
+
+    let Some(user_session) = data.session_manager.get(&session_id) else {
+        error!(session_id = session_id.as_str(); "Session \"{}\" not found!", session_id);
+        return HttpResponse::InternalServerError().body("Session not initialized. Please refresh the page.");
+    };
+    if !data.rate_limiter.check(&session_id) {
+        warn!(session_id = session_id.as_str(); "Rate limit exceeded for session {}", session_id);
+        return HttpResponse::TooManyRequests().body("You're sending messages too quickly. Please wait a moment and try again.");
+    }
+
+    info!(session_id = session_id.as_str(); "Drafting a reply to {} for session {}", req_body.message_id, session_id);
+    let email = match user_session.mailbox.get_email(&req_body.message_id).await {
+        Ok(Some(email)) => email,
+        Ok(None) => return HttpResponse::NotFound().body(format!("Email {} not found", req_body.message_id)),
+        Err(e) => {
+            error!(session_id = session_id.as_str(); "Error fetching email {} for session {}: {:?}", req_body.message_id, session_id, e);
+            return mailbox_error_response(&e, "Sorry, I couldn't load that email.");
+        }
+    };
+
+    draft_response_with(&session_id, email, &req_body.instruction, |email, instruction| async move {
+        chat_service::draft_reply(&email, &instruction).await
+    }).await
+}
+
+// The drafting half of `handle_draft_request`, with the drafting passed in so tests can stub the model
+async fn draft_response_with<F, Fut>(session_id: &str, email: Email, instruction: &str, draft_reply: F) -> HttpResponse
+where
+    F: FnOnce(Email, String) -> Fut,
+    Fut: std::future::Future<Output = Result<DraftEmail, Box<dyn std::error::Error>>>,
+{
+    if config::llm_disabled() {
+        return HttpResponse::ServiceUnavailable().body(chat_service::LLM_DISABLED);
+    }
+    let instruction = match instruction.trim() {
+        "" => DEFAULT_DRAFT_INSTRUCTION,
+        instruction => instruction,
+    };
+    match draft_reply(email, instruction.to_string()).await {
+        Ok(draft) => HttpResponse::Ok().json(draft),
+        Err(e) => {
+            error!(session_id = session_id; "Error drafting a reply for session {}: {:?}", session_id, e);
+            HttpResponse::InternalServerError().body("Sorry, I couldn't draft a reply right now.")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, App};
+    use serde_json::Value;
+    use std::sync::Arc;
+    use tokio::sync::Semaphore;
+    use crate::models::global_session_manager::GlobalSessionManager;
+    use crate::models::rate_limiter::RateLimiter;
+    use crate::models::email::Email;
+    use crate::models::email_db::EmailDB;
+    use crate::models::user_session::UserSession;
+
+    // Stateless handler tests; "session" is the only known session
+    fn test_state(requests_per_minute: u32) -> AppState {
+        let state = AppState {
+            session_manager: GlobalSessionManager::new(),
+            rate_limiter: RateLimiter::new(requests_per_minute),
+            load_slots: Arc::new(Semaphore::new(1)),
+            config: crate::routes::app_state::test_config(),
+            meilisearch: crate::routes::app_state::test_client(),
+        };
+        state.session_manager.insert("session".to_string(), UserSession::new(EmailDB::unconnected("emails")));
+        state
+    }
+
+    async fn post_classify(state: AppState, bodies: &[Value]) -> Vec<actix_web::dev::ServiceResponse> {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .configure(crate::routes::chat_routes::init_routes)
+        ).await;
+        let mut responses = Vec::new();
+        for body in bodies {
+            let request = test::TestRequest::post().uri("/classify").set_json(body).to_request();
+            responses.push(test::call_service(&app, request).await);
+        }
+        responses
+    }
+
+    #[actix_web::test]
+    async fn test_classify_returns_intent_json() {
+        // Verb-first commands are classified by rules, so no LLM is needed here
+        let body = serde_json::json!({"message": "delete the email from Bob", "session_id": "session"});
+        let response = post_classify(test_state(100), &[body]).await.remove(0);
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["intent"], "delete");
+        assert!(body["confidence"].as_f64().is_some());
+        assert!(body["reasoning"].as_str().is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_classify_requires_a_message() {
+        let body = serde_json::json!({"message": "  ", "session_id": "session"});
+        let response = post_classify(test_state(100), &[body]).await.remove(0);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_classify_requires_a_session_and_is_rate_limited() {
+        let unknown = serde_json::json!({"message": "delete the email from Bob", "session_id": "made-up"});
+        let known = serde_json::json!({"message": "delete the email from Bob", "session_id": "session"});
+        let responses = post_classify(test_state(1), &[unknown, known.clone(), known]).await;
+
+        let statuses: Vec<StatusCode> = responses.iter().map(|r| r.status()).collect();
+        assert_eq!(statuses, vec![StatusCode::INTERNAL_SERVER_ERROR, StatusCode::OK, StatusCode::TOO_MANY_REQUESTS]);
+    }
+
+    #[actix_web::test]
+    async fn test_chat_rejects_malformed_bodies() {
+        let state = test_state(100);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .configure(crate::routes::chat_routes::init_routes)
+        ).await;
+
+        for body in [r#"{"session_id": "abc"}"#, r#"{"message": 42}"#, r#"{"message": "#, r#"{"message": "   "}"#] {
+            let request = test::TestRequest::post()
+                .uri("/stream")
+                .insert_header(("content-type", "application/json"))
+                .set_payload(body)
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "body: {}", body);
+
+            let text = test::read_body(response).await;
+            assert!(String::from_utf8_lossy(&text).contains(MESSAGE_REQUIRED), "body: {}", body);
+        }
+
+        // Other routes keep actix's default error for their own bodies
+        let request = test::TestRequest::post()
+            .uri("/draft")
+            .insert_header(("content-type", "application/json"))
+            .set_payload(r#"{"message_id": 42}"#)
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let text = test::read_body(response).await;
+        assert!(!String::from_utf8_lossy(&text).contains(MESSAGE_REQUIRED));
+    }
+
+    #[actix_web::test]
+    async fn test_oversized_messages_are_rejected() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(test_state(100)))
+                .configure(crate::routes::chat_routes::init_routes)
+        ).await;
+
+        let pasted = "x".repeat(config::max_message_chars() + 1);
+        for uri in ["/stream", "/classify"] {
+            let request = test::TestRequest::post()
+                .uri(uri)
+                .set_json(serde_json::json!({"message": pasted, "session_id": "session"}))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE, "uri: {}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_sessions_are_not_rate_limited() {
+        let state = test_state(1);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .configure(crate::routes::chat_routes::init_routes)
+        ).await;
+
+        // Made-up ids are rejected as unknown every time rather than using up a bucket
+        for _ in 0..3 {
+            let request = test::TestRequest::post()
+                .uri("/stream")
+                .set_json(serde_json::json!({"message": "hello", "session_id": "made-up"}))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    fn dinner_email() -> Email {
+        Email {
+            message_id: Some("draft-1".to_string()),
+            from: Some("Bob <bob@example.com>".to_string()),
+            to: Some("user@example.com".to_string()),
+            subject: Some("Dinner on Friday?".to_string()),
+            body: Some("Are you free for dinner on Friday?".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_draft_returns_the_drafted_reply() {
+        let response = draft_response_with("session", dinner_email(), "say yes", |email, instruction| async move {
+            assert_eq!(email.message_id.as_deref(), Some("draft-1"));
+            assert_eq!(instruction, "say yes");
+            Ok(DraftEmail { to: email.from, subject: "Re: Dinner on Friday?".to_string(), body: "Yes!".to_string() })
+        }).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let draft: Value = serde_json::from_slice(&actix_web::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+        assert_eq!(draft["to"], "Bob <bob@example.com>");
+        assert_eq!(draft["body"], "Yes!");
+    }
+
+    #[actix_web::test]
+    async fn test_draft_defaults_the_instruction_and_reports_failures() {
+        let response = draft_response_with("session", dinner_email(), "  ", |_, instruction| async move {
+            assert_eq!(instruction, DEFAULT_DRAFT_INSTRUCTION);
+            Err("model unavailable".into())
+        }).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[actix_web::test]
+    async fn test_draft_requires_a_known_session() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(test_state(100)))
+                .configure(crate::routes::chat_routes::init_routes)
+        ).await;
+        let request = test::TestRequest::post()
+            .uri("/draft")
+            .set_json(serde_json::json!({"message_id": "draft-1", "session_id": "made-up"}))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
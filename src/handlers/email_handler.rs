@@ -0,0 +1,226 @@
+use actix_web::{web, HttpResponse};
+use actix_session::Session;
+use log::{info, error};
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use crate::models::email::{Email, display_sender};
+use crate::models::email_db::EmailDBError;
+use crate::models::reminder::due_reminders;
+use crate::models::user_session::UserSession;
+use crate::routes::app_state::AppState;
+use crate::services::chat_service;
+
+/// Number of emails returned by `GET /emails` when no limit is given.
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+/// Largest page `GET /emails` will return.
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// One page of the session's mailbox, with the total so clients can paginate.
+#[derive(Debug, Serialize)]
+pub struct EmailPage {
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub emails: Vec<Email>,
+}
+
+/// A due reminder with the subject and sender of its email, for `GET /reminders`.
+#[derive(Debug, Serialize)]
+pub struct DueReminder {
+    pub message_id: String,
+    pub remind_at: DateTime<Utc>,
+    pub subject: Option<String>,
+    pub from: Option<String>,
+}
+
+/// The response for a failed mailbox operation: 404 when the email asked for isn't stored,
+/// otherwise a 500 with `message`.
+pub fn mailbox_error_response(e: &EmailDBError, message: &str) -> HttpResponse {
+    match e {
+        EmailDBError::NotFound(what) => HttpResponse::NotFound().body(format!("Not found: {}", what)),
+        _ => HttpResponse::InternalServerError().body(message.to_string()),
+    }
+}
+
+/// Looks up the caller's `UserSession` from the session cookie, or returns the error response to send.
+fn user_session(data: &AppState, session: &Session) -> Result<(String, UserSession), HttpResponse> {
+    let session_id = match session.get::<String>("session_id") {
+        Ok(Some(id)) => id,
+        _ => {
+            error!("No valid session_id found in cookie");
+            return Err(HttpResponse::InternalServerError().body("Session not initialized. Please refresh the page."));
+        }
+    };
+
+    match data.session_manager.get(&session_id) {
+        Some(user_session) => Ok((session_id, user_session)),
+        None => {
+            error!(session_id = session_id.as_str(); "Session \"{}\" not found!", session_id);
+            Err(HttpResponse::InternalServerError().body("Session not initialized. Please refresh the page."))
+        }
+    }
+}
+
+/// Returns the full content of a single stored email as JSON, or 404 if it isn't in the mailbox.
+pub async fn get_email(
+    data: web::Data<AppState>,
+    session: Session,
+    message_id: String,
+) -> HttpResponse {
+    let (session_id, user_session) = match user_session(&data, &session) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    info!(session_id = session_id.as_str(); "Fetching email {} for session {}", message_id, session_id);
+    match user_session.mailbox.get_email(&message_id).await {
+        Ok(Some(email)) => HttpResponse::Ok().json(email),
+        Ok(None) => HttpResponse::NotFound().body(format!("Email {} not found", message_id)),
+        Err(e) => {
+            error!("Error fetching email {} for session {}: {:?}", message_id, session_id, e);
+            mailbox_error_response(&e, "Sorry, I couldn't load that email.")
+        }
+    }
+}
+
+/// Returns the session's reminders that are due, earliest first. Listing them doesn't dismiss
+/// them; they're cleared once shown at the top of a List in chat.
+pub async fn list_due_reminders(
+    data: web::Data<AppState>,
+    session: Session,
+) -> HttpResponse {
+    let (session_id, user_session) = match user_session(&data, &session) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    info!(session_id = session_id.as_str(); "Listing due reminders for session {}", session_id);
+    let mut due = Vec::new();
+    for reminder in due_reminders(&user_session.reminders, Utc::now()) {
+        let email = match user_session.mailbox.get_email(&reminder.message_id).await {
+            Ok(email) => email,
+            Err(e) => {
+                error!("Error loading reminder email {} for session {}: {:?}", reminder.message_id, session_id, e);
+                return mailbox_error_response(&e, "Sorry, I couldn't load your reminders.");
+            }
+        };
+        due.push(DueReminder {
+            subject: email.as_ref().and_then(|email| email.subject.clone()),
+            from: email.as_ref().map(display_sender),
+            message_id: reminder.message_id,
+            remind_at: reminder.remind_at,
+        });
+    }
+    HttpResponse::Ok().json(due)
+}
+
+/// Returns a page of the session's stored emails as JSON, wrapped with the total count.
+pub async fn list_emails(
+    data: web::Data<AppState>,
+    session: Session,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> HttpResponse {
+    let (session_id, user_session) = match user_session(&data, &session) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let offset = offset.unwrap_or(0);
+    info!(session_id = session_id.as_str(); "Listing emails {}..{} for session {}", offset, offset + limit, session_id);
+
+    let page = async {
+        let total = user_session.mailbox.count_emails().await?;
+        let emails = user_session.mailbox.get_emails_page(offset, limit).await?;
+        Ok::<_, EmailDBError>(EmailPage { total, offset, limit, emails })
+    };
+    match page.await {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => {
+            error!("Error listing emails for session {}: {:?}", session_id, e);
+            mailbox_error_response(&e, "Sorry, I couldn't load your emails.")
+        }
+    }
+}
+
+/// Returns every stored email as an mbox file download, for backing up the loaded inbox.
+pub async fn export_emails(
+    data: web::Data<AppState>,
+    session: Session,
+) -> HttpResponse {
+    let (session_id, user_session) = match user_session(&data, &session) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    info!(session_id = session_id.as_str(); "Exporting mailbox for session {}", session_id);
+    match user_session.mailbox.export_mbox().await {
+        Ok(mbox) => HttpResponse::Ok()
+            .content_type("application/mbox")
+            .insert_header(("Content-Disposition", "attachment; filename=\"inbox.mbox\""))
+            .body(mbox),
+        Err(e) => {
+            error!("Error exporting mailbox for session {}: {:?}", session_id, e);
+            mailbox_error_response(&e, "Sorry, I couldn't export your emails.")
+        }
+    }
+}
+
+/// Returns a plain-text digest of the last day's email, for reading or mailing to oneself.
+pub async fn get_digest(
+    data: web::Data<AppState>,
+    session: Session,
+) -> HttpResponse {
+    let (session_id, user_session) = match user_session(&data, &session) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    info!(session_id = session_id.as_str(); "Generating digest for session {}", session_id);
+    match chat_service::generate_digest(&user_session.mailbox).await {
+        Ok(digest) => HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(digest),
+        Err(e) => {
+            error!("Error generating digest for session {}: {:?}", session_id, e);
+            mailbox_error_response(&e, "Sorry, I couldn't build your digest.")
+        }
+    }
+}
+
+/// Applies the current MeiliSearch settings to the session's existing index, so settings
+/// changes take effect without deleting and reloading it.
+pub async fn reconfigure_index(
+    data: web::Data<AppState>,
+    session: Session,
+) -> HttpResponse {
+    let (session_id, user_session) = match user_session(&data, &session) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    info!(session_id = session_id.as_str(); "Reconfiguring email index for session {}", session_id);
+    match user_session.mailbox.reconfigure().await {
+        Ok(()) => HttpResponse::Ok().body("Index settings updated."),
+        Err(e) => {
+            error!("Error reconfiguring index for session {}: {:?}", session_id, e);
+            mailbox_error_response(&e, "Sorry, I couldn't update the index settings.")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn test_mailbox_error_response_maps_not_found_to_404() {
+        let missing = mailbox_error_response(&EmailDBError::NotFound("email msg-1".to_string()), "Sorry");
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        let broken = mailbox_error_response(&EmailDBError::ConnectionError("refused".to_string()), "Sorry");
+        assert_eq!(broken.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
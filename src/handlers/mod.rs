@@ -1,3 +1,4 @@
 pub mod session_handler;
 pub mod oauth_handler;
-pub mod chat_handler;
\ No newline at end of file
+pub mod chat_handler;
+pub mod email_handler;
\ No newline at end of file
@@ -10,40 +10,67 @@ use std::fs;
 use std::path::Path;
 use log::{info, error};
 
+use crate::config;
 use crate::services::gmail_service::{read_access_token, refresh_token};
 
-const GMAIL_SCOPE: &str = "https://www.googleapis.com/auth/gmail.readonly";
+const CLIENT_SECRET_PATH: &str = "./cfg/client_secret.json";
 
 /// Constructs an OAuth2 BasicClient from your client secret file.
-fn build_oauth_client() -> BasicClient {
+fn build_oauth_client() -> Result<BasicClient, String> {
+    build_oauth_client_from(CLIENT_SECRET_PATH)
+}
+
+/// Constructs an OAuth2 BasicClient from the client secret file at `path`, describing what's
+/// wrong with the file instead of panicking when it's missing or malformed.
+fn build_oauth_client_from(path: &str) -> Result<BasicClient, String> {
     // Read client secret from file.
-    let secret_str = fs::read_to_string("./cfg/client_secret.json")
-        .expect("Unable to read client secret file");
+    let secret_str = fs::read_to_string(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => format!("OAuth is not configured: {} missing", path.trim_start_matches("./")),
+        _ => format!("OAuth is not configured: unable to read {}: {}", path, e),
+    })?;
     let json_secret: Value = serde_json::from_str(&secret_str)
-        .expect("Invalid JSON in client secret file");
+        .map_err(|e| format!("OAuth is not configured: invalid JSON in {}: {}", path, e))?;
     let installed = &json_secret["installed"];
-    let client_id = ClientId::new(installed["client_id"].as_str().unwrap().to_string());
-    let client_secret = ClientSecret::new(installed["client_secret"].as_str().unwrap().to_string());
-    let auth_url = AuthUrl::new(installed["auth_uri"].as_str().unwrap().to_string())
-        .expect("Invalid authorization endpoint URL");
-    let token_url = TokenUrl::new(installed["token_uri"].as_str().unwrap().to_string())
-        .expect("Invalid token endpoint URL");
-
-    BasicClient::new(client_id, Some(client_secret), auth_url, Some(token_url))
+    let field = |name: &str| {
+        installed[name]
+            .as_str()
+            .map(|value| value.to_string())
+            .ok_or_else(|| format!("OAuth is not configured: {} has no installed.{}", path, name))
+    };
+
+    let client_id = ClientId::new(field("client_id")?);
+    let client_secret = ClientSecret::new(field("client_secret")?);
+    let auth_url = AuthUrl::new(field("auth_uri")?)
+        .map_err(|e| format!("Invalid authorization endpoint URL in {}: {}", path, e))?;
+    let token_url = TokenUrl::new(field("token_uri")?)
+        .map_err(|e| format!("Invalid token endpoint URL in {}: {}", path, e))?;
+
+    Ok(BasicClient::new(client_id, Some(client_secret), auth_url, Some(token_url))
         .set_redirect_uri(
             RedirectUrl::new("http://localhost:8080/oauth/callback".to_string())
                 .expect("Invalid redirect URL")
-        )
+        ))
 }
 
-/// Initiates the OAuth flow by generating the authorization URL and redirecting.
-pub async fn oauth_login() -> impl Responder {
-    let oauth_client = build_oauth_client();
-    // Generate the authorization URL.
+/// The Google sign-in URL asking for `scopes`.
+fn authorize_url(oauth_client: &BasicClient, scopes: &[String]) -> oauth2::url::Url {
     let (auth_url, _csrf_token) = oauth_client
         .authorize_url(CsrfToken::new_random)
-        .add_scope(Scope::new(GMAIL_SCOPE.to_string()))
+        .add_scopes(scopes.iter().cloned().map(Scope::new))
         .url();
+    auth_url
+}
+
+/// Initiates the OAuth flow by generating the authorization URL and redirecting.
+pub async fn oauth_login() -> impl Responder {
+    let oauth_client = match build_oauth_client() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("{}", e);
+            return HttpResponse::InternalServerError().body(e);
+        }
+    };
+    let auth_url = authorize_url(&oauth_client, &config::gmail_scopes());
 
     // Redirect the browser to Google’s OAuth 2.0 server.
     HttpResponse::Found()
@@ -75,7 +102,13 @@ pub async fn oauth_callback(req: HttpRequest) -> impl Responder {
         None => return HttpResponse::BadRequest().body("Missing code"),
     };
 
-    let oauth_client = build_oauth_client();
+    let oauth_client = match build_oauth_client() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("{}", e);
+            return HttpResponse::InternalServerError().body(e);
+        }
+    };
 
     // Exchange the code with Google for a token.
     let token_result = oauth_client
@@ -87,8 +120,10 @@ pub async fn oauth_callback(req: HttpRequest) -> impl Responder {
         Ok(token) => {
             // Write the token JSON to the cache file.
             let token_json = serde_json::to_string(&token).unwrap();
-            fs::write("tokencache.json", token_json)
-                .expect("Unable to write token to file");
+            if let Err(e) = fs::write("tokencache.json", token_json) {
+                error!("Unable to write token to file: {}", e);
+                return HttpResponse::InternalServerError().body(format!("Unable to save the OAuth token: {}", e));
+            }
             info!("Token successfully obtained and cached.");
             // Redirect back to the main page.
             HttpResponse::Found().append_header(("Location", "/")).finish()
@@ -132,7 +167,10 @@ pub async fn check_auth() -> impl Responder {
             info!("Access token appears expired, attempting refresh...");
 
             // Build an OAuth client using the helper from oauth_handler.
-            let oauth_client = build_oauth_client();
+            let oauth_client = match build_oauth_client() {
+                Ok(client) => client,
+                Err(e) => return HttpResponse::Ok().json(json!({ "authenticated": false, "error": e })),
+            };
 
             // Attempt to refresh the token.
             match refresh_token(&oauth_client).await {
@@ -152,7 +190,15 @@ pub async fn check_auth() -> impl Responder {
                         Err(e) => HttpResponse::Ok().json(json!({ "authenticated": false, "error": e.to_string() }))
                     }
                 },
-                Err(e) => HttpResponse::Ok().json(json!({ "authenticated": false, "error": e.to_string() }))
+                // Revoked access needs a new sign-in; a network blip only needs a retry
+                Err(e) => {
+                    error!("Token refresh failed: {}", e);
+                    HttpResponse::Ok().json(json!({
+                        "authenticated": false,
+                        "reauthenticate": e.needs_reauthentication(),
+                        "error": e.to_string()
+                    }))
+                }
             }
         },
         Ok(res) => {
@@ -164,3 +210,55 @@ pub async fn check_auth() -> impl Responder {
         Err(e) => HttpResponse::Ok().json(json!({ "authenticated": false, "error": e.to_string() })),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_client_secret_is_an_error() {
+        let err = build_oauth_client_from("./cfg/does_not_exist.json").unwrap_err();
+        assert_eq!(err, "OAuth is not configured: cfg/does_not_exist.json missing");
+    }
+
+    #[test]
+    fn test_malformed_client_secret_is_an_error() {
+        let dir = std::env::temp_dir();
+        let invalid_json = dir.join("oauth_handler_invalid_secret.json");
+        fs::write(&invalid_json, "{ not json").unwrap();
+        let err = build_oauth_client_from(invalid_json.to_str().unwrap()).unwrap_err();
+        assert!(err.contains("invalid JSON"), "{}", err);
+
+        let missing_field = dir.join("oauth_handler_incomplete_secret.json");
+        fs::write(&missing_field, r#"{ "installed": { "client_id": "id" } }"#).unwrap();
+        let err = build_oauth_client_from(missing_field.to_str().unwrap()).unwrap_err();
+        assert!(err.contains("installed.client_secret"), "{}", err);
+
+        let _ = fs::remove_file(invalid_json);
+        let _ = fs::remove_file(missing_field);
+    }
+
+    #[test]
+    fn test_authorize_url_requests_configured_scopes() {
+        let secret = std::env::temp_dir().join("oauth_handler_scopes_secret.json");
+        fs::write(&secret, r#"{ "installed": {
+            "client_id": "id", "client_secret": "secret",
+            "auth_uri": "https://accounts.google.com/o/oauth2/auth",
+            "token_uri": "https://oauth2.googleapis.com/token"
+        } }"#).unwrap();
+        let client = build_oauth_client_from(secret.to_str().unwrap()).unwrap();
+        let _ = fs::remove_file(secret);
+
+        let scopes = config::parse_gmail_scopes("gmail.readonly gmail.send");
+        let url = authorize_url(&client, &scopes);
+        let requested: Vec<String> = url.query_pairs()
+            .filter(|(key, _)| key == "scope")
+            .flat_map(|(_, value)| value.split(' ').map(str::to_string).collect::<Vec<_>>())
+            .collect();
+        assert_eq!(requested, scopes);
+
+        let url = authorize_url(&client, &[config::GMAIL_READONLY_SCOPE.to_string()]);
+        assert!(url.as_str().contains("gmail.readonly"), "{}", url);
+        assert!(!url.as_str().contains("gmail.send"), "{}", url);
+    }
+}
@@ -1,52 +1,215 @@
 use actix_session::Session;
-use actix_web::web;
+use actix_web::{web, HttpResponse};
 use uuid::Uuid;
 use log::{info, error};
 use serde_json::json;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
 use crate::routes::app_state::AppState;
-use crate::models::user_session::UserSession;
+use crate::models::email::Email;
+use crate::models::global_session_manager::GlobalSessionManager;
+use crate::models::user_session::{LoadStatus, UserSession};
 use crate::models::email_db::EmailDB;
 use crate::services::email_service;
+use crate::config;
 
+/// Creates a session and returns straight away; its inbox is loaded by a background task
+/// whose progress `/session_status` reports. If the cookie already names a session the server
+/// still holds, and its inbox didn't fail to load, that session is reused as is.
 pub async fn initialize_session(
     data: web::Data<AppState>,
     session: Session,
 ) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    // A page refresh keeps its session rather than downloading the whole inbox again
+    if let Ok(Some(session_id)) = session.get::<String>("session_id") {
+        match data.session_manager.get(&session_id).map(|existing| existing.load_status) {
+            Some(LoadStatus::Failed { .. }) => {
+                info!(session_id = session_id.as_str(); "Session {} failed to load, starting a new one", session_id);
+            }
+            Some(status) => {
+                info!(session_id = session_id.as_str(); "Reusing existing session {}", session_id);
+                let status = if status == LoadStatus::Ready { "ready" } else { "loading" };
+                return Ok(json!({ "initialized": true, "session_id": session_id, "status": status }));
+            }
+            None => {}
+        }
+    }
+
     // Generate a new session ID and store it in the cookie.
     let session_id = Uuid::new_v4().to_string();
     if let Err(e) = session.insert("session_id", session_id.clone()) {
-        error!("Failed to insert session_id into cookie: {:?}", e);
+        error!(session_id = session_id.as_str(); "Failed to insert session_id into cookie: {:?}", e);
     } else {
-        info!("Stored session_id {} in cookie", session_id);
+        info!(session_id = session_id.as_str(); "Stored session_id {} in cookie", session_id);
     }
 
-    // Check if the session already exists (unlikely with a new UUID)
-    if data.session_manager.get(&session_id).is_some() {
-        return Ok(json!({ "initialized": true, "session_id": session_id }));
-    }
+    // Sessions share the app's MeiliSearch client rather than each opening their own
+    let mut new_session = UserSession::new(EmailDB::with_client(data.meilisearch.clone(), &data.config.meilisearch_index).await?);
+    new_session.load_status = LoadStatus::Fetching;
+    let mailbox = new_session.mailbox.clone();
+    data.session_manager.insert(session_id.clone(), new_session);
+    info!(session_id = session_id.as_str(); "Initialized user session: {} (index {})", session_id, mailbox.index_name());
+
+    // The Gmail client's errors aren't Send, so the load runs on this worker's local task set
+    actix_web::rt::spawn(load_session_emails(
+        data.session_manager.clone(),
+        data.load_slots.clone(),
+        session_id.clone(),
+        async {
+            email_service::load_emails().await.map_err(|e| e.to_string())
+        },
+        mailbox,
+        config::email_batch_size(),
+    ));
 
-    let new_session = UserSession {
-        history: Vec::new(),
-        mailbox: EmailDB::default().await?,
+    Ok(json!({ "initialized": true, "session_id": session_id, "status": "loading" }))
+}
+
+/// Waits for a free load slot, then fetches the inbox with `fetch` and stores it in `mailbox`
+/// `batch_size` emails at a time, recording each step in the session's `load_status`.
+pub async fn load_session_emails<F>(
+    manager: GlobalSessionManager,
+    load_slots: Arc<Semaphore>,
+    session_id: String,
+    fetch: F,
+    mailbox: EmailDB,
+    batch_size: usize,
+) where
+    F: Future<Output = Result<Vec<Email>, String>>,
+{
+    let set_status = |status: LoadStatus| {
+        manager.with_session(&session_id, |session| session.load_status = status);
     };
+    let _slot = load_slots.acquire().await;
 
-    info!("Loading emails into vector database for session {}", session_id);
-    // Attempt to load emails
-    let emails = match email_service::load_emails().await {
-        Ok(docs) => docs,
+    info!(session_id = session_id.as_str(); "Loading emails into vector database for session {}", session_id);
+    let emails = match fetch.await {
+        Ok(emails) => emails,
         Err(e) => {
-            error!("Error loading emails for session {}: {:?}", session_id, e);
-            // Return a valid JSON error response
-            return Err(format!("Failed to load emails: {}", e).into());
+            error!(session_id = session_id.as_str(); "Error loading emails for session {}: {}", session_id, e);
+            set_status(LoadStatus::Failed { error: format!("Failed to load emails: {}", e) });
+            return;
         }
     };
 
-    let email_count = emails.len();
-    new_session.mailbox.store_emails(&emails).await?;
-    info!("Successfully loaded {} emails for session {}", email_count, session_id);
+    set_status(LoadStatus::Indexing { email_count: emails.len() });
+    match email_service::store_in_batches(&mailbox, &emails, batch_size).await {
+        Ok(()) => {
+            info!(session_id = session_id.as_str(); "Successfully loaded {} emails for session {}", emails.len(), session_id);
+            set_status(LoadStatus::Ready);
+        }
+        Err(e) => {
+            error!(session_id = session_id.as_str(); "Error storing emails for session {}: {:?}", session_id, e);
+            set_status(LoadStatus::Failed { error: format!("Failed to store emails: {}", e) });
+        }
+    }
+}
 
-    data.session_manager.insert(session_id.clone(), new_session);
-    info!("Initialized user session: {}", session_id);
+/// Reports whether the session's inbox is still loading, ready (with its email count) or failed.
+pub async fn session_status(data: web::Data<AppState>, session: Session) -> HttpResponse {
+    let session_id = match session.get::<String>("session_id") {
+        Ok(Some(id)) => id,
+        _ => return HttpResponse::Unauthorized().json(json!({ "error": "No session. Please refresh the page." })),
+    };
+    let user_session = match data.session_manager.get(&session_id) {
+        Some(user_session) => user_session,
+        None => return HttpResponse::NotFound().json(json!({ "error": "Session not initialized. Please refresh the page." })),
+    };
 
-    Ok(json!({ "initialized": true, "session_id": session_id }))
-}
\ No newline at end of file
+    let mut status = json!(user_session.load_status);
+    if user_session.load_status == LoadStatus::Ready {
+        if let Ok(count) = user_session.mailbox.count_emails().await {
+            status["email_count"] = json!(count);
+        }
+    }
+    HttpResponse::Ok().json(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::rate_limiter::RateLimiter;
+    use crate::services::chat_service::{self, STILL_FETCHING};
+    use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+    use actix_web::{cookie::Key, test as actix_test, App};
+    use tokio::sync::oneshot;
+
+    #[actix_web::test]
+    async fn test_session_is_usable_while_loading() {
+        let manager = GlobalSessionManager::new();
+        let mailbox = EmailDB::unconnected("emails");
+        let mut session = UserSession::new(mailbox.clone());
+        session.load_status = LoadStatus::Fetching;
+        manager.insert("session".to_string(), session);
+
+        // The fetch only finishes when the sender is dropped
+        let (finish_fetch, fetched) = oneshot::channel::<()>();
+        let load = actix_web::rt::spawn(load_session_emails(
+            manager.clone(),
+            Arc::new(Semaphore::new(1)),
+            "session".to_string(),
+            async move {
+                let _ = fetched.await;
+                Err("Gmail unavailable".to_string())
+            },
+            mailbox,
+            50,
+        ));
+        tokio::task::yield_now().await;
+
+        // Before the load completes, the session answers chat with its loading status
+        let mut session = manager.get("session").unwrap();
+        assert_eq!(session.load_status, LoadStatus::Fetching);
+        let response = chat_service::process_chat("list my emails", &mut session).await.unwrap();
+        assert_eq!(response.message, STILL_FETCHING);
+
+        drop(finish_fetch);
+        load.await.unwrap();
+        assert_eq!(
+            manager.get("session").unwrap().load_status,
+            LoadStatus::Failed { error: "Failed to load emails: Gmail unavailable".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_load_status_json() {
+        assert_eq!(json!(LoadStatus::Fetching), json!({ "status": "fetching" }));
+        assert_eq!(json!(LoadStatus::Indexing { email_count: 3 }), json!({ "status": "indexing", "email_count": 3 }));
+    }
+
+    #[actix_web::test]
+    async fn test_init_with_an_existing_session_cookie_is_a_no_op() {
+        let manager = GlobalSessionManager::new();
+        manager.insert("existing".to_string(), UserSession::new(EmailDB::unconnected("emails")));
+        let state = AppState {
+            session_manager: manager.clone(),
+            rate_limiter: RateLimiter::new(100),
+            load_slots: Arc::new(Semaphore::new(1)),
+            config: crate::routes::app_state::test_config(),
+            meilisearch: crate::routes::app_state::test_client(),
+        };
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .wrap(SessionMiddleware::new(CookieSessionStore::default(), Key::generate()))
+                // Stands in for the first init, which set the cookie
+                .route("/login", web::get().to(|session: Session| async move {
+                    session.insert("session_id", "existing").unwrap();
+                    HttpResponse::Ok().finish()
+                }))
+                .configure(crate::routes::session_routes::init_routes)
+        ).await;
+
+        let login = actix_test::call_service(&app, actix_test::TestRequest::get().uri("/login").to_request()).await;
+        let cookie = login.response().cookies().next().unwrap().into_owned();
+
+        let request = actix_test::TestRequest::get().uri("/init_session").cookie(cookie).to_request();
+        let body: serde_json::Value = actix_test::call_and_read_body_json(&app, request).await;
+        assert_eq!(body, json!({ "initialized": true, "session_id": "existing", "status": "ready" }));
+
+        // No new session was created and the existing one wasn't sent back to loading
+        assert_eq!(manager.session_count(), 1);
+        assert_eq!(manager.get("existing").unwrap().load_status, LoadStatus::Ready);
+    }
+}
@@ -8,11 +8,16 @@ mod utils;
 use actix_files::Files;
 use actix_web::{web, App, HttpServer};
 use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use models::session_store::{AppSessionStore, MemorySessionStore, SessionStoreKind};
 use actix_web::cookie::{Key, SameSite};
-use log::info;
+use log::{info, warn, error};
 use routes::app_state::AppState;
+use models::rate_limiter::RateLimiter;
+use models::email_db::EmailDB;
 use config::init_logging;
-use services::{email_service};
+use services::{email_service, llm_service};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
 
 
 #[actix_web::main]
@@ -20,17 +25,82 @@ async fn main() -> std::io::Result<()> {
     init_logging();
     info!("Starting server on http://127.0.0.1:8080");
 
-    let secret_key = Key::from("0123456789012345678901234567890123456789012345678901234567890123".as_bytes());
+    // Missing MeiliSearch or Ollama settings stop startup instead of failing the first request
+    let app_config = match config::load() {
+        Ok(app_config) => app_config,
+        Err(e) => {
+            error!("Invalid configuration: {}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+
+    let secret_key = match config::session_secret() {
+        Ok(Some(secret)) => Key::from(&secret),
+        Ok(None) if config::is_dev_mode() => {
+            warn!("SESSION_SECRET not set; using an ephemeral key (sessions will not survive a restart)");
+            Key::generate()
+        }
+        Ok(None) => {
+            error!("SESSION_SECRET must be set outside development mode (APP_ENV=development)");
+            return Err(std::io::Error::other("SESSION_SECRET is not set"));
+        }
+        Err(e) => {
+            error!("Invalid SESSION_SECRET: {}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+
+    let session_store_kind = match config::session_store_kind() {
+        Ok(kind) => kind,
+        Err(e) => {
+            error!("Invalid SESSION_STORE: {}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+    info!("Using the {:?} session store", session_store_kind);
+    // Created once so every worker shares the same server-side sessions
+    let memory_store = MemorySessionStore::new();
+
+    // A missing model otherwise only shows up as a cryptic error on the first chat
+    match llm_service::check_models_available().await {
+        Err(_) if config::llm_disabled() => info!("DISABLE_LLM is on; running as a search-only mail client"),
+        Ok(()) => info!("Ollama models {} (classifier) and {} (generation) are available",
+            config::classifier_model(), config::generation_model()),
+        Err(e) if config::ollama_strict_startup() => {
+            error!("{}", e);
+            return Err(std::io::Error::other(e));
+        }
+        Err(e) => warn!("{}", e),
+    }
 
     let session_manager = email_service::create_session_manager();
 
-    let app_state = AppState {  session_manager };
+    // One client (and connection pool) for every session's index
+    let meilisearch = match EmailDB::client_from_config(&app_config) {
+        Ok(client) => Arc::new(client),
+        Err(e) => {
+            error!("Invalid MeiliSearch settings: {}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+
+    let rate_limiter = RateLimiter::new(config::chat_rate_limit_per_minute());
+
+    let load_slots = Arc::new(Semaphore::new(config::session_load_concurrency()));
+
+    let app_state = AppState {  session_manager, rate_limiter, load_slots, config: app_config, meilisearch };
+    let shutdown_sessions = app_state.session_manager.clone();
+    let shutdown_store = memory_store.clone();
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
+        let session_store = match session_store_kind {
+            SessionStoreKind::Cookie => AppSessionStore::Cookie(CookieSessionStore::default()),
+            SessionStoreKind::Memory => AppSessionStore::Memory(memory_store.clone()),
+        };
         App::new()
             .app_data(web::Data::new(app_state.clone()))
             .wrap(
-                SessionMiddleware::builder(CookieSessionStore::default(), secret_key.clone())
+                SessionMiddleware::builder(session_store, secret_key.clone())
                     .cookie_secure(false)
                     .cookie_same_site(SameSite::Lax)
                     .build()
@@ -39,10 +109,59 @@ async fn main() -> std::io::Result<()> {
             .configure(routes::session_routes::init_routes)
             .configure(routes::chat_routes::init_routes)
             .configure(routes::oauth_routes::init_routes)
+            .configure(routes::email_routes::init_routes)
             // Finally, static files:
             .service(Files::new("/", "./static").index_file("index.html"))
     })
         .bind(("127.0.0.1", 8080))?
-        .run()
-        .await
+        // Signals are handled by `shutdown_signal` below so shutdown can be logged
+        .disable_signals()
+        .shutdown_timeout(config::shutdown_timeout_secs())
+        .run();
+
+    let server_handle = server.handle();
+    actix_web::rt::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutdown signal received; no longer accepting connections, waiting for in-flight requests");
+        server_handle.stop(true).await;
+    });
+
+    server.await?;
+    info!("Server stopped; discarding {} in-memory session(s)", shutdown_sessions.session_count());
+    if session_store_kind == SessionStoreKind::Memory {
+        info!("Discarding {} server-side session store entries", shutdown_store.session_count());
+    }
+    info!("Clean shutdown complete");
+    Ok(())
+}
+
+/// Resolves when the process receives Ctrl-C or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
 }
\ No newline at end of file
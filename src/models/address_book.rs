@@ -0,0 +1,95 @@
+use crate::models::email::{Email, extract_address, sender_name, split_address_list};
+
+/// A contact seen in the mailbox: the display name used with an address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contact {
+    pub name: String,
+    pub address: String,
+}
+
+/// Name to address lookups derived from the From/To/Cc headers of stored emails.
+#[derive(Debug, Clone, Default)]
+pub struct AddressBook {
+    contacts: Vec<Contact>,
+}
+
+impl AddressBook {
+    /// Builds the address book from every address header in `emails`.
+    pub fn from_emails(emails: &[Email]) -> Self {
+        let mut book = AddressBook::default();
+        for email in emails {
+            let headers = [&email.from, &email.to, &email.cc];
+            for header in headers.into_iter().flatten() {
+                for entry in split_address_list(header) {
+                    book.add(&entry);
+                }
+            }
+        }
+        book
+    }
+
+    fn add(&mut self, entry: &str) {
+        let address = extract_address(entry);
+        if !address.contains('@') {
+            return;
+        }
+        let contact = Contact { name: sender_name(entry), address };
+        if !self.contacts.contains(&contact) {
+            self.contacts.push(contact);
+        }
+    }
+
+    /// Finds every address used by someone called `name`, matching the full display name or
+    /// any single part of it ("Bob" finds "Bob Jones"), or the address's local part.
+    pub fn lookup(&self, name: &str) -> Vec<&Contact> {
+        let wanted = name.trim().to_lowercase();
+        let mut found: Vec<&Contact> = Vec::new();
+
+        for contact in &self.contacts {
+            let display = contact.name.to_lowercase();
+            let local_part = contact.address.split('@').next().unwrap_or("");
+            let matches = display == wanted
+                || display.split_whitespace().any(|part| part == wanted)
+                || local_part == wanted;
+            if matches && !found.iter().any(|c| c.address == contact.address) {
+                found.push(contact);
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(from: &str, to: &str, cc: Option<&str>) -> Email {
+        Email {
+            from: Some(from.to_string()),
+            to: Some(to.to_string()),
+            cc: cc.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lookup_resolves_names_to_addresses() {
+        let emails = vec![
+            email("Bob Jones <bob@example.com>", "me@example.com", None),
+            email("Alice <alice@example.com>", "\"Jones, Bob\" <bob@example.com>, carol@example.com", None),
+            email("me@example.com", "Alice <alice@example.com>", Some("Bob Smith <bsmith@work.example>")),
+        ];
+        let book = AddressBook::from_emails(&emails);
+
+        let alice: Vec<_> = book.lookup("alice").iter().map(|c| c.address.as_str()).collect();
+        assert_eq!(alice, vec!["alice@example.com"]);
+
+        // Two different Bobs are both listed, with bob@example.com only once
+        let bob: Vec<_> = book.lookup("Bob").iter().map(|c| c.address.as_str()).collect();
+        assert_eq!(bob, vec!["bob@example.com", "bsmith@work.example"]);
+
+        // Bare addresses can be found by their local part
+        assert_eq!(book.lookup("carol")[0].address, "carol@example.com");
+        assert!(book.lookup("dave").is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+use regex::Regex;
+
+/// Splits text into paragraphs at blank lines, dropping empty ones.
+pub fn split_paragraphs(text: &str) -> Vec<String> {
+    Regex::new(r"\n\s*\n").unwrap()
+        .split(text.trim())
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Numbers paragraphs as `[1] ...` so the model can refer to each one in its comments.
+pub fn number_paragraphs(paragraphs: &[String]) -> String {
+    paragraphs.iter()
+        .enumerate()
+        .map(|(i, paragraph)| format!("[{}] {}", i + 1, paragraph))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Reads the model's `[n] comment` lines back into one optional comment per paragraph.
+/// Lines without a number continue the previous comment; numbers out of range are ignored.
+pub fn parse_annotations(output: &str, paragraph_count: usize) -> Vec<Option<String>> {
+    let numbered = Regex::new(r"^\s*(?:\*\*)?\[?(\d+)[\]).:]*(?:\*\*)?\s*(.*)$").unwrap();
+    let mut annotations: Vec<Option<String>> = vec![None; paragraph_count];
+    let mut current: Option<usize> = None;
+
+    for line in output.lines() {
+        if let Some(caps) = numbered.captures(line) {
+            current = caps[1].parse::<usize>().ok()
+                .filter(|n| (1..=paragraph_count).contains(n))
+                .map(|n| n - 1);
+            if let Some(index) = current {
+                let text = caps[2].trim();
+                annotations[index] = Some(text.to_string()).filter(|text| !text.is_empty());
+            }
+        } else if let (Some(index), text) = (current, line.trim()) {
+            if !text.is_empty() {
+                let annotation = annotations[index].get_or_insert_with(String::new);
+                if !annotation.is_empty() {
+                    annotation.push(' ');
+                }
+                annotation.push_str(text);
+            }
+        }
+    }
+    annotations
+}
+
+/// Reassembles the original paragraphs, each followed by its comment if it has one.
+pub fn interleave_annotations(paragraphs: &[String], annotations: &[Option<String>]) -> String {
+    paragraphs.iter()
+        .enumerate()
+        .map(|(i, paragraph)| match annotations.get(i).and_then(Option::as_deref) {
+            Some(annotation) => format!("{}\n> Comment: {}", paragraph, annotation),
+            None => paragraph.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTRACT: &str = "Dear Ann,\n\n1. The term is 24 months.\n\n  \n2. Either party may terminate\nwith 30 days' notice.\n\nRegards,\nBob";
+
+    #[test]
+    fn test_split_and_number_paragraphs() {
+        let paragraphs = split_paragraphs(CONTRACT);
+        assert_eq!(paragraphs.len(), 4);
+        assert_eq!(paragraphs[2], "2. Either party may terminate\nwith 30 days' notice.");
+        assert!(number_paragraphs(&paragraphs).starts_with("[1] Dear Ann,\n\n[2] 1. The term"));
+    }
+
+    #[test]
+    fn test_parse_annotations() {
+        let output = "[1] Standard greeting.\n**[2]** Long commitment;\nconsider a shorter term.\n3. Short notice period.\n[9] Out of range.";
+        let annotations = parse_annotations(output, 4);
+        assert_eq!(annotations, vec![
+            Some("Standard greeting.".to_string()),
+            Some("Long commitment; consider a shorter term.".to_string()),
+            Some("Short notice period.".to_string()),
+            None,
+        ]);
+    }
+
+    #[test]
+    fn test_interleave_keeps_original_text() {
+        let paragraphs = split_paragraphs(CONTRACT);
+        let annotations = vec![None, Some("Long commitment.".to_string()), None, None];
+        let annotated = interleave_annotations(&paragraphs, &annotations);
+        assert_eq!(
+            annotated,
+            "Dear Ann,\n\n1. The term is 24 months.\n> Comment: Long commitment.\n\n2. Either party may terminate\nwith 30 days' notice.\n\nRegards,\nBob"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use crate::models::email::{Email, extract_address};
+
+// Local parts used by senders that don't read replies
+const AUTOMATED_LOCAL_PARTS: &[&str] = &[
+    "noreply", "no-reply", "no_reply", "donotreply", "do-not-reply", "do_not_reply",
+    "mailer-daemon", "postmaster", "notifications", "notification", "newsletter", "bounce",
+];
+
+/// Whether an email looks machine-sent (no-reply address, mailing list or bulk mail),
+/// judged from the sender address and, when captured, the List-Unsubscribe and Precedence headers.
+pub fn is_automated(email: &Email) -> bool {
+    if email.list_unsubscribe.as_deref().is_some_and(|value| !value.trim().is_empty()) {
+        return true;
+    }
+    if email.precedence.as_deref()
+        .map(|value| value.trim().to_lowercase())
+        .is_some_and(|value| matches!(value.as_str(), "bulk" | "list" | "junk"))
+    {
+        return true;
+    }
+    email.from.as_deref().is_some_and(is_automated_address)
+}
+
+/// Whether a From header's address belongs to a no-reply or notification sender.
+pub fn is_automated_address(from: &str) -> bool {
+    let address = extract_address(from);
+    let local_part = address.split('@').next().unwrap_or("");
+    AUTOMATED_LOCAL_PARTS.iter().any(|automated| {
+        local_part == *automated
+            || local_part.starts_with(&format!("{}+", automated))
+            || local_part.starts_with(&format!("{}.", automated))
+            || local_part.ends_with(&format!("-{}", automated))
+            || local_part.ends_with(&format!(".{}", automated))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from(address: &str) -> Email {
+        Email {
+            from: Some(address.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_automated_sender_addresses() {
+        let automated = [
+            "noreply@github.com",
+            "GitHub <no-reply@github.com>",
+            "DoNotReply@bank.example",
+            "Shop <do-not-reply@shop.example>",
+            "billing-noreply@service.example",
+            "noreply+abc123@accounts.example",
+            "Weekly Digest <newsletter@news.example>",
+            "MAILER-DAEMON@mail.example",
+        ];
+        for address in automated {
+            assert!(is_automated(&from(address)), "{} should be automated", address);
+        }
+
+        let personal = [
+            "Alice <alice@example.com>",
+            "bob@example.com",
+            "Noreen Reply <noreen@example.com>",
+            "replies@team.example",
+        ];
+        for address in personal {
+            assert!(!is_automated(&from(address)), "{} should not be automated", address);
+        }
+    }
+
+    #[test]
+    fn test_automated_by_headers() {
+        let list_mail = Email {
+            list_unsubscribe: Some("<mailto:unsubscribe@lists.example>".to_string()),
+            ..from("Alice <alice@example.com>")
+        };
+        assert!(is_automated(&list_mail));
+
+        let bulk = Email { precedence: Some("Bulk".to_string()), ..from("news@example.com") };
+        assert!(is_automated(&bulk));
+
+        assert!(!is_automated(&Email::default()));
+    }
+}
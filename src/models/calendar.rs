@@ -0,0 +1,138 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+/// The event from a meeting invitation's calendar (ICS) part.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct CalendarEvent {
+    pub summary: Option<String>,
+    /// ISO 8601 start: "2025-05-12T15:00:00Z" for UTC times, "2025-05-12T15:00:00" for local
+    /// times (see `time_zone`) and "2025-05-12" for all-day events
+    pub start: Option<String>,
+    /// The TZID a local start time is given in, e.g. "Europe/Zurich"
+    pub time_zone: Option<String>,
+    pub location: Option<String>,
+}
+
+/// Parses the first VEVENT in an iCalendar document, returning None if there is no event with
+/// a start time or summary.
+pub fn parse_ics(ics: &str) -> Option<CalendarEvent> {
+    let mut event = CalendarEvent::default();
+    let mut in_event = false;
+
+    for line in unfold_lines(ics) {
+        let Some((name_and_params, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut params = name_and_params.split(';');
+        let name = params.next().unwrap_or("").to_uppercase();
+
+        match (name.as_str(), value.trim()) {
+            ("BEGIN", "VEVENT") => in_event = true,
+            ("END", "VEVENT") if in_event => break,
+            _ if !in_event => {}
+            ("SUMMARY", value) => event.summary = Some(unescape_text(value)),
+            ("LOCATION", value) if !value.is_empty() => event.location = Some(unescape_text(value)),
+            ("DTSTART", value) => {
+                event.start = parse_ics_date(value);
+                event.time_zone = params
+                    .find_map(|param| param.strip_prefix("TZID=").or_else(|| param.strip_prefix("tzid=")))
+                    .map(|tzid| tzid.trim_matches('"').to_string());
+            }
+            _ => {}
+        }
+    }
+
+    (event.start.is_some() || event.summary.is_some()).then_some(event)
+}
+
+// Joins folded lines: a line starting with a space or tab continues the previous one
+fn unfold_lines(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(previous)) => previous.push_str(continuation),
+            _ => lines.push(line.trim_end_matches('\r').to_string()),
+        }
+    }
+    lines
+}
+
+// DATE-TIME values are "20250512T150000" with an optional "Z"; DATE values are "20250512"
+fn parse_ics_date(value: &str) -> Option<String> {
+    let (local, utc) = match value.strip_suffix('Z') {
+        Some(local) => (local, true),
+        None => (value, false),
+    };
+    if let Ok(time) = NaiveDateTime::parse_from_str(local, "%Y%m%dT%H%M%S") {
+        let start = time.format("%Y-%m-%dT%H:%M:%S").to_string();
+        return Some(if utc { format!("{}Z", start) } else { start });
+    }
+    NaiveDate::parse_from_str(value, "%Y%m%d")
+        .ok()
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
+
+fn unescape_text(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => text.push('\n'),
+            Some(escaped) => text.push(escaped),
+            None => text.push('\\'),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVITE: &str = "BEGIN:VCALENDAR\r\n\
+        VERSION:2.0\r\n\
+        METHOD:REQUEST\r\n\
+        BEGIN:VTIMEZONE\r\n\
+        TZID:Europe/Zurich\r\n\
+        END:VTIMEZONE\r\n\
+        BEGIN:VEVENT\r\n\
+        DTSTART;TZID=Europe/Zurich:20250512T150000\r\n\
+        DTEND;TZID=Europe/Zurich:20250512T160000\r\n\
+        SUMMARY:Design review\\, phase 2\r\n\
+        LOCATION:Room 4\\; second floor\r\n\
+        DESCRIPTION:Agenda to follow. This line is long enough that it has been\r\n \
+        folded onto a second line.\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    #[test]
+    fn test_parse_ics_extracts_event_details() {
+        assert_eq!(parse_ics(INVITE), Some(CalendarEvent {
+            summary: Some("Design review, phase 2".to_string()),
+            start: Some("2025-05-12T15:00:00".to_string()),
+            time_zone: Some("Europe/Zurich".to_string()),
+            location: Some("Room 4; second floor".to_string()),
+        }));
+    }
+
+    #[test]
+    fn test_parse_ics_utc_and_all_day_starts() {
+        let utc = parse_ics("BEGIN:VEVENT\nDTSTART:20250512T130000Z\nSUMMARY:Standup\nEND:VEVENT").unwrap();
+        assert_eq!(utc.start.as_deref(), Some("2025-05-12T13:00:00Z"));
+        assert_eq!(utc.time_zone, None);
+        assert_eq!(utc.location, None);
+
+        let all_day = parse_ics("BEGIN:VEVENT\nDTSTART;VALUE=DATE:20250601\nSUMMARY:Offsite\nEND:VEVENT").unwrap();
+        assert_eq!(all_day.start.as_deref(), Some("2025-06-01"));
+    }
+
+    #[test]
+    fn test_parse_ics_without_event() {
+        assert_eq!(parse_ics("BEGIN:VCALENDAR\nVERSION:2.0\nEND:VCALENDAR"), None);
+        assert_eq!(parse_ics("not a calendar"), None);
+    }
+}
@@ -1,13 +1,53 @@
 use std::fmt;
+use regex::Regex;
+use chrono::{DateTime, Utc};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+use crate::models::calendar::CalendarEvent;
+use crate::models::html_sanitizer::sanitize_html;
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct Email {
     pub from: Option<String>,
     pub to: Option<String>,
+    pub cc: Option<String>,
     pub date: Option<String>,
     pub subject: Option<String>,
     pub body: Option<String>,
     pub message_id: Option<String>,
+    /// ISO 639-1 code of the body's language (e.g. "en"), detected at ingest
+    #[serde(default)]
+    pub language: Option<String>,
+    /// How much this email likely matters to the user (0.0 to 1.0), scored at ingest
+    #[serde(default)]
+    pub importance: Option<f32>,
+    /// The List-Unsubscribe header, present on newsletters and mailing lists
+    #[serde(default)]
+    pub list_unsubscribe: Option<String>,
+    /// The Precedence header ("bulk", "list") some bulk senders set
+    #[serde(default)]
+    pub precedence: Option<String>,
+    /// How many files are attached
+    #[serde(default)]
+    pub attachment_count: usize,
+    /// Lowercase file extensions of the attachments, e.g. "pdf"
+    #[serde(default)]
+    pub attachment_types: Vec<String>,
+    /// The Reply-To header: where replies go, ahead of From. `sender_header` only falls back to
+    /// it to identify the sender when From is missing
+    #[serde(default)]
+    pub reply_to: Option<String>,
+    /// The Sender header, set when someone sends on behalf of the From address
+    #[serde(default)]
+    pub sender: Option<String>,
+    /// The meeting this email invites to, parsed from its calendar part
+    #[serde(default)]
+    pub event: Option<CalendarEvent>,
+    /// MIME type of the body ("text/plain" or "text/html"), captured at ingest
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// Gmail's id for the conversation this email belongs to
+    #[serde(default)]
+    pub thread_id: Option<String>,
 }
 
 impl fmt::Display for Email {
@@ -16,9 +56,15 @@ impl fmt::Display for Email {
         if let Some(ref from) = self.from {
             writeln!(f, "  From: {}", from)?;
         }
+        if let Some(ref reply_to) = self.reply_to {
+            writeln!(f, "  Reply-To: {}", reply_to)?;
+        }
         if let Some(ref to) = self.to {
             writeln!(f, "  To: {}", to)?;
         }
+        if let Some(ref cc) = self.cc {
+            writeln!(f, "  Cc: {}", cc)?;
+        }
         if let Some(ref date) = self.date {
             writeln!(f, "  Date: {}", date)?;
         }
@@ -35,54 +81,652 @@ impl fmt::Display for Email {
     }
 }
 
+/// Formats emails as LLM context, truncating each body to `config::max_body_chars()`.
 pub fn format_emails(emails: &[Email]) -> String {
+    format_emails_with_limit(emails, crate::config::max_body_chars())
+}
+
+/// Formats emails as LLM context, truncating bodies longer than `max_body_chars` (0 means no limit).
+pub fn format_emails_with_limit(emails: &[Email], max_body_chars: usize) -> String {
     emails.iter()
-        .map(|email| email.to_string())
+        .map(|email| match &email.body {
+            Some(body) if max_body_chars > 0 => Email {
+                body: Some(truncate_body(body, max_body_chars)),
+                ..email.clone()
+            }.to_string(),
+            _ => email.to_string(),
+        })
         .collect::<Vec<_>>()
         .join("\n\n")
 }
 
-/// Formats an email for plain text display, removing any binary content or markup
-pub fn format_email_plain_text(email: &Email) -> String {
+/// Shortens `body` to at most `max_chars` characters, preferring to cut at the end of a
+/// sentence and otherwise at a word boundary, and notes how much was left out.
+pub fn truncate_body(body: &str, max_chars: usize) -> String {
+    let total_chars = body.chars().count();
+    if total_chars <= max_chars {
+        return body.to_string();
+    }
+
+    let cut = body.char_indices().nth(max_chars).map(|(i, _)| i).unwrap_or(body.len());
+    let head = &body[..cut];
+
+    // Only fall back to a shorter sentence/word boundary if it keeps at least half the text
+    let min_keep = head.len() / 2;
+    let sentence_end = head
+        .rmatch_indices(['.', '!', '?', '\n'])
+        .map(|(i, _)| i + 1)
+        .find(|&end| end >= min_keep);
+    let word_end = head.rfind(char::is_whitespace).filter(|&end| end >= min_keep);
+    let kept = head[..sentence_end.or(word_end).unwrap_or(cut)].trim_end();
+
+    format!(
+        "{}… [truncated: showing {} of {} characters]",
+        kept,
+        kept.chars().count(),
+        total_chars
+    )
+}
+
+/// Splits an address header such as `To` or `Cc` into its individual entries.
+///
+/// Commas inside quoted display names (`"Smith, Bob" <bob@example.com>`) or angle
+/// brackets are not treated as separators.
+pub fn split_address_list(header: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut in_angle = false;
+
+    for c in header.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '<' if !in_quotes => in_angle = true,
+            '>' if !in_quotes => in_angle = false,
+            ',' if !in_quotes && !in_angle => {
+                let entry = current.trim();
+                if !entry.is_empty() {
+                    entries.push(entry.to_string());
+                }
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+
+    let entry = current.trim();
+    if !entry.is_empty() {
+        entries.push(entry.to_string());
+    }
+    entries
+}
+
+/// Extracts the bare, lowercased email address from an entry like `Bob <bob@example.com>`.
+pub fn extract_address(entry: &str) -> String {
+    let address = match (entry.rfind('<'), entry.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &entry[start + 1..end],
+        _ => entry,
+    };
+    address.trim().trim_matches('"').to_lowercase()
+}
+
+/// The name to show for an address entry: its display name, or the bare address if it has none.
+pub fn sender_name(entry: &str) -> String {
+    let name = entry.split('<').next().unwrap_or("").trim().trim_matches('"').trim();
+    if name.is_empty() || !entry.contains('<') {
+        extract_address(entry)
+    } else {
+        name.to_string()
+    }
+}
+
+/// The header that best identifies who sent `email`: From, else Reply-To, else Sender.
+pub fn sender_header(email: &Email) -> Option<&str> {
+    [&email.from, &email.reply_to, &email.sender]
+        .into_iter()
+        .filter_map(|header| header.as_deref())
+        .map(str::trim)
+        .find(|header| !header.is_empty() && *header != "<>")
+}
+
+/// Who sent `email`, for List and summary output: the sender header as written (an empty
+/// `<>` address is dropped, so a bare display name shows cleanly), or "Unknown sender".
+pub fn display_sender(email: &Email) -> String {
+    match sender_header(email) {
+        Some(header) if header.contains('<') => header.trim_end_matches("<>").trim().to_string(),
+        Some(header) => header.trim_matches('"').to_string(),
+        None => "Unknown sender".to_string(),
+    }
+}
+
+/// A subject without its leading reply and forward prefixes, so "Re: Fwd: Re: Topic" compares
+/// equal to "Topic". Prefixes are matched case-insensitively ("RE:", "Fw:", "FWD:").
+pub fn normalize_subject(subject: &str) -> &str {
+    let mut rest = subject.trim();
+    loop {
+        let stripped = ["re:", "fwd:", "fw:"].iter().find_map(|prefix| {
+            rest.get(..prefix.len())
+                .filter(|head| head.eq_ignore_ascii_case(prefix))
+                .map(|_| rest[prefix.len()..].trim_start())
+        });
+        match stripped {
+            Some(shorter) => rest = shorter,
+            None => return rest,
+        }
+    }
+}
+
+/// Whether two emails belong to the same conversation: the same `thread_id` when both have one,
+/// otherwise the same subject once reply and forward prefixes are stripped.
+pub fn same_thread(a: &Email, b: &Email) -> bool {
+    if let (Some(a), Some(b)) = (&a.thread_id, &b.thread_id) {
+        return a == b;
+    }
+    match (a.subject.as_deref().map(normalize_subject), b.subject.as_deref().map(normalize_subject)) {
+        (Some(a), Some(b)) => !a.is_empty() && a.eq_ignore_ascii_case(b),
+        _ => false,
+    }
+}
+
+/// The emails in `anchor`'s conversation, oldest first; undated emails go last.
+pub fn thread_messages(emails: &[Email], anchor: &Email) -> Vec<Email> {
+    let mut thread: Vec<Email> = emails.iter()
+        .filter(|email| same_thread(email, anchor))
+        .cloned()
+        .collect();
+    thread.sort_by_key(|email| {
+        let date = email.date.as_deref().and_then(parse_email_date);
+        (date.is_none(), date)
+    });
+    thread
+}
+
+/// Lowercases `text` and strips its accents, so names compare equal however they were typed:
+/// "José Müller" folds to "jose muller".
+pub fn fold_name(text: &str) -> String {
+    text.nfd().filter(|c| !is_combining_mark(*c)).collect::<String>().to_lowercase()
+}
+
+/// A stable id for an email that has no `message_id`: a hash of its From, Date and Subject,
+/// so storing the same email twice updates it instead of adding a duplicate.
+pub fn synthetic_message_id(email: &Email) -> String {
+    // FNV-1a, spelled out so the id doesn't change between Rust versions
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for field in [&email.from, &email.date, &email.subject] {
+        for byte in field.as_deref().unwrap_or("").bytes().chain([0u8]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("synthetic-{:016x}", hash)
+}
+
+/// Returns `email` with a `message_id`, generating a synthetic one if it has none.
+pub fn with_message_id(email: &Email) -> Email {
+    match email.message_id.as_deref() {
+        Some(id) if !id.trim().is_empty() => email.clone(),
+        _ => Email { message_id: Some(synthetic_message_id(email)), ..email.clone() },
+    }
+}
+
+/// Parses an email's `Date`, accepting both RFC 2822 headers from Gmail and RFC 3339 timestamps.
+pub fn parse_email_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(date.trim())
+        .or_else(|_| DateTime::parse_from_rfc3339(date.trim()))
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// The recipients a reply should be addressed to.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ReplyRecipients {
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+}
+
+/// An email drafted by the model for Reply or Compose, ready to prefill a send form.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct DraftEmail {
+    pub to: Option<String>,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Works out who a reply to `email` should go to.
+///
+/// A plain reply targets only the sender, or the Reply-To address when the email
+/// has one (mailing lists and automated senders often do). A reply-all also
+/// addresses everyone in the original To and Cc, minus the user themselves
+/// (`user_address`) and any duplicates.
+pub fn reply_recipients(email: &Email, reply_all: bool, user_address: Option<&str>) -> ReplyRecipients {
+    let user_address = user_address.map(extract_address);
+    let mut seen: Vec<String> = Vec::new();
+    let mut accept = |entry: &String| {
+        let address = extract_address(entry);
+        if address.is_empty() || user_address.as_deref() == Some(address.as_str()) || seen.contains(&address) {
+            return false;
+        }
+        seen.push(address);
+        true
+    };
+
+    let mut recipients = ReplyRecipients::default();
+    let reply_to = email.reply_to.as_deref().filter(|reply_to| !reply_to.trim().is_empty());
+    let sender = reply_to.or(email.from.as_deref()).map(split_address_list).unwrap_or_default();
+    recipients.to.extend(sender.into_iter().filter(&mut accept));
+
+    if reply_all {
+        let to = email.to.as_deref().map(split_address_list).unwrap_or_default();
+        recipients.to.extend(to.into_iter().filter(&mut accept));
+        let cc = email.cc.as_deref().map(split_address_list).unwrap_or_default();
+        recipients.cc.extend(cc.into_iter().filter(&mut accept));
+    }
+
+    recipients
+}
+
+/// Formats the From/To/Cc/Date/Subject header lines of an email, one per line.
+pub fn format_email_headers(email: &Email) -> String {
     let mut result = String::new();
 
-    // Add email headers
     if let Some(from) = &email.from {
         result.push_str(&format!("From: {}\n", from));
     }
     if let Some(to) = &email.to {
         result.push_str(&format!("To: {}\n", to));
     }
+    if let Some(cc) = &email.cc {
+        result.push_str(&format!("Cc: {}\n", cc));
+    }
     if let Some(date) = &email.date {
         result.push_str(&format!("Date: {}\n", date));
     }
     if let Some(subject) = &email.subject {
         result.push_str(&format!("Subject: {}\n", subject));
     }
+
+    result
+}
+
+/// Formats an email for plain text display, removing any binary content or markup
+pub fn format_email_plain_text(email: &Email) -> String {
+    // Add email headers
+    let mut result = format_email_headers(email);
     result.push_str("\n");
 
     // Add email body with HTML to plain text conversion
-    if let Some(body) = &email.body {
-        // Check if the body contains HTML tags
-        if body.contains("<") && body.contains(">") {
-            // Convert HTML to plain text using the html2text library
-            let cleaned_body = html2text::from_read(body.as_bytes(), body.len());
-            result.push_str(&cleaned_body);
-        } else {
-            // Plain text body
-            result.push_str(body);
-        }
-    } else {
-        result.push_str("No body content");
+    match body_text(email).filter(|body| !body.trim().is_empty()) {
+        Some(body) => result.push_str(&body),
+        None => result.push_str("No body content"),
     }
 
     result
 }
 
+/// Whether the email has any text in its body once HTML is converted and whitespace trimmed.
+/// Subject-only and attachment-only emails don't.
+pub fn has_text_body(email: &Email) -> bool {
+    body_text(email).is_some_and(|body| !body.trim().is_empty())
+}
+
+/// The email's body as plain text, converted from HTML if that's what it is.
+pub fn body_text(email: &Email) -> Option<String> {
+    email.body.as_deref().map(|body| plain_text_body(body, email.content_type.as_deref()))
+}
+
+/// Converts an HTML body to plain text wrapped at `config::html_wrap_width()`; plain text
+/// bodies are returned unchanged.
+pub fn plain_text_body(body: &str, content_type: Option<&str>) -> String {
+    plain_text_body_with_width(body, content_type, crate::config::html_wrap_width())
+}
+
+/// Converts an HTML body to plain text with lines wrapped at `width` columns, first stripping
+/// trackers if `config::sanitize_html()` is on.
+pub fn plain_text_body_with_width(body: &str, content_type: Option<&str>, width: usize) -> String {
+    if !is_html_body(body, content_type) {
+        return body.to_string();
+    }
+    if !crate::config::sanitize_html() {
+        return html2text::from_read(body.as_bytes(), width);
+    }
+    let sanitized = sanitize_html(body);
+    if sanitized.trackers_removed > 0 {
+        log::debug!("Removed {} tracker(s) from an HTML body", sanitized.trackers_removed);
+    }
+    // Convert HTML to plain text using the html2text library
+    html2text::from_read(sanitized.html.as_bytes(), width)
+}
+
+/// Whether `body` is HTML. A known content type decides; without one, a body containing both
+/// "<" and ">" is taken to be HTML.
+pub fn is_html_body(body: &str, content_type: Option<&str>) -> bool {
+    let mime_type = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|mime_type| mime_type.trim().to_lowercase());
+    match mime_type.as_deref() {
+        Some("text/html") => true,
+        Some("text/plain") => false,
+        _ => body.contains('<') && body.contains('>'),
+    }
+}
+
+/// Detects the language of an email body, returning its ISO 639-1 code (e.g. "de").
+///
+/// Runs on the plain text so HTML markup doesn't skew detection. Languages without a
+/// two-letter code fall back to whatlang's ISO 639-3 code.
+pub fn detect_language(body: &str, content_type: Option<&str>) -> Option<String> {
+    let info = whatlang::detect(&plain_text_body(body, content_type))?;
+    let code = info.lang().code();
+    let short = LANGUAGE_CODES.iter()
+        .find(|(iso3, _, _)| *iso3 == code)
+        .map(|(_, iso1, _)| *iso1)
+        .unwrap_or(code);
+    Some(short.to_string())
+}
+
+/// Finds a language the user asked to filter by, e.g. "show my English emails" or "emails in
+/// German". A language named any other way ("explain Kai's email in English") is the language
+/// to answer in, not a filter.
+pub fn language_in_query(query: &str) -> Option<String> {
+    let query = query.to_lowercase();
+    LANGUAGE_CODES.iter()
+        .find(|(_, _, name)| {
+            let pattern = format!(
+                r"\b{0}\s+(?:e-?mails?|mails?|messages?)\b|\b(?:e-?mails|mails|messages)\s+(?:written\s+)?in\s+{0}\b",
+                name
+            );
+            Regex::new(&pattern).unwrap().is_match(&query)
+        })
+        .map(|(_, iso1, _)| iso1.to_string())
+}
+
+// (ISO 639-3 as used by whatlang, ISO 639-1, English name)
+const LANGUAGE_CODES: &[(&str, &str, &str)] = &[
+    ("eng", "en", "english"),
+    ("deu", "de", "german"),
+    ("fra", "fr", "french"),
+    ("spa", "es", "spanish"),
+    ("ita", "it", "italian"),
+    ("por", "pt", "portuguese"),
+    ("nld", "nl", "dutch"),
+    ("swe", "sv", "swedish"),
+    ("dan", "da", "danish"),
+    ("nob", "nb", "norwegian"),
+    ("fin", "fi", "finnish"),
+    ("pol", "pl", "polish"),
+    ("ces", "cs", "czech"),
+    ("rus", "ru", "russian"),
+    ("ukr", "uk", "ukrainian"),
+    ("tur", "tr", "turkish"),
+    ("ell", "el", "greek"),
+    ("ara", "ar", "arabic"),
+    ("heb", "he", "hebrew"),
+    ("hin", "hi", "hindi"),
+    ("jpn", "ja", "japanese"),
+    ("kor", "ko", "korean"),
+    ("cmn", "zh", "chinese"),
+];
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_display_sender() {
+        let email = |from: Option<&str>, reply_to: Option<&str>, sender: Option<&str>| Email {
+            from: from.map(String::from),
+            reply_to: reply_to.map(String::from),
+            sender: sender.map(String::from),
+            ..Default::default()
+        };
+
+        // Present
+        assert_eq!(display_sender(&email(Some("Alice <alice@example.com>"), None, None)), "Alice <alice@example.com>");
+        assert_eq!(display_sender(&email(Some("bob@example.com"), Some("team@example.com"), None)), "bob@example.com");
+
+        // Missing: fall back to Reply-To, then Sender, then a placeholder
+        assert_eq!(display_sender(&email(None, Some("team@example.com"), None)), "team@example.com");
+        assert_eq!(display_sender(&email(Some("  "), None, Some("bot@example.com"))), "bot@example.com");
+        assert_eq!(display_sender(&Email::default()), "Unknown sender");
+
+        // Display name only
+        assert_eq!(display_sender(&email(Some("\"Alice Smith\""), None, None)), "Alice Smith");
+        assert_eq!(display_sender(&email(Some("Alice Smith <>"), None, None)), "Alice Smith");
+    }
+
+    #[test]
+    fn test_normalize_subject_strips_reply_and_forward_prefixes() {
+        assert_eq!(normalize_subject("Re: Development Timeline"), "Development Timeline");
+        assert_eq!(normalize_subject("Re: Fwd: Re: Topic"), "Topic");
+        assert_eq!(normalize_subject("RE:FW: fwd:  Budget "), "Budget");
+        assert_eq!(normalize_subject("Topic"), "Topic");
+        // Only leading prefixes count
+        assert_eq!(normalize_subject("Regarding: Re: Topic"), "Regarding: Re: Topic");
+        assert_eq!(normalize_subject("Re: "), "");
+    }
+
+    #[test]
+    fn test_thread_messages_are_chronological() {
+        let email = |id: &str, thread: Option<&str>, subject: &str, date: &str| Email {
+            message_id: Some(id.to_string()),
+            thread_id: thread.map(str::to_string),
+            subject: Some(subject.to_string()),
+            date: Some(date.to_string()),
+            ..Default::default()
+        };
+        let emails = vec![
+            email("reply", Some("t1"), "Re: Budget", "Tue, 13 May 2025 09:00:00 +0000"),
+            email("other", Some("t2"), "Budget", "Mon, 12 May 2025 10:00:00 +0000"),
+            email("start", Some("t1"), "Budget", "Mon, 12 May 2025 09:00:00 +0000"),
+            email("legacy", None, "RE: budget", "Wed, 14 May 2025 09:00:00 +0000"),
+            email("unrelated", None, "Lunch", "Mon, 12 May 2025 08:00:00 +0000"),
+        ];
+
+        let ids = |thread: Vec<Email>| thread.into_iter().map(|e| e.message_id.unwrap()).collect::<Vec<_>>();
+        // Thread ids decide when both emails have one; the subject is the fallback
+        assert_eq!(ids(thread_messages(&emails, &emails[0])), vec!["start", "reply", "legacy"]);
+        assert_eq!(ids(thread_messages(&emails, &emails[3])), vec!["start", "other", "reply", "legacy"]);
+        assert_eq!(ids(thread_messages(&emails, &emails[4])), vec!["unrelated"]);
+    }
+
+    #[test]
+    fn test_fold_name_strips_accents() {
+        assert_eq!(fold_name("José"), "jose");
+        assert_eq!(fold_name("Müller"), "muller");
+        assert_eq!(fold_name("Zoë Ångström <zoe@example.com>"), "zoe angstrom <zoe@example.com>");
+        // Precomposed and decomposed forms fold the same way
+        assert_eq!(fold_name("Jos\u{e9}"), fold_name("Jose\u{301}"));
+        assert_eq!(fold_name("Bob"), "bob");
+    }
+
+    #[test]
+    fn test_synthetic_message_id_is_stable() {
+        let email = Email {
+            from: Some("alice@example.com".to_string()),
+            date: Some("2025-03-04T12:00:00Z".to_string()),
+            subject: Some("Hello".to_string()),
+            ..Default::default()
+        };
+        let id = synthetic_message_id(&email);
+        assert!(id.starts_with("synthetic-"));
+        assert_eq!(id, synthetic_message_id(&email.clone()));
+
+        // The body doesn't matter, but the identifying headers do
+        let other_body = Email { body: Some("edited".to_string()), ..email.clone() };
+        assert_eq!(synthetic_message_id(&other_body), id);
+        let other_subject = Email { subject: Some("Hello again".to_string()), ..email.clone() };
+        assert_ne!(synthetic_message_id(&other_subject), id);
+
+        assert_eq!(with_message_id(&email).message_id, Some(id));
+        let with_id = Email { message_id: Some("msg-1".to_string()), ..email };
+        assert_eq!(with_message_id(&with_id).message_id.as_deref(), Some("msg-1"));
+    }
+
+    #[test]
+    fn test_split_address_list_respects_quotes() {
+        let entries = split_address_list("\"Smith, Bob\" <bob@example.com>, carol@example.com,  ");
+        assert_eq!(entries, vec!["\"Smith, Bob\" <bob@example.com>", "carol@example.com"]);
+        assert_eq!(extract_address(&entries[0]), "bob@example.com");
+        assert_eq!(extract_address("Carol@Example.com"), "carol@example.com");
+    }
+
+    #[test]
+    fn test_detect_language() {
+        let german = "Hallo zusammen, leider wurden die Rechnungen für das Schuljahr wegen eines technischen Fehlers doppelt verschickt. Bitte bezahlen Sie nur eine davon.";
+        let english = "<p>Hi team, the invoices for the school year were sent twice because of a technical error. Please pay only one of them.</p>";
+        assert_eq!(detect_language(german, Some("text/plain")).as_deref(), Some("de"));
+        assert_eq!(detect_language(english, None).as_deref(), Some("en"));
+
+        assert_eq!(language_in_query("show my English emails").as_deref(), Some("en"));
+        assert_eq!(language_in_query("any German mail from Kai?").as_deref(), Some("de"));
+        assert_eq!(language_in_query("show my emails"), None);
+        assert_eq!(language_in_query("list emails written in French").as_deref(), Some("fr"));
+        assert_eq!(language_in_query("Explain Kai's email in English"), None);
+        assert_eq!(language_in_query("summarize the invoice in German please"), None);
+    }
+
+    #[test]
+    fn test_sender_name_and_date_parsing() {
+        assert_eq!(sender_name("\"Alice Smith\" <alice@example.com>"), "Alice Smith");
+        assert_eq!(sender_name("<bob@example.com>"), "bob@example.com");
+        assert_eq!(sender_name("Carol@Example.com"), "carol@example.com");
+
+        let rfc2822 = parse_email_date("Mon, 05 May 2025 09:29:43 +0200").unwrap();
+        let rfc3339 = parse_email_date("2025-05-05T07:29:43Z").unwrap();
+        assert_eq!(rfc2822, rfc3339);
+        assert!(parse_email_date("not a date").is_none());
+    }
+
+    #[test]
+    fn test_reply_recipients_reply_vs_reply_all() {
+        let email = Email {
+            from: Some("Alice <alice@example.com>".to_string()),
+            to: Some("Me <me@example.com>, Bob <bob@example.com>".to_string()),
+            cc: Some("carol@example.com, ALICE@example.com".to_string()),
+            subject: Some("Budget thread".to_string()),
+            message_id: Some("budget-1".to_string()),
+            ..Default::default()
+        };
+
+        // A plain reply goes only to the sender
+        let reply = reply_recipients(&email, false, Some("me@example.com"));
+        assert_eq!(reply.to, vec!["Alice <alice@example.com>"]);
+        assert!(reply.cc.is_empty());
+
+        // Reply-all includes everyone except the user, without duplicating the sender
+        let reply_all = reply_recipients(&email, true, Some("me@example.com"));
+        assert_eq!(reply_all.to, vec!["Alice <alice@example.com>", "Bob <bob@example.com>"]);
+        assert_eq!(reply_all.cc, vec!["carol@example.com"]);
+    }
+
+    #[test]
+    fn test_reply_recipients_prefer_reply_to() {
+        let email = Email {
+            from: Some("Rust Users <noreply@lists.example.org>".to_string()),
+            reply_to: Some("rust-users@lists.example.org".to_string()),
+            to: Some("me@example.com".to_string()),
+            cc: Some("bob@example.com".to_string()),
+            ..Default::default()
+        };
+
+        let reply = reply_recipients(&email, false, Some("me@example.com"));
+        assert_eq!(reply.to, vec!["rust-users@lists.example.org"]);
+
+        let reply_all = reply_recipients(&email, true, Some("me@example.com"));
+        assert_eq!(reply_all.to, vec!["rust-users@lists.example.org"]);
+        assert_eq!(reply_all.cc, vec!["bob@example.com"]);
+
+        // A blank Reply-To header falls back to From
+        let blank = Email { reply_to: Some(" ".to_string()), ..email };
+        assert_eq!(reply_recipients(&blank, false, None).to, vec!["Rust Users <noreply@lists.example.org>"]);
+    }
+
+    #[test]
+    fn test_truncate_long_body_on_sentence_boundary() {
+        let sentence = "The roadmap for next quarter covers several initiatives. ";
+        let body = sentence.repeat(10_000 / sentence.len() + 1);
+        assert!(body.len() > 10_000);
+
+        let truncated = truncate_body(&body, 1000);
+        let (kept, note) = truncated.split_once('…').unwrap();
+        assert!(kept.chars().count() <= 1000);
+        assert!(kept.ends_with("initiatives."), "should cut after a full sentence: {}", kept);
+        assert!(note.contains(&format!("of {} characters", body.len())));
+
+        // Short bodies and a zero limit leave the email untouched
+        assert_eq!(truncate_body("Short body.", 1000), "Short body.");
+        let emails = vec![Email { body: Some(body.clone()), ..Default::default() }];
+        assert!(format_emails_with_limit(&emails, 0).contains(&body));
+        assert!(!format_emails_with_limit(&emails, 1000).contains(&body));
+        assert!(format_email_plain_text(&emails[0]).contains(&body));
+    }
+
+    #[test]
+    fn test_truncate_body_falls_back_to_word_boundary() {
+        let truncated = truncate_body("alpha beta gamma delta epsilon", 14);
+        assert!(truncated.starts_with("alpha beta…"), "{}", truncated);
+        // Multi-byte characters are counted, not bytes, and never split
+        assert!(truncate_body(&"é".repeat(50), 10).starts_with(&"é".repeat(10)));
+    }
+
+    #[test]
+    fn test_html_body_wrapped_to_width() {
+        let paragraph = "The quarterly roadmap covers hiring, the billing migration and the new onboarding flow. ".repeat(20);
+        let html = format!("<html><body><p>{}</p><p>{}</p></body></html>", paragraph, paragraph);
+
+        for width in [40, 80] {
+            let text = plain_text_body_with_width(&html, Some("text/html"), width);
+            assert!(text.lines().count() > 2, "long paragraphs should be wrapped");
+            for line in text.lines() {
+                assert!(line.chars().count() <= width, "line exceeds {} columns: {:?}", width, line);
+            }
+        }
+    }
+
+    #[test]
+    fn test_plain_text_body_with_angle_brackets_is_unchanged() {
+        let body = "Only retry if x > 5 and y < 3.\nUse <b> for bold.\n\n-- \nAlice <alice@example.com>";
+        assert_eq!(plain_text_body_with_width(body, Some("text/plain"), 80), body);
+        assert_eq!(plain_text_body_with_width(body, Some("Text/Plain; charset=UTF-8"), 80), body);
+
+        let email = Email { body: Some(body.to_string()), content_type: Some("text/plain".to_string()), ..Default::default() };
+        assert!(format_email_plain_text(&email).ends_with(body));
+    }
+
+    #[test]
+    fn test_html_body_text_drops_trackers() {
+        let html = r#"<p>Spring sale!</p><script>track("open")</script><img src="https://t.example.com/open.gif?u=42" width="1" height="1" alt="pixel">"#;
+        let text = plain_text_body_with_width(html, Some("text/html"), 80);
+        assert!(text.contains("Spring sale!"), "{}", text);
+        assert!(!text.contains("track(") && !text.contains("t.example.com") && !text.contains("pixel"), "{}", text);
+    }
+
+    #[test]
+    fn test_is_html_body_uses_content_type_before_heuristic() {
+        assert!(is_html_body("Plain words only", Some("text/html")));
+        assert!(!is_html_body("<p>Looks like HTML</p>", Some("text/plain")));
+        assert!(is_html_body("<p>Hi</p>", None));
+        assert!(is_html_body("<p>Hi</p>", Some("application/octet-stream")));
+        assert!(!is_html_body("x > 5", None));
+    }
+
+    #[test]
+    fn test_bodyless_emails_have_no_text_body() {
+        let email = |body: Option<&str>| Email {
+            subject: Some("Scan".to_string()),
+            body: body.map(str::to_string),
+            ..Default::default()
+        };
+        assert!(has_text_body(&email(Some("See attached."))));
+        assert!(!has_text_body(&email(None)));
+        assert!(!has_text_body(&email(Some(" \n\t "))));
+        assert!(!has_text_body(&email(Some("<html><body><p> </p></body></html>"))));
+
+        assert!(format_email_plain_text(&email(Some("  \n  "))).ends_with("\n\nNo body content"));
+    }
+
     #[test]
     fn test_format_email_plain_text() {
         // Create a test email with some HTML markup and binary-like content
@@ -95,6 +739,7 @@ mod tests {
                 "<html><body><h1>Meeting Notes</h1><p>Hi Bob,</p><p>Here are the <b>important</b> points from our meeting:</p><ul><li>Project deadline: May 15th</li><li>Budget approved: $10,000</li><li>Team members: Alice, Bob, Charlie</li></ul><p>Attached is the <a href='schedule.pdf'>schedule</a>.</p><p>Best regards,<br>Alice</p></body></html>".to_string()
             ),
             message_id: Some("msg123".to_string()),
+            ..Default::default()
         };
 
         // Format the email as plain text
@@ -141,6 +786,7 @@ mod tests {
             subject: Some("Important notice about double billing".to_string()),
             body: Some(r#"<p style="margin-top:0cm;margin-right:0cm;margin-bottom:12.0pt;margin-left:0cm;"><span style="font-size:9.0pt;font-family:'Verdana',sans-serif;color:black;">Dear parents and students,</span></p><p style="margin-top:0cm;margin-right:0cm;margin-bottom:12.0pt;margin-left:0cm;font-variant-ligatures:normal;font-variant-caps:normal;orphans:2;text-align:start;widows:2;-webkit-text-stroke-width:0px;text-decoration-thickness:initial;text-decoration-style:initial;text-decoration-color:initial;word-spacing:0px;"><span style="font-size:9.0pt;font-family:'Verdana',sans-serif;color:black;">Unfortunately, the invoices for the copying fee and the student association contribution for the school year 2024/25 were sent out twice due to a technical error.</span></p><p style="margin-top:0cm;margin-right:0cm;margin-bottom:12.0pt;margin-left:0cm;font-variant-ligatures:normal;font-variant-caps:normal;orphans:2;text-align:start;widows:2;-webkit-text-stroke-width:0px;text-decoration-thickness:initial;text-decoration-style:initial;text-decoration-color:initial;word-spacing:0px;"><span style="font-size:9.0pt;font-family:'Verdana',sans-serif;color:black;">The invoices show the same invoice number and the same invoice date. We ask you to pay only one invoice and destroy the second one.</span></p><p style="margin-top:0cm;margin-right:0cm;margin-bottom:12.0pt;margin-left:0cm;font-variant-ligatures:normal;font-variant-caps:normal;orphans:2;text-align:start;widows:2;-webkit-text-stroke-width:0px;text-decoration-thickness:initial;text-decoration-style:initial;text-decoration-color:initial;word-spacing:0px;"><span style="font-size:9.0pt;font-family:'Verdana',sans-serif;color:black;">We apologize for the inconvenience.</span></p><p style="margin-top:0cm;margin-right:0cm;margin-bottom:12.0pt;margin-left:0cm;font-variant-ligatures:normal;font-variant-caps:normal;orphans:2;text-align:start;widows:2;-webkit-text-stroke-width:0px;text-decoration-thickness:initial;text-decoration-style:initial;text-decoration-color:initial;word-spacing:0px;"><span style="font-size:9.0pt;font-family:'Verdana',sans-serif;color:black;">Kind regards,</span></p><p style="margin-top:0cm;margin-right:0cm;margin-bottom:12.0pt;margin-left:0cm;font-variant-ligatures:normal;font-variant-caps:normal;orphans:2;text-align:start;widows:2;-webkit-text-stroke-width:0px;text-decoration-thickness:initial;text-decoration-style:initial;text-decoration-color:initial;word-spacing:0px;"><a name="_MailAutoSig"><span style="font-size:9.0pt;font-family:'Arial Black',sans-serif;color:black;">Example School</span></a></p><p style="margin-top:0cm;margin-right:0cm;margin-bottom:12.0pt;margin-left:0cm;font-variant-ligatures:normal;font-variant-caps:normal;orphans:2;text-align:start;widows:2;-webkit-text-stroke-width:0px;text-decoration-thickness:initial;text-decoration-style:initial;text-decoration-color:initial;word-spacing:0px;"><span style="font-size:9.0pt;font-family:'Arial Black',sans-serif;color:black;">Test Sender</span></p><p style="margin-top:0cm;margin-right:0cm;margin-bottom:12.0pt;margin-left:0cm;font-variant-ligatures:normal;font-variant-caps:normal;orphans:2;text-align:start;widows:2;-webkit-text-stroke-width:0px;text-decoration-thickness:initial;text-decoration-style:initial;text-decoration-color:initial;word-spacing:0px;"><span style="font-size:9.0pt;font-family:'Arial',sans-serif;color:black;">Administration</span></p><p style="margin-top:0cm;margin-right:0cm;margin-bottom:12.0pt;margin-left:0cm;font-variant-ligatures:normal;font-variant-caps:normal;orphans:2;text-align:start;widows:2;-webkit-text-stroke-width:0px;text-decoration-thickness:initial;text-decoration-style:initial;text-decoration-color:initial;word-spacing:0px;"><span style="font-size:9.0pt;font-family:'Arial',sans-serif;color:black;">Example Road 17<br />12345 Example City<br />Phone 123 456 7890</span></p><p style="margin-top:0cm;margin-right:0cm;margin-bottom:12.0pt;margin-left:0cm;font-variant-ligatures:normal;font-variant-caps:normal;orphans:2;text-align:start;widows:2;-webkit-text-stroke-width:0px;text-decoration-thickness:initial;text-decoration-style:initial;text-decoration-color:initial;word-spacing:0px;"><span style="font-size:9.0pt;font-family:'Verdana',sans-serif;color:black;"><a href="http://www.example.org/"><span style="font-family:'Arial',sans-serif;">www.example.org</span></a></span></p><p><span style="font-size:10.0pt;font-family:'Arial',sans-serif;">&nbsp;</span></p>"#.to_string()),
             message_id: Some("test123".to_string()),
+            ..Default::default()
         };
 
         // Format the email as plain text
@@ -1,13 +1,20 @@
-use meilisearch_sdk::{client::Client, indexes::Index};
+use meilisearch_sdk::{client::Client, documents::DocumentsQuery, errors::ErrorCode, indexes::Index, settings::Settings};
 use crate::config;
-use crate::models::email::Email;
-use crate::models::email_query::QueryCriteria;
-use log::{error};
+use crate::models::email::{Email, extract_address, fold_name, normalize_subject, parse_email_date, sender_name, split_address_list, thread_messages, with_message_id};
+use crate::models::email_query::{FieldWeights, QueryCriteria, Relaxation, classify_sender_query};
+use crate::models::mbox::to_mbox;
+use crate::models::redaction::{redact, redact_text};
+use crate::models::similarity::most_similar;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use regex::Regex;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// An async wrapper for the MeiliSearch Email DB.
 #[derive(Clone)]
 pub struct EmailDB {
-    admin_client: Client,
+    admin_client: Arc<Client>,
     index: Index,
 }
 
@@ -25,11 +32,21 @@ pub enum EmailDBError {
 
     #[error("Operation error: {0}")]
     OperationError(String),
+
+    /// The email (or other document) asked for isn't stored
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Configuration error: {0}")]
+    ConfigError(#[from] config::ConfigError),
 }
 
 impl From<meilisearch_sdk::errors::Error> for EmailDBError {
     fn from(error: meilisearch_sdk::errors::Error) -> Self {
         match error {
+            meilisearch_sdk::errors::Error::Meilisearch(e) if e.error_code == ErrorCode::DocumentNotFound => {
+                EmailDBError::NotFound(e.error_message)
+            }
             e => EmailDBError::OperationError(e.to_string()),
         }
     }
@@ -41,10 +58,22 @@ impl From<meilisearch_sdk::errors::Error> for EmailDBError {
 pub trait EmailDBInterface {
     async fn store_email(&self, email: &Email) -> Result<(), EmailDBError>;
     async fn delete_email(&self, message_id: &str) -> Result<(), EmailDBError>;
+    async fn get_email(&self, message_id: &str) -> Result<Option<Email>, EmailDBError>;
     async fn search_emails(&self, query: &str) -> Result<Vec<Email>, EmailDBError>;
     async fn store_emails(&self, emails: &[Email]) -> Result<(), EmailDBError>;
     async fn get_all_emails(&self) -> Result<Vec<Email>, EmailDBError>;
+    async fn count_emails(&self) -> Result<usize, EmailDBError>;
+    async fn get_emails_page(&self, offset: usize, limit: usize) -> Result<Vec<Email>, EmailDBError>;
+    async fn get_most_important_emails(&self, limit: usize) -> Result<Vec<Email>, EmailDBError>;
+    async fn find_similar(&self, reference: &Email, top_n: usize) -> Result<Vec<Email>, EmailDBError>;
+    async fn export_mbox(&self) -> Result<String, EmailDBError>;
+    async fn reconfigure(&self) -> Result<(), EmailDBError>;
     async fn search_emails_by_criteria(&self, criteria: QueryCriteria) -> Result<Vec<Email>, EmailDBError>;
+    async fn search_with_relaxation(&self, criteria: QueryCriteria) -> Result<(Vec<Email>, Vec<Relaxation>), EmailDBError>;
+    async fn find_by_criteria(&self, criteria: &QueryCriteria) -> Result<Vec<Email>, EmailDBError>;
+    async fn count_by_criteria(&self, criteria: &QueryCriteria) -> Result<usize, EmailDBError>;
+    async fn get_thread(&self, anchor: &Email) -> Result<Vec<Email>, EmailDBError>;
+    async fn delete_by_criteria(&self, criteria: QueryCriteria) -> Result<usize, EmailDBError>;
     async fn clear(&self) -> Result<(), EmailDBError>;
 }
 
@@ -60,6 +89,10 @@ impl EmailDBInterface for EmailDB {
         self.delete_email(message_id).await
     }
 
+    async fn get_email(&self, message_id: &str) -> Result<Option<Email>, EmailDBError> {
+        self.get_email(message_id).await
+    }
+
     async fn search_emails(&self, query: &str) -> Result<Vec<Email>, EmailDBError> {
         self.search_emails(query).await
     }
@@ -72,10 +105,54 @@ impl EmailDBInterface for EmailDB {
         self.get_all_emails().await
     }
 
+    async fn count_emails(&self) -> Result<usize, EmailDBError> {
+        self.count_emails().await
+    }
+
+    async fn get_emails_page(&self, offset: usize, limit: usize) -> Result<Vec<Email>, EmailDBError> {
+        self.get_emails_page(offset, limit).await
+    }
+
+    async fn get_most_important_emails(&self, limit: usize) -> Result<Vec<Email>, EmailDBError> {
+        self.get_most_important_emails(limit).await
+    }
+
+    async fn find_similar(&self, reference: &Email, top_n: usize) -> Result<Vec<Email>, EmailDBError> {
+        self.find_similar(reference, top_n).await
+    }
+
+    async fn export_mbox(&self) -> Result<String, EmailDBError> {
+        self.export_mbox().await
+    }
+
+    async fn reconfigure(&self) -> Result<(), EmailDBError> {
+        self.reconfigure().await
+    }
+
     async fn search_emails_by_criteria(&self, criteria: QueryCriteria) -> Result<Vec<Email>, EmailDBError> {
         self.search_emails_by_criteria(criteria).await
     }
 
+    async fn search_with_relaxation(&self, criteria: QueryCriteria) -> Result<(Vec<Email>, Vec<Relaxation>), EmailDBError> {
+        self.search_with_relaxation(criteria).await
+    }
+
+    async fn find_by_criteria(&self, criteria: &QueryCriteria) -> Result<Vec<Email>, EmailDBError> {
+        self.find_by_criteria(criteria).await
+    }
+
+    async fn count_by_criteria(&self, criteria: &QueryCriteria) -> Result<usize, EmailDBError> {
+        self.count_by_criteria(criteria).await
+    }
+
+    async fn get_thread(&self, anchor: &Email) -> Result<Vec<Email>, EmailDBError> {
+        self.get_thread(anchor).await
+    }
+
+    async fn delete_by_criteria(&self, criteria: QueryCriteria) -> Result<usize, EmailDBError> {
+        self.delete_by_criteria(criteria).await
+    }
+
     async fn clear(&self) -> Result<(), EmailDBError> {
         self.clear().await
     }
@@ -87,18 +164,34 @@ impl EmailDB {
         admin_key: Option<&str>,
         index_name: &str,
     ) -> Result<Self, EmailDBError> {
+        Self::with_client(Arc::new(Self::admin_client(url, admin_key)?), index_name).await
+    }
+
+    /// Creates a MeiliSearch client with the admin key. Wrapped in an `Arc`, one client can back
+    /// every session's `EmailDB` so they share its connection pool.
+    pub fn admin_client(url: &str, admin_key: Option<&str>) -> Result<Client, EmailDBError> {
         // Validate keys for write and read operations.
         let admin_key = admin_key
             .filter(|k| !k.is_empty())
             .ok_or_else(|| EmailDBError::AuthError("Admin key is required".to_string()))?;
 
-        // Create admin and search clients.
-        let admin_client = Client::new(url, Some(admin_key))
-            .map_err(|e| EmailDBError::ConnectionError(format!("Failed to create admin client: {}", e)))?;
+        Client::new(url, Some(admin_key))
+            .map_err(|e| EmailDBError::ConnectionError(format!("Failed to create admin client: {}", e)))
+    }
+
+    /// The admin client for the configured MeiliSearch server.
+    pub fn client_from_config(config: &config::Config) -> Result<Client, EmailDBError> {
+        Self::admin_client(config.meilisearch_url.as_str(), Some(config.meilisearch_admin_key.as_str()))
+    }
 
-        // Verify connectivity.
-        admin_client.health().await
-            .map_err(|e| EmailDBError::AuthError(format!("Failed to verify health: {}", e)))?;
+    /// Opens `index_name` through an existing (possibly shared) client, creating the index if needed.
+    pub async fn with_client(admin_client: Arc<Client>, index_name: &str) -> Result<Self, EmailDBError> {
+        // Verify connectivity, waiting for MeiliSearch if it is still starting up.
+        let retry = HealthRetry::from_config();
+        wait_until_healthy(|| admin_client.health(), &retry).await
+            .map_err(|e| EmailDBError::ConnectionError(format!(
+                "MeiliSearch at {} not healthy after {} attempts: {}", admin_client.get_host(), retry.attempts, e
+            )))?;
 
         // Get the index; if it doesn't exist, create it and set filterable attributes.
         let index = match admin_client.get_index(index_name).await {
@@ -113,10 +206,8 @@ impl EmailDB {
                 let idx = admin_client.get_index(index_name).await
                     .map_err(|e| EmailDBError::IndexError(format!("Failed to get index after creation: {}", e)))?;
 
-                // Set filterable attributes only for newly created index
-                let filterable_attributes = vec!["from", "to", "subject", "date"];
-                idx.set_filterable_attributes(&filterable_attributes).await
-                    .map_err(|e| EmailDBError::IndexError(format!("Failed to set filterable attributes: {}", e)))?;
+                // Existing indexes keep their settings until `reconfigure` is called
+                apply_index_settings(&admin_client, &idx).await?;
 
                 idx
             }
@@ -128,23 +219,51 @@ impl EmailDB {
         })
     }
 
+    /// Applies the current index settings (filterable, sortable and searchable attributes and
+    /// ranking rules) to this existing index, keeping its documents.
+    pub async fn reconfigure(&self) -> Result<(), EmailDBError> {
+        apply_index_settings(&self.admin_client, &self.index).await
+    }
+
+    /// An EmailDB handle that never contacts MeiliSearch, for tests that only need a session.
+    #[cfg(test)]
+    pub(crate) fn unconnected(index_name: &str) -> Self {
+        let admin_client = Arc::new(Client::new("http://localhost:7700", Some("test")).unwrap());
+        let index = admin_client.index(index_name);
+        EmailDB { admin_client, index }
+    }
+
     pub async fn default() -> Result<Self, EmailDBError> {
+        Self::from_config(&config::load()?).await
+    }
+
+    /// Connects to the configured index (`MEILI_INDEX`, default "emails") with the given settings.
+    pub async fn from_config(config: &config::Config) -> Result<Self, EmailDBError> {
         Self::new(
-            config::meilisearch_url().as_str(),
-            Some(config::meilisearch_admin_key().as_str()),
-           "emails"
+            config.meilisearch_url.as_str(),
+            Some(config.meilisearch_admin_key.as_str()),
+            config.meilisearch_index.as_str(),
         ).await
     }
 
+    /// Name of the MeiliSearch index this handle reads and writes.
+    pub fn index_name(&self) -> &str {
+        &self.index.uid
+    }
+
+    /// Stores an email, generating a synthetic id if it has no `message_id` so it isn't dropped.
     pub async fn store_email(&self, email: &Email) -> Result<(), EmailDBError> {
-        self.index.add_or_update(&[email], Some("message_id"))
+        self.index.add_or_update(&[with_message_id(email)], Some("message_id"))
             .await?
             .wait_for_completion(&self.admin_client, None, None)
             .await?;
         Ok(())
     }
 
+    /// Deletes a stored email, returning `NotFound` if there's no email with that id.
     pub async fn delete_email(&self, message_id: &str) -> Result<(), EmailDBError> {
+        // Meilisearch reports deleting a missing document as a success, so check first
+        self.index.get_document::<Email>(message_id).await?;
         self.index.delete_document(message_id)
             .await?
             .wait_for_completion(&self.admin_client, None, None)
@@ -152,6 +271,15 @@ impl EmailDB {
         Ok(())
     }
 
+    /// Fetches a single email by its message id, returning `None` if it isn't stored.
+    pub async fn get_email(&self, message_id: &str) -> Result<Option<Email>, EmailDBError> {
+        match self.index.get_document::<Email>(message_id).await.map_err(EmailDBError::from) {
+            Ok(email) => Ok(Some(email)),
+            Err(EmailDBError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     pub async fn search_emails(&self, query: &str) -> Result<Vec<Email>, EmailDBError> {
         // For tests, if the query is a specific test-related string, use a custom handling
         // This helps make tests more reliable without changing production behavior
@@ -168,8 +296,10 @@ impl EmailDB {
         Ok(search_result.hits.into_iter().map(|hit| hit.result).collect())
     }
 
+    /// Stores emails in bulk, generating synthetic ids for any without a `message_id`.
     pub async fn store_emails(&self, emails: &[Email]) -> Result<(), EmailDBError> {
-        self.index.add_or_update(emails, Some("message_id"))
+        let emails: Vec<Email> = emails.iter().map(with_message_id).collect();
+        self.index.add_or_update(&emails, Some("message_id"))
             .await?
             .wait_for_completion(&self.admin_client, None, None)
             .await?;
@@ -187,6 +317,106 @@ impl EmailDB {
         Ok(search_result.hits.into_iter().map(|hit| hit.result).collect())
     }
 
+    /// Gets up to `limit` stored emails starting at `offset`, for paging through the mailbox.
+    /// Reads documents rather than searching, since search results stop at `maxTotalHits` (1000).
+    pub async fn get_emails_page(&self, offset: usize, limit: usize) -> Result<Vec<Email>, EmailDBError> {
+        let documents = DocumentsQuery::new(&self.index)
+            .with_offset(offset)
+            .with_limit(limit)
+            .execute::<Email>()
+            .await?;
+
+        Ok(documents.results)
+    }
+
+    /// Gets up to `limit` emails ordered by their ingest-time importance score, highest first.
+    pub async fn get_most_important_emails(&self, limit: usize) -> Result<Vec<Email>, EmailDBError> {
+        let sorted = self.index.search()
+            .with_sort(&["importance:desc"])
+            .with_limit(limit)
+            .execute::<Email>()
+            .await;
+
+        match sorted {
+            Ok(search_result) => Ok(search_result.hits.into_iter().map(|hit| hit.result).collect()),
+            // Indexes created before importance was sortable reject the sort until reconfigured
+            Err(e) => {
+                warn!("Sorting {} by importance failed ({}); sorting in memory instead", self.index.uid, e);
+                Ok(most_important(self.get_every_email().await?, limit))
+            }
+        }
+    }
+
+    /// Gets up to `top_n` stored emails on the same topic as `reference`, most similar first,
+    /// leaving out `reference` itself.
+    pub async fn find_similar(&self, reference: &Email, top_n: usize) -> Result<Vec<Email>, EmailDBError> {
+        Ok(most_similar(reference, self.get_every_email().await?, top_n))
+    }
+
+    /// Serializes every stored email into mbox format, for backups.
+    pub async fn export_mbox(&self) -> Result<String, EmailDBError> {
+        Ok(to_mbox(&self.get_every_email().await?))
+    }
+
+    /// Finds every stored email satisfying `criteria` exactly (see `email_matches_criteria`),
+    /// unlike the ranked and truncated results of `search_emails_by_criteria`.
+    pub async fn find_by_criteria(&self, criteria: &QueryCriteria) -> Result<Vec<Email>, EmailDBError> {
+        Ok(self.get_every_email().await?
+            .into_iter()
+            .filter(|email| email_matches_criteria(email, criteria))
+            .collect())
+    }
+
+    /// Counts the stored emails satisfying `criteria`, with the same matching as `find_by_criteria`.
+    pub async fn count_by_criteria(&self, criteria: &QueryCriteria) -> Result<usize, EmailDBError> {
+        Ok(self.get_every_email().await?
+            .iter()
+            .filter(|email| email_matches_criteria(email, criteria))
+            .count())
+    }
+
+    /// Every stored email in `anchor`'s conversation, oldest first.
+    pub async fn get_thread(&self, anchor: &Email) -> Result<Vec<Email>, EmailDBError> {
+        Ok(thread_messages(&self.get_every_email().await?, anchor))
+    }
+
+    /// Deletes every email matching `criteria`, returning how many were removed.
+    pub async fn delete_by_criteria(&self, criteria: QueryCriteria) -> Result<usize, EmailDBError> {
+        let message_ids: Vec<String> = self.find_by_criteria(&criteria).await?
+            .into_iter()
+            .filter_map(|email| email.message_id)
+            .collect();
+        if message_ids.is_empty() {
+            return Ok(0);
+        }
+        self.index.delete_documents(&message_ids)
+            .await?
+            .wait_for_completion(&self.admin_client, None, None)
+            .await?;
+        Ok(message_ids.len())
+    }
+
+    // Reads the whole index a page at a time, unlike `get_all_emails` which stops at 100
+    async fn get_every_email(&self) -> Result<Vec<Email>, EmailDBError> {
+        const PAGE_SIZE: usize = 100;
+        let mut emails = Vec::new();
+        loop {
+            let page = self.get_emails_page(emails.len(), PAGE_SIZE).await?;
+            let done = page.len() < PAGE_SIZE;
+            emails.extend(page);
+            if done {
+                break;
+            }
+        }
+        Ok(emails)
+    }
+
+    /// Returns how many emails are stored in the index.
+    pub async fn count_emails(&self) -> Result<usize, EmailDBError> {
+        let stats = self.index.get_stats().await?;
+        Ok(stats.number_of_documents)
+    }
+
     pub async fn search_emails_by_criteria(&self, criteria: QueryCriteria) -> Result<Vec<Email>, EmailDBError> {
         // Special test handling
         #[cfg(test)]
@@ -262,11 +492,17 @@ impl EmailDB {
                     .with_limit(100)
                     .execute::<Email>()
                     .await?;
-                let results: Vec<Email> = search_result.hits.into_iter().map(|hit| hit.result).collect();
+                let results: Vec<Email> = search_result.hits.into_iter()
+                    .map(|hit| hit.result)
+                    .filter(|email| criteria.language.is_none() || email.language == criteria.language)
+                    .filter(|email| email_in_date_range(email, criteria.date_from, criteria.date_to))
+                    .filter(|email| attachments_match(email, &criteria))
+                    .filter(|email| phrases_match(email, &criteria))
+                    .collect();
                 
-                // Get the query details
-                let name_lower = from_name.to_lowercase();
-                let raw_query_lower = criteria.raw_query.to_lowercase();
+                // Get the query details, folded so "Jose" matches "José"
+                let name_lower = fold_name(from_name);
+                let raw_query_lower = fold_name(&criteria.raw_query);
                 
                 #[derive(Debug)]
                 struct ScoredEmail {
@@ -281,7 +517,7 @@ impl EmailDB {
                 // and calculate their base scores
                 for email in results {
                     let from_text = match &email.from {
-                        Some(from) => from.to_lowercase(),
+                        Some(from) => fold_name(from),
                         None => continue, // Skip emails with no from field
                     };
                     
@@ -320,7 +556,7 @@ impl EmailDB {
                             if email_name == name_lower {
                                 score = 40.0; // Very high match for email username matching search term
                                 is_exact_name_match = true;
-                                log::info!("Found exact match between email username '{}' and search term '{}'", email_name, name_lower);
+                                log::info!("Found exact match between email username '{}' and search term '{}'", redact_text(&email_name), redact_text(&name_lower));
                             }
                         }
                     }
@@ -342,7 +578,7 @@ impl EmailDB {
                     }
                     
                     // Get subject and date for further scoring
-                    let subject = email.subject.as_ref().map(|s| s.to_lowercase()).unwrap_or_default();
+                    let subject = email.subject.as_deref().map(|s| normalize_subject(s).to_lowercase()).unwrap_or_default();
                     let date = email.date.as_ref().unwrap_or(&"".to_string()).clone();
                     
                     // Special test cases for Kai's email with invoice query
@@ -387,6 +623,11 @@ impl EmailDB {
                 // apply additional scoring criteria
                 for result in &mut filtered_results {
                     let email = &result.email;
+
+                    // Senders the user asked to prioritize outrank otherwise equal matches
+                    if email.from.as_deref().is_some_and(|from| is_priority_sender(from, &criteria.priority_senders)) {
+                        result.score += PRIORITY_SENDER_BOOST;
+                    }
                     
                     // Check for specific terms in the query
                     let has_updated_term = raw_query_lower.contains("updated") || 
@@ -401,7 +642,13 @@ impl EmailDB {
                     
                     // If subject contains terms mentioned in query, boost score
                     if let Some(ref subject) = email.subject {
-                        let subject_lower = subject.to_lowercase();
+                        // Compare without "Re:"/"Fwd:" so replies match the thread's topic
+                        let subject_lower = normalize_subject(subject).to_lowercase();
+
+                        // The query names the subject, e.g. "the development timeline email"
+                        if !subject_lower.is_empty() && raw_query_lower.contains(&subject_lower) {
+                            result.score += 15.0;
+                        }
                         
                         if has_updated_term && subject_lower.contains("update") {
                             result.score += 15.0; // Big boost for matching "update" term
@@ -444,9 +691,10 @@ impl EmailDB {
                 filtered_results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
                 
                 // Debug log for query detection
-                log::info!("Checking if query '{}' is generic about sender '{}'", raw_query_lower, name_lower);
-                let is_generic = Self::is_generic_query_about_sender(&raw_query_lower, &name_lower);
-                log::info!("Is generic query: {}", is_generic);
+                log::info!("Checking if query '{}' is generic about sender '{}'", redact_text(&raw_query_lower), redact_text(&name_lower));
+                let query_class = classify_sender_query(&raw_query_lower, &name_lower);
+                log::info!("Is generic query: {} (confidence {:.2})", query_class.generic, query_class.confidence);
+                let is_generic = query_class.generic;
                 
                 // Special test case handling: If this is a query about "Kai" with no other qualifiers,
                 // explicitly prioritize the most recent email regardless of other scoring factors
@@ -463,12 +711,7 @@ impl EmailDB {
                         b_date.cmp(a_date)
                     });
                     
-                    log::info!(
-                        "Selected: From: {:?}, Subject: {:?}, Date: {:?}",
-                        filtered_results[0].email.from,
-                        filtered_results[0].email.subject,
-                        filtered_results[0].email.date
-                    );
+                    log::info!("Selected: {}", redact(&filtered_results[0].email));
                     
                     // Return only the most recent email for this special case
                     return Ok(vec![filtered_results[0].email.clone()]);
@@ -498,12 +741,7 @@ impl EmailDB {
                     });
                     
                     log::info!("Generic sender query detected. Prioritizing most recent email.");
-                    log::info!(
-                        "Selected: From: {:?}, Subject: {:?}, Date: {:?}",
-                        filtered_results[0].email.from,
-                        filtered_results[0].email.subject,
-                        filtered_results[0].email.date
-                    );
+                    log::info!("Selected: {}", redact(&filtered_results[0].email));
                     
                     // Return only the most recent email for generic queries
                     return Ok(vec![filtered_results[0].email.clone()]);
@@ -515,44 +753,117 @@ impl EmailDB {
             }
         }
 
+        // Same idea for a recipient given by name ("emails I sent to Bob"): filter in code,
+        // since Meilisearch filters only match the exact To header
+        if criteria.from.is_none() {
+            if let Some(ref to_name) = criteria.to {
+                if !to_name.contains('@') {
+                    let search_result = self.index.search()
+                        .with_query("")
+                        .with_limit(100)
+                        .execute::<Email>()
+                        .await?;
+
+                    let mut scored: Vec<(f64, Email)> = search_result.hits.into_iter()
+                        .map(|hit| hit.result)
+                        .filter(|email| criteria.language.is_none() || email.language == criteria.language)
+                        .filter(|email| email_in_date_range(email, criteria.date_from, criteria.date_to))
+                        .filter(|email| attachments_match(email, &criteria))
+                        .filter(|email| phrases_match(email, &criteria))
+                        .filter_map(|email| {
+                            let score = recipient_match_score(email.to.as_deref()?, to_name);
+                            (score > 0.0).then_some((score, email))
+                        })
+                        .collect();
+
+                    // Best match first, most recent first among equal scores
+                    scored.sort_by(|(a_score, a), (b_score, b)| {
+                        b_score.partial_cmp(a_score)
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                            .then_with(|| {
+                                let a_date = a.date.as_deref().and_then(parse_email_date);
+                                let b_date = b.date.as_deref().and_then(parse_email_date);
+                                b_date.cmp(&a_date)
+                            })
+                    });
+                    return Ok(scored.into_iter().map(|(_, email)| email).collect());
+                }
+            }
+        }
+
         // Proceed with normal MeiliSearch query + filters
         use crate::models::query_builder::EmailQueryBuilder;
         let builder = EmailQueryBuilder::new(criteria.clone());
         let (query, filter) = builder.build_meili_query();
-        let mut search_query = self.index.search();
-        if let Some(ref q) = query { search_query.with_query(q); }
-        if let Some(ref f) = filter { search_query.with_filter(f); }
-        let search_result = search_query
-            .execute::<Email>()
-            .await
-            .map_err(|e| EmailDBError::OperationError(format!("Search failed: {}", e)))?;
-        Ok(search_result.hits.into_iter().map(|hit| hit.result).collect())
+        let mut results: Vec<Email> = if criteria.date_from.is_some() || criteria.date_to.is_some() {
+            // Dates are stored as header strings, which don't order correctly as a filter, so
+            // every match is fetched and the range applied here
+            self.search_every_hit(query.as_deref(), filter.as_deref()).await?
+                .into_iter()
+                .filter(|email| email_in_date_range(email, criteria.date_from, criteria.date_to))
+                .collect()
+        } else {
+            let mut search_query = self.index.search();
+            if let Some(ref q) = query { search_query.with_query(q); }
+            if let Some(ref f) = filter { search_query.with_filter(f); }
+            let search_result = search_query
+                .execute::<Email>()
+                .await
+                .map_err(|e| EmailDBError::OperationError(format!("Search failed: {}", e)))?;
+            search_result.hits.into_iter().map(|hit| hit.result).collect()
+        };
+        if let Some(weights) = &criteria.field_weights {
+            let terms: Vec<String> = criteria.keywords.iter().chain(&criteria.phrases).cloned().collect();
+            results = rank_by_field_weights(results, &terms, weights);
+        }
+        // Stable sort keeps Meilisearch's relevance order within each group
+        results.sort_by_key(|email| {
+            !email.from.as_deref().is_some_and(|from| is_priority_sender(from, &criteria.priority_senders))
+        });
+        Ok(results)
     }
     
-    // Helper function to determine if a query is just asking for emails from a sender
-    // without any specific qualifiers
-    fn is_generic_query_about_sender(query: &str, sender_name: &str) -> bool {
-        // Check for common patterns that indicate a generic request for emails
-        let contains_email_terms = query.contains("email") || 
-                                  query.contains("message") || 
-                                  query.contains("mail") || 
-                                  query.contains("explain") ||
-                                  query.contains("please");
-                                 
-        let mentions_sender = (query.contains("from") && query.contains(sender_name)) ||
-                             query.contains(&format!("from {}", sender_name)) ||
-                             query.contains(&format!("{}'s", sender_name));
-        
-        // Check for specific qualifiers that would make it not a generic query
-        let has_specific_qualifiers = query.contains("invoice") || 
-                                     query.contains("update") || 
-                                     query.contains("meeting") || 
-                                     query.contains("subject") || 
-                                     query.contains("about");
-                                     
-        // If it's a simple query like "explain the email from Kai" without specific qualifiers,
-        // consider it a generic query that should return the most recent email
-        contains_email_terms && mentions_sender && !has_specific_qualifiers
+    // Every hit for a search a page at a time, rather than just Meilisearch's default first 20
+    async fn search_every_hit(&self, query: Option<&str>, filter: Option<&str>) -> Result<Vec<Email>, EmailDBError> {
+        const PAGE_SIZE: usize = 100;
+        let mut emails = Vec::new();
+        loop {
+            let mut search_query = self.index.search();
+            search_query.with_offset(emails.len()).with_limit(PAGE_SIZE);
+            if let Some(q) = query { search_query.with_query(q); }
+            if let Some(f) = filter { search_query.with_filter(f); }
+            let page: Vec<Email> = search_query
+                .execute::<Email>()
+                .await
+                .map_err(|e| EmailDBError::OperationError(format!("Search failed: {}", e)))?
+                .hits.into_iter().map(|hit| hit.result).collect();
+            let done = page.len() < PAGE_SIZE;
+            emails.extend(page);
+            if done {
+                return Ok(emails);
+            }
+        }
+    }
+
+    /// Searches with `criteria` and, when nothing matches, retries with its `relaxations` in turn.
+    /// Returns the matches and the constraints dropped to find them (none if the first search matched).
+    pub async fn search_with_relaxation(&self, criteria: QueryCriteria) -> Result<(Vec<Email>, Vec<Relaxation>), EmailDBError> {
+        let steps = criteria.relaxations();
+        let matches = self.search_emails_by_criteria(criteria).await?;
+        if !matches.is_empty() {
+            return Ok((matches, Vec::new()));
+        }
+
+        let mut dropped = Vec::new();
+        for (relaxation, broader) in steps {
+            dropped.push(relaxation);
+            let matches = self.search_emails_by_criteria(broader).await?;
+            if !matches.is_empty() {
+                log::info!("Search matched nothing; found {} emails after dropping {:?}", matches.len(), dropped);
+                return Ok((matches, dropped));
+            }
+        }
+        Ok((Vec::new(), Vec::new()))
     }
 
     /// Clears all emails in the index.
@@ -565,6 +876,217 @@ impl EmailDB {
     }
 }
 
+pub(crate) const FILTERABLE_ATTRIBUTES: &[&str] = &["from", "to", "subject", "date", "language", "attachment_count", "attachment_types"];
+const SORTABLE_ATTRIBUTES: &[&str] = &["importance"];
+const SEARCHABLE_ATTRIBUTES: &[&str] = &["*"];
+const RANKING_RULES: &[&str] = &["words", "typo", "proximity", "attribute", "sort", "exactness"];
+
+/// The settings every email index should have.
+pub fn index_settings() -> Settings {
+    Settings::new()
+        .with_filterable_attributes(FILTERABLE_ATTRIBUTES)
+        .with_sortable_attributes(SORTABLE_ATTRIBUTES)
+        .with_searchable_attributes(SEARCHABLE_ATTRIBUTES)
+        .with_ranking_rules(RANKING_RULES)
+}
+
+async fn apply_index_settings(client: &Client, index: &Index) -> Result<(), EmailDBError> {
+    index.set_settings(&index_settings()).await
+        .map_err(|e| EmailDBError::IndexError(format!("Failed to update index settings: {}", e)))?
+        .wait_for_completion(client, None, None).await
+        .map_err(|e| EmailDBError::IndexError(format!("Failed to complete index settings update: {}", e)))?;
+    Ok(())
+}
+
+/// How patiently `EmailDB::new` waits for MeiliSearch to report healthy.
+#[derive(Debug, Clone)]
+pub struct HealthRetry {
+    pub attempts: u32,
+    /// Wait after the first failed check; doubles after each further failure
+    pub interval: Duration,
+    pub max_interval: Duration,
+}
+
+impl HealthRetry {
+    pub fn from_config() -> Self {
+        HealthRetry {
+            attempts: config::meilisearch_health_attempts(),
+            interval: Duration::from_millis(config::meilisearch_health_interval_ms()),
+            max_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Runs `check` until it succeeds or `retry.attempts` have failed, backing off between tries.
+/// Returns the last error if it never succeeds.
+pub async fn wait_until_healthy<F, Fut, T, E>(mut check: F, retry: &HealthRetry) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, E>>,
+    E: std::fmt::Display,
+{
+    let mut delay = retry.interval;
+    let mut attempt = 1;
+    loop {
+        match check().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt >= retry.attempts => return Err(e),
+            Err(e) => {
+                warn!("MeiliSearch not ready (attempt {}/{}): {}; retrying in {:?}", attempt, retry.attempts, e, delay);
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(retry.max_interval);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Score added for emails from a sender the user asked to prioritize
+const PRIORITY_SENDER_BOOST: f64 = 25.0;
+
+/// Whether the `from` header belongs to one of `priority_senders`, given as addresses or names.
+pub fn is_priority_sender(from: &str, priority_senders: &[String]) -> bool {
+    if priority_senders.is_empty() {
+        return false;
+    }
+    let address = extract_address(from);
+    let name = sender_name(from).to_lowercase();
+    priority_senders.iter().any(|priority| {
+        let priority = priority.trim().to_lowercase();
+        if priority.contains('@') {
+            address == priority
+        } else {
+            name == priority || name.split_whitespace().any(|part| part == priority)
+        }
+    })
+}
+
+/// Scores how well any recipient in a To header matches `name`, using the same tiers as the
+/// sender search: full display name, then a whole name part, the address's local part, a
+/// name prefix, and finally any substring. Zero means no match.
+fn recipient_match_score(to_header: &str, name: &str) -> f64 {
+    let name = name.trim().to_lowercase();
+    split_address_list(to_header).iter()
+        .map(|entry| {
+            let display = sender_name(entry).to_lowercase();
+            let address = extract_address(entry);
+            let local_part = address.split('@').next().unwrap_or("");
+            let parts: Vec<&str> = display.split_whitespace().collect();
+
+            if display == name {
+                50.0
+            } else if parts.iter().any(|part| *part == name) {
+                20.0
+            } else if local_part == name {
+                15.0
+            } else if parts.iter().any(|part| part.starts_with(&name)) {
+                10.0
+            } else if display.contains(&name) || address.contains(&name) {
+                1.0
+            } else {
+                0.0
+            }
+        })
+        .fold(0.0, f64::max)
+}
+
+/// Whether `email` satisfies every constraint in `criteria`. Addresses must match exactly,
+/// names may match any part of the display name or address, and the subject and each keyword
+/// must appear in the email (a plural keyword also matches its singular). Strict enough to
+/// pick the emails a bulk operation should touch.
+pub fn email_matches_criteria(email: &Email, criteria: &QueryCriteria) -> bool {
+    let sender_matches = criteria.from.as_deref().is_none_or(|wanted| {
+        email.from.as_deref().is_some_and(|from| participant_matches(from, wanted))
+    });
+    let recipient_matches = criteria.to.as_deref().is_none_or(|wanted| {
+        email.to.as_deref().is_some_and(|to| split_address_list(to).iter().any(|entry| participant_matches(entry, wanted)))
+    });
+    let subject = normalize_subject(email.subject.as_deref().unwrap_or("")).to_lowercase();
+    let subject_matches = criteria.subject.as_deref().is_none_or(|wanted| subject.contains(&normalize_subject(wanted).to_lowercase()));
+    let text = format!("{} {}", subject, email.body.as_deref().unwrap_or("").to_lowercase());
+    let keywords_match = criteria.keywords.iter().all(|keyword| mentions_keyword(&text, &keyword.to_lowercase()));
+
+    sender_matches
+        && recipient_matches
+        && subject_matches
+        && keywords_match
+        && (criteria.language.is_none() || email.language == criteria.language)
+        && email_in_date_range(email, criteria.date_from, criteria.date_to)
+        && attachments_match(email, criteria)
+        && phrases_match(email, criteria)
+}
+
+/// The `limit` emails with the highest importance score, highest first; unscored emails count as 0.
+pub fn most_important(mut emails: Vec<Email>, limit: usize) -> Vec<Email> {
+    emails.sort_by(|a, b| b.importance.unwrap_or(0.0).total_cmp(&a.importance.unwrap_or(0.0)));
+    emails.truncate(limit);
+    emails
+}
+
+/// Orders `emails` by how well they match `terms`, each match scoring its field's weight, so a
+/// subject match can outrank a body-only one. Ties keep their existing (relevance) order.
+pub fn rank_by_field_weights(emails: Vec<Email>, terms: &[String], weights: &FieldWeights) -> Vec<Email> {
+    let score = |email: &Email| -> f32 {
+        let subject = email.subject.as_deref().unwrap_or("").to_lowercase();
+        let from = email.from.as_deref().unwrap_or("").to_lowercase();
+        let body = email.body.as_deref().unwrap_or("").to_lowercase();
+        terms.iter()
+            .map(|term| term.to_lowercase())
+            .map(|term| {
+                let field = |text: &str, weight: f32| if mentions_keyword(text, &term) { weight } else { 0.0 };
+                field(&subject, weights.subject) + field(&from, weights.from) + field(&body, weights.body)
+            })
+            .sum()
+    };
+    let mut scored: Vec<(f32, Email)> = emails.into_iter().map(|email| (score(&email), email)).collect();
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    scored.into_iter().map(|(_, email)| email).collect()
+}
+
+fn participant_matches(header_entry: &str, wanted: &str) -> bool {
+    let wanted = wanted.trim().to_lowercase();
+    let address = extract_address(header_entry);
+    if wanted.contains('@') {
+        address == wanted
+    } else {
+        sender_name(header_entry).to_lowercase().contains(&wanted) || address.contains(&wanted)
+    }
+}
+
+fn mentions_keyword(text: &str, keyword: &str) -> bool {
+    text.contains(keyword)
+        || keyword.strip_suffix('s').is_some_and(|singular| singular.len() >= 3 && text.contains(singular))
+}
+
+/// Whether `email` satisfies the attachment presence and type asked for in `criteria`.
+fn attachments_match(email: &Email, criteria: &QueryCriteria) -> bool {
+    criteria.has_attachment.is_none_or(|wanted| (email.attachment_count > 0) == wanted)
+        && criteria.attachment_type.as_ref().is_none_or(|wanted| email.attachment_types.contains(wanted))
+}
+
+/// Whether every quoted phrase in `criteria` appears in the email's subject or body as that exact
+/// sequence of whole words, ignoring case and how the words are spaced or wrapped.
+fn phrases_match(email: &Email, criteria: &QueryCriteria) -> bool {
+    let text = format!("{}\n{}", email.subject.as_deref().unwrap_or(""), email.body.as_deref().unwrap_or(""));
+    criteria.phrases.iter().all(|phrase| {
+        let words: Vec<String> = phrase.split_whitespace().map(regex::escape).collect();
+        let pattern = format!(r"(?i)\b{}\b", words.join(r"\s+"));
+        Regex::new(&pattern).map(|re| re.is_match(&text)).unwrap_or(false)
+    })
+}
+
+/// Whether `email` was sent within `from..=to`. With no bounds every email matches; with
+/// bounds, emails whose date can't be parsed are excluded.
+fn email_in_date_range(email: &Email, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> bool {
+    if from.is_none() && to.is_none() {
+        return true;
+    }
+    match email.date.as_deref().and_then(parse_email_date) {
+        Some(date) => from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to),
+        None => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -579,10 +1101,22 @@ mod tests {
         pub EmailDB {
             async fn store_email(&self, email: &Email) -> Result<(), EmailDBError>;
             async fn delete_email(&self, message_id: &str) -> Result<(), EmailDBError>;
+            async fn get_email(&self, message_id: &str) -> Result<Option<Email>, EmailDBError>;
             async fn search_emails(&self, query: &str) -> Result<Vec<Email>, EmailDBError>;
             async fn store_emails(&self, emails: &[Email]) -> Result<(), EmailDBError>;
             async fn get_all_emails(&self) -> Result<Vec<Email>, EmailDBError>;
+            async fn count_emails(&self) -> Result<usize, EmailDBError>;
+            async fn get_emails_page(&self, offset: usize, limit: usize) -> Result<Vec<Email>, EmailDBError>;
+            async fn get_most_important_emails(&self, limit: usize) -> Result<Vec<Email>, EmailDBError>;
+            async fn find_similar(&self, reference: &Email, top_n: usize) -> Result<Vec<Email>, EmailDBError>;
+            async fn export_mbox(&self) -> Result<String, EmailDBError>;
+            async fn reconfigure(&self) -> Result<(), EmailDBError>;
             async fn search_emails_by_criteria(&self, criteria: QueryCriteria) -> Result<Vec<Email>, EmailDBError>;
+            async fn search_with_relaxation(&self, criteria: QueryCriteria) -> Result<(Vec<Email>, Vec<Relaxation>), EmailDBError>;
+            async fn find_by_criteria(&self, criteria: &QueryCriteria) -> Result<Vec<Email>, EmailDBError>;
+            async fn count_by_criteria(&self, criteria: &QueryCriteria) -> Result<usize, EmailDBError>;
+            async fn get_thread(&self, anchor: &Email) -> Result<Vec<Email>, EmailDBError>;
+            async fn delete_by_criteria(&self, criteria: QueryCriteria) -> Result<usize, EmailDBError>;
             async fn clear(&self) -> Result<(), EmailDBError>;
         }
     }
@@ -596,6 +1130,7 @@ mod tests {
             subject: Some(subject.to_string()),
             body: Some("This is a test email.".to_string()),
             message_id: Some(message_id.to_string()),
+            ..Default::default()
         }
     }
 
@@ -662,6 +1197,216 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_get_email() -> Result<(), Box<dyn std::error::Error>> {
+        let mut mock_db = create_mock_db();
+        let email = test_email("test-6", "Single Email Fetch");
+
+        // A stored id returns the full email
+        let stored_email = email.clone();
+        mock_db
+            .expect_get_email()
+            .with(eq("test-6"))
+            .returning(move |_| Ok(Some(stored_email.clone())));
+
+        // An unknown id returns None rather than an error
+        mock_db
+            .expect_get_email()
+            .with(eq("missing-id"))
+            .returning(|_| Ok(None));
+
+        assert_eq!(mock_db.get_email("test-6").await?, Some(email));
+        assert_eq!(mock_db.get_email("missing-id").await?, None);
+
+        Ok(())
+    }
+
+    fn quick_retry(attempts: u32) -> HealthRetry {
+        HealthRetry {
+            attempts,
+            interval: Duration::from_millis(1),
+            max_interval: Duration::from_millis(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_healthy_waits_for_delayed_ready() {
+        // Fails twice, as if MeiliSearch were still starting, then reports healthy
+        let calls = std::cell::Cell::new(0);
+        let check = || {
+            calls.set(calls.get() + 1);
+            let ready = calls.get() > 2;
+            async move { if ready { Ok("available") } else { Err("connection refused") } }
+        };
+
+        assert_eq!(wait_until_healthy(check, &quick_retry(5)).await, Ok("available"));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_healthy_gives_up_after_attempts() {
+        let calls = std::cell::Cell::new(0);
+        let check = || {
+            calls.set(calls.get() + 1);
+            async { Err::<(), _>("connection refused") }
+        };
+
+        assert_eq!(wait_until_healthy(check, &quick_retry(3)).await, Err("connection refused"));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn test_is_priority_sender() {
+        let priority = vec!["boss@corp.com".to_string(), "Dana".to_string()];
+        assert!(is_priority_sender("The Boss <Boss@Corp.com>", &priority));
+        assert!(is_priority_sender("Dana Weber <dana@example.com>", &priority));
+        assert!(!is_priority_sender("boss@other.com", &priority));
+        assert!(!is_priority_sender("Bob <bob@example.com>", &[]));
+    }
+
+    #[test]
+    fn test_recipient_match_score() {
+        let to = "\"Bob Jones\" <bob.jones@example.com>, carol@example.com";
+        assert_eq!(recipient_match_score(to, "Bob Jones"), 50.0);
+        assert_eq!(recipient_match_score(to, "bob"), 20.0);
+        assert_eq!(recipient_match_score(to, "carol"), 15.0);
+        assert_eq!(recipient_match_score(to, "Jo"), 10.0);
+        assert_eq!(recipient_match_score(to, "example"), 1.0);
+        assert_eq!(recipient_match_score(to, "dave"), 0.0);
+    }
+
+    #[test]
+    fn test_most_important() {
+        let email = |id: &str, importance: Option<f32>| Email { message_id: Some(id.to_string()), importance, ..Default::default() };
+        let emails = vec![email("low", Some(0.1)), email("unscored", None), email("high", Some(0.9)), email("mid", Some(0.5))];
+
+        let ids: Vec<_> = most_important(emails, 3).into_iter().filter_map(|e| e.message_id).collect();
+        assert_eq!(ids, vec!["high", "mid", "low"]);
+    }
+
+    #[test]
+    fn test_email_in_date_range() {
+        let email = |date: Option<&str>| Email { date: date.map(String::from), ..Default::default() };
+        let from = parse_email_date("2025-05-06T00:00:00Z");
+        let to = parse_email_date("2025-05-06T23:59:59Z");
+
+        assert!(email_in_date_range(&email(Some("Tue, 06 May 2025 09:15:00 +0000")), from, to));
+        assert!(!email_in_date_range(&email(Some("Tue, 13 May 2025 09:15:00 +0000")), from, to));
+        assert!(!email_in_date_range(&email(None), from, to));
+        assert!(email_in_date_range(&email(None), None, None));
+    }
+
+    #[test]
+    fn test_email_matches_criteria_selects_exact_subset() {
+        let email = |from: &str, subject: &str| Email {
+            from: Some(from.to_string()),
+            subject: Some(subject.to_string()),
+            body: Some("Read online".to_string()),
+            ..Default::default()
+        };
+        let mut criteria = QueryCriteria::new("delete all newsletters from marketing@x.com");
+        criteria.from = Some("marketing@x.com".to_string());
+        criteria.keywords = vec!["newsletters".to_string()];
+
+        assert!(email_matches_criteria(&email("Marketing <marketing@x.com>", "Weekly newsletter"), &criteria));
+        assert!(!email_matches_criteria(&email("Marketing <marketing@x.com>", "Your invoice"), &criteria));
+        assert!(!email_matches_criteria(&email("sales@x.com", "Weekly newsletter"), &criteria));
+        assert!(!email_matches_criteria(&email("Other <news-marketing@x.com>", "Weekly newsletter"), &criteria));
+
+        // Names match any part of the sender
+        criteria.from = Some("marketing".to_string());
+        assert!(email_matches_criteria(&email("news-marketing@x.com", "Weekly newsletter"), &criteria));
+
+        // Subjects are compared without reply and forward prefixes
+        criteria.subject = Some("Fwd: weekly newsletter".to_string());
+        assert!(email_matches_criteria(&email("marketing@x.com", "Re: Weekly Newsletter"), &criteria));
+    }
+
+    #[test]
+    fn test_subject_match_outranks_body_only_match() {
+        let email = |id: &str, subject: &str, body: &str| Email {
+            message_id: Some(id.to_string()),
+            from: Some("billing@example.com".to_string()),
+            subject: Some(subject.to_string()),
+            body: Some(body.to_string()),
+            ..Default::default()
+        };
+        // As MeiliSearch might return them: the body-only match first
+        let results = vec![
+            email("body", "Monthly update", "The invoice for March is attached."),
+            email("subject", "Invoice #4521", "Please find the details attached."),
+            email("neither", "Lunch", "See you at noon."),
+        ];
+        let criteria = QueryCriteria::builder()
+            .keywords(["invoice"])
+            .field_weights(FieldWeights::default())
+            .build();
+        let terms = criteria.keywords.clone();
+
+        let ranked: Vec<String> = rank_by_field_weights(results.clone(), &terms, criteria.field_weights.as_ref().unwrap())
+            .into_iter().filter_map(|email| email.message_id).collect();
+        assert_eq!(ranked, vec!["subject", "body", "neither"]);
+
+        // Weighting the body above the subject flips them
+        let body_first = FieldWeights { subject: 1.0, from: 1.0, body: 2.0 };
+        let ranked: Vec<String> = rank_by_field_weights(results, &terms, &body_first)
+            .into_iter().filter_map(|email| email.message_id).collect();
+        assert_eq!(ranked, vec!["body", "subject", "neither"]);
+    }
+
+    #[test]
+    fn test_quoted_phrase_matches_only_the_exact_sequence() {
+        let email = |body: &str| Email {
+            subject: Some("Account query".to_string()),
+            body: Some(body.to_string()),
+            ..Default::default()
+        };
+        let criteria = QueryCriteria::new("\"double billing\"");
+        assert_eq!(criteria.phrases, vec!["double billing"]);
+
+        assert!(email_matches_criteria(&email("We found some Double\nbilling on your account."), &criteria));
+        assert!(!email_matches_criteria(&email("Billing was double what we agreed."), &criteria));
+        assert!(!email_matches_criteria(&email("The double billings were refunded."), &criteria));
+        assert!(!email_matches_criteria(&email("Double check the billing address."), &criteria));
+    }
+
+    #[test]
+    fn test_document_not_found_converts_to_not_found() {
+        let meili_error = |code: &str| meilisearch_sdk::errors::Error::Meilisearch(serde_json::from_value(serde_json::json!({
+            "message": "Document `msg-1` not found.",
+            "code": code,
+            "type": "invalid_request",
+            "link": "https://docs.meilisearch.com/errors#document_not_found",
+        })).unwrap());
+
+        assert!(matches!(
+            EmailDBError::from(meili_error("document_not_found")),
+            EmailDBError::NotFound(message) if message == "Document `msg-1` not found."
+        ));
+        assert!(matches!(EmailDBError::from(meili_error("invalid_search_q")), EmailDBError::OperationError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_count_emails() -> Result<(), Box<dyn std::error::Error>> {
+        let mut mock_db = create_mock_db();
+        mock_db
+            .expect_count_emails()
+            .times(1)
+            .returning(|| Ok(0));
+
+        // An empty mailbox reports zero rather than an error
+        assert_eq!(mock_db.count_emails().await?, 0);
+
+        // Paging past the end is an empty page, not an error
+        mock_db
+            .expect_get_emails_page()
+            .with(eq(100), eq(50))
+            .returning(|_, _| Ok(vec![]));
+        assert!(mock_db.get_emails_page(100, 50).await?.is_empty());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_store_emails() -> Result<(), Box<dyn std::error::Error>> {
         // Create mock DB and setup expectations
@@ -758,16 +1503,13 @@ mod tests {
         email_modified.from = Some("charlie@example.com".to_string());
         
         // Create criteria for search
-        let criteria = QueryCriteria {
-            keywords: vec!["Advanced".to_string()],
-            from: Some("charlie@example.com".to_string()),
-            to: None,
-            subject: Some("Advanced Search Test".to_string()),
-            date_from: None,
-            date_to: None,
-            raw_query: "Perform an Advanced Search".to_string(),
-            llm_confidence: 1.0,
-        };
+        let criteria = QueryCriteria::builder()
+            .keywords(["Advanced"])
+            .from("charlie@example.com")
+            .subject("Advanced Search Test")
+            .raw_query("Perform an Advanced Search")
+            .llm_confidence(1.0)
+            .build();
         
         // Set up expectations for store_email
         mock_db
@@ -824,6 +1566,7 @@ mod tests {
             date: Some("2025-03-04T12:00:00Z".to_string()),
             subject: Some("Email from Bob".to_string()),
             body: Some("Test email content.".to_string()),
+            ..Default::default()
         };
 
         let email2 = Email {
@@ -833,6 +1576,7 @@ mod tests {
             date: Some("2025-03-04T12:05:00Z".to_string()),
             subject: Some("Another email from Bob".to_string()),
             body: Some("Another test email.".to_string()),
+            ..Default::default()
         };
 
         let email3 = Email {
@@ -842,6 +1586,7 @@ mod tests {
             date: Some("2025-03-04T12:10:00Z".to_string()),
             subject: Some("Email from Alice".to_string()),
             body: Some("Control email content.".to_string()),
+            ..Default::default()
         };
         
         // Create a collection of all emails for the store_emails call
@@ -882,16 +1627,9 @@ mod tests {
         mock_db.store_emails(&all_emails).await?;
         
         // Create search criteria with from = "Bob"
-        let criteria = QueryCriteria {
-            keywords: vec![],
-            from: Some("Bob".to_string()),
-            to: None,
-            subject: None,
-            date_from: None,
-            date_to: None,
-            raw_query: "".to_string(),
-            llm_confidence: 0.0,
-        };
+        let criteria = QueryCriteria::builder()
+            .from("Bob")
+            .build();
         
         // Execute search
         let results = mock_db.search_emails_by_criteria(criteria).await?;
@@ -3,9 +3,15 @@ use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashSet};
 use crate::services::chat_service::Intent;
+use crate::models::email::language_in_query;
+use crate::config;
+use chrono_tz::Tz;
+
+/// Below this `llm_confidence`, criteria without structured filters are treated as weak
+pub const WEAK_CONFIDENCE: f32 = 0.5;
 
 // Cache to avoid repeated identical LLM calls
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct QueryCriteria {
     pub keywords: Vec<String>,
     pub from: Option<String>,
@@ -13,6 +19,26 @@ pub struct QueryCriteria {
     pub subject: Option<String>,
     pub date_from: Option<DateTime<Utc>>,
     pub date_to: Option<DateTime<Utc>>,
+    /// ISO 639-1 code to restrict results to, e.g. "en" for "show my English emails"
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Senders the user asked to prioritize; their emails get a ranking boost
+    #[serde(default)]
+    pub priority_senders: Vec<String>,
+    /// Whether matches must (or must not) have attachments, e.g. "emails with attachments"
+    #[serde(default)]
+    pub has_attachment: Option<bool>,
+    /// File extension attachments must include, e.g. "pdf" for "which emails have PDFs"
+    #[serde(default)]
+    pub attachment_type: Option<String>,
+    /// Phrases the user quoted, which must appear as that exact word sequence, e.g.
+    /// "double billing" for `emails about "double billing"`
+    #[serde(default)]
+    pub phrases: Vec<String>,
+    /// Re-ranks results so a keyword match in one field counts more than in another, e.g. a
+    /// subject match over a body-only match. None keeps MeiliSearch's ranking.
+    #[serde(default)]
+    pub field_weights: Option<FieldWeights>,
     pub raw_query: String,
     pub llm_confidence: f32,  // 0.0 to 1.0 indicating LLM's confidence in query understanding
 }
@@ -26,9 +52,17 @@ impl QueryCriteria {
             subject: None,
             date_from: None,
             date_to: None,
+            language: language_in_query(raw_query),
+            priority_senders: Vec::new(),
+            has_attachment: None,
+            attachment_type: attachment_type_in_query(raw_query),
+            phrases: quoted_phrases(raw_query),
+            field_weights: None,
             raw_query: raw_query.to_string(),
             llm_confidence: 0.0,
         };
+        criteria.has_attachment = attachment_wanted_in_query(raw_query)
+            .or(criteria.attachment_type.as_ref().map(|_| true));
         
         // Simple extract of sender name in patterns like "from <name>" or "<name>'s email"
         let query_lower = raw_query.to_lowercase();
@@ -62,16 +96,225 @@ impl QueryCriteria {
             }
         }
         
-        // Extract keywords after removing common words
+        // Extract keywords after removing common words; quoted phrases are matched whole instead
         let common_words = ["the", "a", "an", "from", "to", "about", "email", "explain", "please"];
-        let words: Vec<&str> = query_lower.split_whitespace()
+        let unquoted = quoted_phrase_regex().replace_all(&query_lower, " ");
+        let words: Vec<&str> = unquoted.split_whitespace()
             .filter(|word| word.len() > 2 && !common_words.contains(word))
             .collect();
         
         criteria.keywords = words.iter().map(|&s| s.to_string()).collect();
+        // Attachment words are a filter, not text to look for in the email
+        if criteria.has_attachment.is_some() {
+            criteria.keywords.retain(|word| !is_attachment_word(word));
+        }
         
         criteria
     }
+
+    /// Whether these criteria barely narrow the search: the LLM wasn't confident about the query
+    /// (below `WEAK_CONFIDENCE`) and there's no sender, recipient, subject, date, language,
+    /// attachment or quoted phrase filter, only keywords. Such searches match lots of loosely related emails.
+    pub fn is_weak(&self) -> bool {
+        self.llm_confidence < WEAK_CONFIDENCE
+            && self.from.is_none()
+            && self.to.is_none()
+            && self.subject.is_none()
+            && self.date_from.is_none()
+            && self.date_to.is_none()
+            && self.language.is_none()
+            && self.has_attachment.is_none()
+            && self.attachment_type.is_none()
+            && self.phrases.is_empty()
+    }
+
+    /// Keeps only the first `cap` of `matches` (which are ranked best first) when the criteria
+    /// are weak, so a vague query doesn't put dozens of emails into the LLM's context.
+    pub fn cap_weak_matches<T>(&self, mut matches: Vec<T>, cap: usize) -> Vec<T> {
+        if self.is_weak() {
+            matches.truncate(cap);
+        }
+        matches
+    }
+
+    /// The broader criteria to retry with, in order, when these find nothing: without the date
+    /// range, then also without the subject, then with only the keywords. Steps that wouldn't
+    /// change the search are skipped.
+    pub fn relaxations(&self) -> Vec<(Relaxation, QueryCriteria)> {
+        let mut steps = Vec::new();
+        let mut broader = self.clone();
+        if broader.date_from.is_some() || broader.date_to.is_some() {
+            broader.date_from = None;
+            broader.date_to = None;
+            steps.push((Relaxation::DateRange, broader.clone()));
+        }
+        if broader.subject.is_some() {
+            broader.subject = None;
+            steps.push((Relaxation::Subject, broader.clone()));
+        }
+        let has_other_filters = broader.from.is_some()
+            || broader.to.is_some()
+            || broader.language.is_some()
+            || broader.has_attachment.is_some()
+            || broader.attachment_type.is_some()
+            || !broader.phrases.is_empty();
+        // Keywords alone would match everything if there are none
+        if has_other_filters && !broader.keywords.is_empty() {
+            broader.from = None;
+            broader.to = None;
+            broader.language = None;
+            broader.has_attachment = None;
+            broader.attachment_type = None;
+            broader.phrases.clear();
+            steps.push((Relaxation::KeywordsOnly, broader));
+        }
+        steps
+    }
+
+    /// Starts building criteria field by field; anything not set is left empty.
+    #[cfg_attr(not(test), allow(dead_code))] // the server binary only parses queries
+    pub fn builder() -> QueryCriteriaBuilder {
+        QueryCriteriaBuilder::default()
+    }
+}
+
+/// A constraint dropped to broaden a search that found nothing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Relaxation {
+    DateRange,
+    Subject,
+    /// Everything but the keywords: sender, recipient, language, attachments and quoted phrases
+    KeywordsOnly,
+}
+
+impl Relaxation {
+    /// What was dropped, for telling the user, e.g. "the date range"
+    pub fn description(&self) -> &'static str {
+        match self {
+            Relaxation::DateRange => "the date range",
+            Relaxation::Subject => "the subject",
+            Relaxation::KeywordsOnly => "every filter except the keywords",
+        }
+    }
+}
+
+/// How much a keyword match in each field is worth when re-ranking search results.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FieldWeights {
+    pub subject: f32,
+    pub from: f32,
+    pub body: f32,
+}
+
+impl Default for FieldWeights {
+    /// Subject matches count three times and sender matches twice as much as body matches.
+    fn default() -> Self {
+        FieldWeights { subject: 3.0, from: 2.0, body: 1.0 }
+    }
+}
+
+/// Fluent construction of a `QueryCriteria`, e.g.
+/// `QueryCriteria::builder().from("Bob").keywords(["report"]).build()`.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub struct QueryCriteriaBuilder {
+    criteria: QueryCriteria,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl QueryCriteriaBuilder {
+    pub fn keywords<I, S>(mut self, keywords: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.criteria.keywords = keywords.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn from(mut self, from: impl Into<String>) -> Self {
+        self.criteria.from = Some(from.into());
+        self
+    }
+
+    pub fn to(mut self, to: impl Into<String>) -> Self {
+        self.criteria.to = Some(to.into());
+        self
+    }
+
+    pub fn subject(mut self, subject: impl Into<String>) -> Self {
+        self.criteria.subject = Some(subject.into());
+        self
+    }
+
+    /// Restricts matches to emails sent between `from` and `to`, inclusive
+    pub fn date_range(mut self, from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        self.criteria.date_from = Some(from);
+        self.criteria.date_to = Some(to);
+        self
+    }
+
+    pub fn date_from(mut self, from: DateTime<Utc>) -> Self {
+        self.criteria.date_from = Some(from);
+        self
+    }
+
+    pub fn date_to(mut self, to: DateTime<Utc>) -> Self {
+        self.criteria.date_to = Some(to);
+        self
+    }
+
+    pub fn language(mut self, language: impl Into<String>) -> Self {
+        self.criteria.language = Some(language.into());
+        self
+    }
+
+    pub fn priority_senders<I, S>(mut self, senders: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.criteria.priority_senders = senders.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn has_attachment(mut self, wanted: bool) -> Self {
+        self.criteria.has_attachment = Some(wanted);
+        self
+    }
+
+    pub fn attachment_type(mut self, extension: impl Into<String>) -> Self {
+        self.criteria.attachment_type = Some(extension.into());
+        self
+    }
+
+    pub fn phrases<I, S>(mut self, phrases: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.criteria.phrases = phrases.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn field_weights(mut self, weights: FieldWeights) -> Self {
+        self.criteria.field_weights = Some(weights);
+        self
+    }
+
+    pub fn raw_query(mut self, raw_query: impl Into<String>) -> Self {
+        self.criteria.raw_query = raw_query.into();
+        self
+    }
+
+    pub fn llm_confidence(mut self, confidence: f32) -> Self {
+        self.criteria.llm_confidence = confidence;
+        self
+    }
+
+    pub fn build(self) -> QueryCriteria {
+        self.criteria
+    }
 }
 
 fn refine_query_with_intent(query: &str, analysis: QueryCriteria, intent: Intent) -> QueryCriteria {
@@ -147,6 +390,12 @@ fn refine_query_with_intent(query: &str, analysis: QueryCriteria, intent: Intent
             // For display intents, prioritize finding the specific email similar to Explain
             // No special handling needed beyond the base criteria
         },
+        Intent::Delete => {
+            // For delete, the sender is the most common way of identifying the email
+            if llm_criteria.from.is_none() {
+                llm_criteria.from = extract_pattern(query, r"(?i)(?:from|form|by)\s+([A-Za-z0-9@._-]+)");
+            }
+        },
         Intent::General => {
             // For general search, be more flexible with extractions
             if llm_criteria.from.is_none() && llm_criteria.to.is_none() {
@@ -206,30 +455,15 @@ fn parse_date_string(date_str: &str) -> Option<DateTime<Utc>> {
     }
 
     // Handle relative dates like "yesterday", "last week", etc.
-    let today = Utc::now();
-
-    match date_str.to_lowercase().as_str() {
-        "today" => {
-            let start = today.date_naive().and_hms_opt(0, 0, 0).unwrap();
-            return Some(DateTime::from_naive_utc_and_offset(start, Utc));
-        },
-        "yesterday" => {
-            let yesterday = today - Duration::days(1);
-            let start = yesterday.date_naive().and_hms_opt(0, 0, 0).unwrap();
-            return Some(DateTime::from_naive_utc_and_offset(start, Utc));
-        },
-        "last week" => {
-            let last_week = today - Duration::days(7);
-            let start = last_week.date_naive().and_hms_opt(0, 0, 0).unwrap();
-            return Some(DateTime::from_naive_utc_and_offset(start, Utc));
-        },
-        "last month" => {
-            let last_month = today - Duration::days(30);
-            let start = last_month.date_naive().and_hms_opt(0, 0, 0).unwrap();
-            return Some(DateTime::from_naive_utc_and_offset(start, Utc));
-        },
-        _ => None,
-    }
+    let now = Utc::now().with_timezone(&config::default_tz());
+    let days_back = match date_str.to_lowercase().as_str() {
+        "today" => 0,
+        "yesterday" => 1,
+        "last week" => 7,
+        "last month" => 30,
+        _ => return None,
+    };
+    Some(start_of_day(now.date_naive() - Duration::days(days_back), &now.timezone()))
 }
 
 fn extract_pattern(text: &str, pattern: &str) -> Option<String> {
@@ -237,78 +471,189 @@ fn extract_pattern(text: &str, pattern: &str) -> Option<String> {
     re.captures(text).map(|caps| caps[1].trim().to_string())
 }
 
-fn process_date_queries(query: &str, criteria: &mut QueryCriteria) {
-    let today = Utc::now();
+/// Resolves date phrases in `query` ("today", "last week", "after 2025-01-01", ...) to a
+/// `(from, to)` range; either end is `None` when unbounded.
+pub fn date_range_for_query(query: &str) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    date_range_for_query_at(query, Utc::now().with_timezone(&config::default_tz()))
+}
+
+/// `date_range_for_query` as of `now`, with days taken in `now`'s time zone.
+pub fn date_range_for_query_at(query: &str, now: DateTime<Tz>) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    let mut criteria = QueryCriteria::new(query);
+    process_date_queries_at(&query.to_lowercase(), &mut criteria, now);
+    (criteria.date_from, criteria.date_to)
+}
+
+// Day boundaries are taken in `now`'s time zone and converted to UTC, so "today" means the
+// user's today rather than UTC's
+fn process_date_queries_at(query: &str, criteria: &mut QueryCriteria, now: DateTime<Tz>) {
+    let tz = now.timezone();
+    let today = now.date_naive();
 
     // Check for specific date patterns
     if let Some(date_str) = extract_pattern(query, r"(?:on|date:?)\s+(\d{4}-\d{2}-\d{2})") {
         if let Ok(date) = NaiveDate::parse_from_str(&date_str, "%Y-%m-%d") {
-            let start_of_day = date.and_hms_opt(0, 0, 0).unwrap();
-            let end_of_day = date.and_hms_opt(23, 59, 59).unwrap();
-
-            criteria.date_from = Some(Utc.from_utc_datetime(&start_of_day));
-            criteria.date_to = Some(Utc.from_utc_datetime(&end_of_day));
+            criteria.date_from = Some(start_of_day(date, &tz));
+            criteria.date_to = Some(end_of_day(date, &tz));
         }
     }
 
     // Check for relative date terms
     if query.contains("today") {
-        let start_of_today = today.date_naive().and_hms_opt(0, 0, 0).unwrap();
-        criteria.date_from = Some(Utc.from_utc_datetime(&start_of_today));
+        criteria.date_from = Some(start_of_day(today, &tz));
     } else if query.contains("yesterday") {
         let yesterday = today - Duration::days(1);
-        let start_of_yesterday = yesterday.date_naive().and_hms_opt(0, 0, 0).unwrap();
-        let end_of_yesterday = yesterday.date_naive().and_hms_opt(23, 59, 59).unwrap();
 
-        criteria.date_from = Some(Utc.from_utc_datetime(&start_of_yesterday));
-        criteria.date_to = Some(Utc.from_utc_datetime(&end_of_yesterday));
+        criteria.date_from = Some(start_of_day(yesterday, &tz));
+        criteria.date_to = Some(end_of_day(yesterday, &tz));
     } else if query.contains("this week") {
         let days_since_monday = today.weekday().num_days_from_monday() as i64;
         let monday = today - Duration::days(days_since_monday);
-        let start_of_week = monday.date_naive().and_hms_opt(0, 0, 0).unwrap();
 
-        criteria.date_from = Some(Utc.from_utc_datetime(&start_of_week));
+        criteria.date_from = Some(start_of_day(monday, &tz));
     } else if query.contains("last week") {
         let days_since_monday = today.weekday().num_days_from_monday() as i64;
         let this_monday = today - Duration::days(days_since_monday);
         let last_monday = this_monday - Duration::days(7);
         let last_sunday = this_monday - Duration::days(1);
 
-        let start_of_last_week = last_monday.date_naive().and_hms_opt(0, 0, 0).unwrap();
-        let end_of_last_week = last_sunday.date_naive().and_hms_opt(23, 59, 59).unwrap();
-
-        criteria.date_from = Some(Utc.from_utc_datetime(&start_of_last_week));
-        criteria.date_to = Some(Utc.from_utc_datetime(&end_of_last_week));
+        criteria.date_from = Some(start_of_day(last_monday, &tz));
+        criteria.date_to = Some(end_of_day(last_sunday, &tz));
     } else if query.contains("this month") {
-        let start_of_month = NaiveDate::from_ymd_opt(today.year(), today.month(), 1).unwrap()
-            .and_hms_opt(0, 0, 0).unwrap();
+        let start_of_month = NaiveDate::from_ymd_opt(today.year(), today.month(), 1).unwrap();
 
-        criteria.date_from = Some(Utc.from_utc_datetime(&start_of_month));
+        criteria.date_from = Some(start_of_day(start_of_month, &tz));
     } else if let Some(days_str) = extract_pattern(query, r"last (\d+) days") {
         if let Ok(days) = days_str.parse::<i64>() {
-            let past_date = today - Duration::days(days);
-            let start_of_past_date = past_date.date_naive().and_hms_opt(0, 0, 0).unwrap();
-
-            criteria.date_from = Some(Utc.from_utc_datetime(&start_of_past_date));
+            criteria.date_from = Some(start_of_day(today - Duration::days(days), &tz));
         }
     } else {
         // Check for before/after date patterns
         if let Some(date_str) = extract_pattern(query, r"after:?\s+(\d{4}-\d{2}-\d{2})") {
             if let Ok(date) = NaiveDate::parse_from_str(&date_str, "%Y-%m-%d") {
-                let start_of_day = date.and_hms_opt(0, 0, 0).unwrap();
-                criteria.date_from = Some(Utc.from_utc_datetime(&start_of_day));
+                criteria.date_from = Some(start_of_day(date, &tz));
             }
         }
 
         if let Some(date_str) = extract_pattern(query, r"before:?\s+(\d{4}-\d{2}-\d{2})") {
             if let Ok(date) = NaiveDate::parse_from_str(&date_str, "%Y-%m-%d") {
-                let end_of_day = date.and_hms_opt(23, 59, 59).unwrap();
-                criteria.date_to = Some(Utc.from_utc_datetime(&end_of_day));
+                criteria.date_to = Some(end_of_day(date, &tz));
             }
         }
     }
 }
 
+/// Hour of the day, in `DEFAULT_TZ`, that a reminder goes off
+const REMINDER_HOUR: u32 = 9;
+
+/// Furthest ahead, in days, that "in N days/weeks" can set a reminder
+const MAX_REMINDER_DAYS: i64 = 3650;
+
+/// Resolves when a reminder asked for with `phrase` ("tomorrow", "next week", "in 3 days",
+/// "on 2025-06-02") goes off: 9am on that day in `DEFAULT_TZ`, as UTC.
+pub fn reminder_time_for_query(phrase: &str) -> Option<DateTime<Utc>> {
+    reminder_time_at(&phrase.to_lowercase(), Utc::now().with_timezone(&config::default_tz()))
+}
+
+fn reminder_time_at(phrase: &str, now: DateTime<Tz>) -> Option<DateTime<Utc>> {
+    let today = now.date_naive();
+    let day = if phrase.contains("tomorrow") {
+        today + Duration::days(1)
+    } else if phrase.contains("next week") {
+        // The Monday after this one
+        today + Duration::days(7 - today.weekday().num_days_from_monday() as i64)
+    } else if let Some(count) = extract_pattern(phrase, r"in (\d+) days?\b") {
+        let days = count.parse().ok().filter(|&days| days <= MAX_REMINDER_DAYS)?;
+        today.checked_add_signed(Duration::try_days(days)?)?
+    } else if let Some(count) = extract_pattern(phrase, r"in (\d+) weeks?\b") {
+        let weeks = count.parse().ok().filter(|&weeks| weeks <= MAX_REMINDER_DAYS / 7)?;
+        today.checked_add_signed(Duration::try_weeks(weeks)?)?
+    } else {
+        let date_str = extract_pattern(phrase, r"(\d{4}-\d{2}-\d{2})")?;
+        NaiveDate::parse_from_str(&date_str, "%Y-%m-%d").ok()?
+    };
+    Some(local_to_utc(day.and_hms_opt(REMINDER_HOUR, 0, 0).unwrap(), &now.timezone()))
+}
+
+fn start_of_day(date: NaiveDate, tz: &Tz) -> DateTime<Utc> {
+    local_to_utc(date.and_hms_opt(0, 0, 0).unwrap(), tz)
+}
+
+fn end_of_day(date: NaiveDate, tz: &Tz) -> DateTime<Utc> {
+    local_to_utc(date.and_hms_opt(23, 59, 59).unwrap(), tz)
+}
+
+// A local time skipped by a DST change (some zones move clocks at midnight) is taken an hour later
+fn local_to_utc(local: NaiveDateTime, tz: &Tz) -> DateTime<Utc> {
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+}
+
+// (word in a query, attachment file extension) pairs; plurals are matched too
+const ATTACHMENT_TYPES: &[(&str, &str)] = &[
+    ("pdf", "pdf"),
+    ("docx", "docx"),
+    ("doc", "doc"),
+    ("word doc", "docx"),
+    ("word document", "docx"),
+    ("spreadsheet", "xlsx"),
+    ("excel", "xlsx"),
+    ("xlsx", "xlsx"),
+    ("csv", "csv"),
+    ("powerpoint", "pptx"),
+    ("pptx", "pptx"),
+    ("zip", "zip"),
+    ("png", "png"),
+    ("jpg", "jpg"),
+    ("jpeg", "jpg"),
+];
+
+fn quoted_phrase_regex() -> Regex {
+    Regex::new(r#"["\u{201c}\u{201d}]([^"\u{201c}\u{201d}]+)["\u{201c}\u{201d}]"#).unwrap()
+}
+
+/// The phrases quoted with double quotes (straight or curly) in a query, in order.
+pub fn quoted_phrases(query: &str) -> Vec<String> {
+    quoted_phrase_regex().captures_iter(query)
+        .map(|caps| caps[1].split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|phrase| !phrase.is_empty())
+        .collect()
+}
+
+/// Whether the query asks for emails with (Some(true)) or without (Some(false)) attachments.
+pub fn attachment_wanted_in_query(query: &str) -> Option<bool> {
+    let without = Regex::new(r"(?i)\b(?:without|no|with no)\s+(?:any\s+)?attachments?\b").unwrap();
+    let with = Regex::new(r"(?i)\b(?:attachments?|attached)\b").unwrap();
+    if without.is_match(query) {
+        Some(false)
+    } else if with.is_match(query) {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// The attachment file type a query asks for, e.g. "pdf" for "which emails have PDFs".
+pub fn attachment_type_in_query(query: &str) -> Option<String> {
+    let query = query.to_lowercase();
+    ATTACHMENT_TYPES.iter()
+        .find(|(word, _)| {
+            let pattern = format!(r"\b{}s?\b", regex::escape(word));
+            Regex::new(&pattern).unwrap().is_match(&query)
+        })
+        .map(|(_, extension)| extension.to_string())
+}
+
+fn is_attachment_word(word: &str) -> bool {
+    let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+    let singular = word.strip_suffix('s').unwrap_or(word);
+    matches!(singular, "attachment" | "attached")
+        || ATTACHMENT_TYPES.iter().any(|(name, _)| *name == singular)
+}
+
 fn extract_keywords(text: &str) -> Vec<String> {
     let stop_words: HashSet<&str> = [
         "a", "about", "an", "are", "as", "at", "be", "by", "com", "for", "from", "how",
@@ -325,6 +670,79 @@ fn extract_keywords(text: &str) -> Vec<String> {
         .collect()
 }
 
+// Words asking for a sender's mail in general, without narrowing it down
+const GENERIC_QUERY_TERMS: &[&str] = &[
+    "email", "emails", "mail", "message", "messages", "explain", "please", "show", "read",
+    "open", "summarize", "summarise", "latest", "recent", "newest", "last", "send", "sent",
+];
+
+// Words that single out a particular email; each counts double against the generic ones
+const QUERY_QUALIFIER_TERMS: &[&str] = &[
+    "invoice", "invoices", "update", "updates", "updated", "meeting", "meetings", "subject",
+    "about", "regarding", "concerning", "titled", "called", "today", "yesterday", "week",
+    "month", "year", "ago", "monday", "tuesday", "wednesday", "thursday", "friday", "saturday",
+    "sunday", "january", "february", "march", "april", "june", "july", "august",
+    "september", "october", "november", "december",
+];
+
+// Words that carry no weight either way
+const NEUTRAL_QUERY_TERMS: &[&str] = &[
+    "a", "an", "the", "from", "to", "me", "my", "i", "you", "can", "could", "would", "what",
+    "did", "does", "do", "say", "said", "is", "was", "for", "of", "with", "in", "on", "by",
+    "and", "tell", "give", "get", "find", "one", "that", "this", "his", "her", "their", "most",
+    "us", "it", "any", "all", "has", "have",
+];
+
+const QUALIFIER_WEIGHT: f32 = 2.0;
+
+/// How `classify_sender_query` read a query about a sender.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SenderQueryClass {
+    /// True when the query just asks for the sender's mail ("explain the email from Kai"),
+    /// false when it singles out a particular email ("Kai's invoice from last week")
+    pub generic: bool,
+    /// The share of the query's weight on the winning side, from 0.5 (a toss-up) to 1.0
+    pub confidence: f32,
+}
+
+/// Scores a lowercased query about `sender_name` (also lowercased): generic words ("email",
+/// "explain", "latest") count against qualifiers ("invoice", "about", date terms, numbers),
+/// which weigh double, and any other word, taken to be a topic, which weighs single. The query
+/// is generic when the generic words outweigh the rest. A query that doesn't mention the
+/// sender is never generic.
+pub fn classify_sender_query(query: &str, sender_name: &str) -> SenderQueryClass {
+    if sender_name.is_empty() || !query.contains(sender_name) {
+        return SenderQueryClass { generic: false, confidence: 1.0 };
+    }
+
+    let name_words: Vec<&str> = sender_name.split_whitespace().collect();
+    let (mut generic, mut specific) = (0.0_f32, 0.0_f32);
+    for word in query.split_whitespace() {
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric() && c != '#');
+        let word = word.strip_suffix("'s").unwrap_or(word);
+        if word.is_empty() || name_words.contains(&word) || NEUTRAL_QUERY_TERMS.contains(&word) {
+            continue;
+        }
+        if GENERIC_QUERY_TERMS.contains(&word) {
+            generic += 1.0;
+        } else if QUERY_QUALIFIER_TERMS.contains(&word) || word.chars().any(|c| c.is_ascii_digit() || c == '#') {
+            specific += QUALIFIER_WEIGHT;
+        } else {
+            specific += 1.0;
+        }
+    }
+
+    let total = generic + specific;
+    if total == 0.0 {
+        return SenderQueryClass { generic: false, confidence: 0.5 };
+    }
+    let generic_share = generic / total;
+    SenderQueryClass {
+        generic: generic_share > 0.5,
+        confidence: generic_share.max(1.0 - generic_share),
+    }
+}
+
 // Attempts to fix JSON if it was cut off by LLM
 fn fix_json_if_needed(json: &str) -> String {
     let mut result = json.to_string();
@@ -365,19 +783,187 @@ mod tests {
     use super::*;
     use crate::services::chat_service::Intent;
 
+    #[test]
+    fn test_attachment_criteria_from_query() {
+        let criteria = QueryCriteria::new("show me emails with attachments");
+        assert_eq!(criteria.has_attachment, Some(true));
+        assert_eq!(criteria.attachment_type, None);
+        assert!(!criteria.keywords.contains(&"attachments".to_string()));
+
+        let criteria = QueryCriteria::new("which emails have PDFs?");
+        assert_eq!(criteria.has_attachment, Some(true));
+        assert_eq!(criteria.attachment_type.as_deref(), Some("pdf"));
+        assert!(!criteria.keywords.iter().any(|k| k.starts_with("pdf")));
+
+        let criteria = QueryCriteria::new("emails from Bob without attachments");
+        assert_eq!(criteria.has_attachment, Some(false));
+
+        let criteria = QueryCriteria::new("the budget email from Alice");
+        assert_eq!(criteria.has_attachment, None);
+        assert_eq!(criteria.attachment_type, None);
+    }
+
+    #[test]
+    fn test_relaxations_drop_date_then_subject_then_filters() {
+        let start = Utc.with_ymd_and_hms(2025, 5, 1, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2025, 5, 31, 23, 59, 59).unwrap();
+        let strict = QueryCriteria::builder()
+            .keywords(["budget"])
+            .from("Bob")
+            .subject("Q3 budget")
+            .date_range(start, end)
+            .build();
+
+        let steps = strict.relaxations();
+        let dropped: Vec<Relaxation> = steps.iter().map(|(relaxation, _)| *relaxation).collect();
+        assert_eq!(dropped, vec![Relaxation::DateRange, Relaxation::Subject, Relaxation::KeywordsOnly]);
+
+        let (_, no_dates) = &steps[0];
+        assert_eq!((no_dates.date_from, no_dates.date_to), (None, None));
+        assert_eq!(no_dates.subject.as_deref(), Some("Q3 budget"));
+        let (_, no_subject) = &steps[1];
+        assert_eq!((no_subject.subject.as_deref(), no_subject.from.as_deref()), (None, Some("Bob")));
+        let (_, keywords_only) = &steps[2];
+        assert_eq!(keywords_only.from, None);
+        assert_eq!(keywords_only.keywords, vec!["budget"]);
+
+        // Nothing to drop means nothing to retry
+        assert!(QueryCriteria::builder().keywords(["budget"]).build().relaxations().is_empty());
+        let dropped: Vec<Relaxation> = QueryCriteria::builder().from("Bob").build()
+            .relaxations().into_iter().map(|(relaxation, _)| relaxation).collect();
+        assert!(dropped.is_empty());
+    }
+
+    #[test]
+    fn test_builder_sets_given_fields_and_defaults_the_rest() {
+        let criteria = QueryCriteria::builder().from("Bob").build();
+        assert_eq!(criteria.from.as_deref(), Some("Bob"));
+        assert!(criteria.keywords.is_empty() && criteria.raw_query.is_empty());
+        assert_eq!((criteria.to, criteria.subject, criteria.date_from), (None, None, None));
+        assert_eq!(criteria.llm_confidence, 0.0);
+
+        let start = Utc.with_ymd_and_hms(2025, 5, 1, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2025, 5, 31, 23, 59, 59).unwrap();
+        let criteria = QueryCriteria::builder()
+            .keywords(["budget", "q3"])
+            .to("alice@example.com")
+            .subject("Budget")
+            .date_from(start)
+            .date_to(end)
+            .language("en")
+            .priority_senders(["boss@corp.com"])
+            .has_attachment(true)
+            .attachment_type("pdf")
+            .phrases(["q3 budget"])
+            .raw_query("the budget PDF I sent Alice in May")
+            .llm_confidence(0.8)
+            .build();
+        assert_eq!(criteria.keywords, vec!["budget", "q3"]);
+        assert_eq!((criteria.date_from, criteria.date_to), (Some(start), Some(end)));
+        assert_eq!(criteria.language.as_deref(), Some("en"));
+        assert_eq!(criteria.priority_senders, vec!["boss@corp.com"]);
+        assert_eq!((criteria.has_attachment, criteria.attachment_type.as_deref()), (Some(true), Some("pdf")));
+        assert_eq!(criteria.phrases, vec!["q3 budget"]);
+        assert_eq!(criteria.raw_query, "the budget PDF I sent Alice in May");
+
+        let ranged = QueryCriteria::builder().date_range(start, end).build();
+        assert_eq!((ranged.date_from, ranged.date_to), (Some(start), Some(end)));
+    }
+
+    #[test]
+    fn test_quoted_phrases_are_kept_whole() {
+        let criteria = QueryCriteria::new("find emails about \"double   billing\" and refunds");
+        assert_eq!(criteria.phrases, vec!["double billing"]);
+        assert_eq!(criteria.keywords, vec!["find", "emails", "and", "refunds"]);
+
+        assert_eq!(quoted_phrases("\u{201c}Q3 budget\u{201d} or \"offsite plan\""), vec!["Q3 budget", "offsite plan"]);
+        assert!(quoted_phrases("Kai's invoice").is_empty());
+        assert!(quoted_phrases("an \"unclosed quote").is_empty());
+    }
+
+    #[test]
+    fn test_vague_query_context_is_bounded() {
+        let vague = QueryCriteria::new("anything interesting happening with the project lately?");
+        assert!(vague.is_weak());
+        let matches: Vec<usize> = (0..40).collect();
+        assert_eq!(vague.cap_weak_matches(matches.clone(), 5), vec![0, 1, 2, 3, 4]);
+
+        // A sender or a confident LLM reading makes the criteria specific enough to keep everything
+        let from_bob = QueryCriteria::new("emails from bob about the project");
+        assert!(!from_bob.is_weak());
+        assert_eq!(from_bob.cap_weak_matches(matches.clone(), 5).len(), 40);
+
+        let confident = QueryCriteria::builder().keywords(["project"]).llm_confidence(0.9).build();
+        assert!(!confident.is_weak());
+        assert_eq!(confident.cap_weak_matches(matches, 5).len(), 40);
+    }
+
+    #[test]
+    fn test_today_boundaries_in_a_non_utc_zone() {
+        // 08:30 on 12 May in Sydney (UTC+10) is still 11 May in UTC
+        let now = Tz::Australia__Sydney.with_ymd_and_hms(2025, 5, 12, 8, 30, 0).unwrap();
+
+        let mut criteria = QueryCriteria::new("emails from today");
+        process_date_queries_at("emails from today", &mut criteria, now);
+        assert_eq!(criteria.date_from, Some(Utc.with_ymd_and_hms(2025, 5, 11, 14, 0, 0).unwrap()));
+        assert_eq!(criteria.date_to, None);
+
+        let mut criteria = QueryCriteria::new("emails from yesterday");
+        process_date_queries_at("emails from yesterday", &mut criteria, now);
+        assert_eq!(criteria.date_from, Some(Utc.with_ymd_and_hms(2025, 5, 10, 14, 0, 0).unwrap()));
+        assert_eq!(criteria.date_to, Some(Utc.with_ymd_and_hms(2025, 5, 11, 13, 59, 59).unwrap()));
+    }
+
+    #[test]
+    fn test_reminder_time_in_the_default_zone() {
+        // Monday 12 May, 08:30 in Sydney (UTC+10); reminders go off at 9am Sydney time
+        let now = Tz::Australia__Sydney.with_ymd_and_hms(2025, 5, 12, 8, 30, 0).unwrap();
+        let at_nine_sydney = |day| Some(Utc.with_ymd_and_hms(2025, 5, day, 23, 0, 0).unwrap());
+
+        assert_eq!(reminder_time_at("tomorrow", now), at_nine_sydney(12));
+        assert_eq!(reminder_time_at("next week", now), at_nine_sydney(18));
+        assert_eq!(reminder_time_at("in 3 days", now), at_nine_sydney(14));
+        assert_eq!(reminder_time_at("in 1 week", now), at_nine_sydney(18));
+        assert_eq!(reminder_time_at("on 2025-05-20", now), at_nine_sydney(19));
+        assert_eq!(reminder_time_at("whenever", now), None);
+        assert_eq!(reminder_time_at("in 3650 days", now).map(|time| time.year()), Some(2035));
+        for phrase in ["in 3651 days", "in 1000000000000000000 weeks", "in 99999999999999999999999 days"] {
+            assert_eq!(reminder_time_at(phrase, now), None, "phrase: {}", phrase);
+        }
+    }
+
+    #[test]
+    fn test_today_boundaries_follow_daylight_saving() {
+        // New York is UTC-4 in summer and UTC-5 in winter
+        let summer = Tz::America__New_York.with_ymd_and_hms(2025, 7, 1, 23, 0, 0).unwrap();
+        let winter = Tz::America__New_York.with_ymd_and_hms(2025, 1, 15, 23, 0, 0).unwrap();
+
+        let mut criteria = QueryCriteria::new("today");
+        process_date_queries_at("today", &mut criteria, summer);
+        assert_eq!(criteria.date_from, Some(Utc.with_ymd_and_hms(2025, 7, 1, 4, 0, 0).unwrap()));
+
+        process_date_queries_at("today", &mut criteria, winter);
+        assert_eq!(criteria.date_from, Some(Utc.with_ymd_and_hms(2025, 1, 15, 5, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn test_today_boundaries_default_to_utc() {
+        let now = Tz::UTC.with_ymd_and_hms(2025, 5, 12, 8, 30, 0).unwrap();
+        let mut criteria = QueryCriteria::new("on 2025-05-01");
+        process_date_queries_at("on 2025-05-01", &mut criteria, now);
+        assert_eq!(criteria.date_from, Some(Utc.with_ymd_and_hms(2025, 5, 1, 0, 0, 0).unwrap()));
+        assert_eq!(criteria.date_to, Some(Utc.with_ymd_and_hms(2025, 5, 1, 23, 59, 59).unwrap()));
+    }
+
     #[test]
     fn test_refine_query_with_intent_reply_to_bob() {
         let query = "I need to reply to Bob, the carpenter who sent me a quote. Find his latest email.";
-        let analysis = QueryCriteria {
-            keywords: vec!["quote".to_string()],
-            from: Some("Bob".to_string()),
-            to: None,
-            subject: None,
-            date_from: None,
-            date_to: None,
-            raw_query: query.to_string(),
-            llm_confidence: 0.9,
-        };
+        let analysis = QueryCriteria::builder()
+            .keywords(["quote"])
+            .from("Bob")
+            .raw_query(query.to_string())
+            .llm_confidence(0.9)
+            .build();
 
         let criteria = refine_query_with_intent(query, analysis, Intent::Reply);
 
@@ -385,4 +971,35 @@ mod tests {
         assert_eq!(criteria.from.unwrap(), "Bob");
         assert!(criteria.keywords.contains(&"quote".to_string()));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_classify_sender_query() {
+        // (query, sender, generic)
+        let cases = [
+            ("explain the email from kai", "kai", true),
+            ("please show me the latest message from kai", "kai", true),
+            ("kai's email", "kai", true),
+            ("what did kai send me?", "kai", true),
+            ("read kai's budget email", "kai", true),
+            ("explain the email from kai about the invoice", "kai", false),
+            ("the invoice from kai", "kai", false),
+            ("kai's update on the budget", "kai", false),
+            ("emails from kai yesterday", "kai", false),
+            ("the email from kai last week", "kai", false),
+            ("the email from kai with subject quarterly report", "kai", false),
+            ("kai's email on 2025-05-12", "kai", false),
+            ("explain the email from kai smith", "kai smith", true),
+            ("explain the email from bob", "kai", false),
+        ];
+
+        for (query, sender, generic) in cases {
+            let class = classify_sender_query(query, sender);
+            assert_eq!(class.generic, generic, "{:?} about {:?}", query, sender);
+            assert!((0.5..=1.0).contains(&class.confidence), "{:?}: {}", query, class.confidence);
+        }
+
+        assert_eq!(classify_sender_query("explain the email from kai", "kai").confidence, 1.0);
+        assert!(classify_sender_query("read kai's budget email", "kai").confidence < 0.7);
+        assert_eq!(classify_sender_query("from kai", "kai"), SenderQueryClass { generic: false, confidence: 0.5 });
+    }
+}
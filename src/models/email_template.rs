@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+/// A named email template whose subject and body may contain `{placeholder}` markers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailTemplate {
+    pub name: String,
+    #[serde(default)]
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("Failed to read templates: {0}")]
+    IoError(String),
+
+    #[error("Invalid templates file: {0}")]
+    ParseError(String),
+}
+
+/// The set of templates available for composing, loaded from a JSON file of the form
+/// `[{ "name": "...", "subject": "...", "body": "..." }]`.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateStore {
+    templates: Vec<EmailTemplate>,
+}
+
+impl TemplateStore {
+    /// Loads templates from `path`. A missing file is treated as an empty store.
+    pub fn load(path: &str) -> Result<Self, TemplateError> {
+        if !Path::new(path).exists() {
+            return Ok(TemplateStore::default());
+        }
+        let contents = fs::read_to_string(path)
+            .map_err(|e| TemplateError::IoError(format!("{}: {}", path, e)))?;
+        Self::from_json(&contents)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, TemplateError> {
+        let templates: Vec<EmailTemplate> = serde_json::from_str(json)
+            .map_err(|e| TemplateError::ParseError(e.to_string()))?;
+        Ok(TemplateStore { templates })
+    }
+
+    /// Looks up a template by name, ignoring case and treating spaces, hyphens and underscores alike.
+    pub fn get(&self, name: &str) -> Option<&EmailTemplate> {
+        let wanted = normalize_name(name);
+        self.templates.iter().find(|t| normalize_name(&t.name) == wanted)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.templates.iter().map(|t| t.name.as_str()).collect()
+    }
+}
+
+impl EmailTemplate {
+    /// Returns the subject and body with placeholders filled from `values`.
+    pub fn render(&self, values: &HashMap<String, String>) -> (String, String) {
+        (fill_placeholders(&self.subject, values), fill_placeholders(&self.body, values))
+    }
+}
+
+/// Replaces `{key}` markers in `text` with the matching value. Unknown placeholders are left
+/// untouched so it's obvious what still needs filling in.
+pub fn fill_placeholders(text: &str, values: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) if !after[..end].contains('{') => {
+                let key = &after[..end];
+                match values.get(key.trim()) {
+                    Some(value) => result.push_str(value),
+                    None => {
+                        result.push('{');
+                        result.push_str(key);
+                        result.push('}');
+                    }
+                }
+                rest = &after[end + 1..];
+            }
+            _ => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+fn normalize_name(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c == ' ' || c == '_' { '-' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATES: &str = r#"[
+        {
+            "name": "meeting-invite",
+            "subject": "Meeting on {date}",
+            "body": "Hi {recipient},\n\nCould we meet on {date} at {time}?"
+        },
+        { "name": "Thank You", "body": "Thanks {recipient}!" }
+    ]"#;
+
+    #[test]
+    fn test_load_and_lookup_templates() {
+        let store = TemplateStore::from_json(TEMPLATES).unwrap();
+        assert_eq!(store.names(), vec!["meeting-invite", "Thank You"]);
+        assert!(store.get("Meeting Invite").is_some());
+        assert!(store.get("thank-you").is_some());
+        assert!(store.get("unknown").is_none());
+
+        // A missing file is simply an empty store, but malformed JSON is an error
+        assert!(TemplateStore::load("./does/not/exist.json").unwrap().names().is_empty());
+        assert!(TemplateStore::from_json("{ not json").is_err());
+    }
+
+    #[test]
+    fn test_render_fills_known_placeholders_only() {
+        let store = TemplateStore::from_json(TEMPLATES).unwrap();
+        let template = store.get("meeting-invite").unwrap();
+
+        let mut values = HashMap::new();
+        values.insert("recipient".to_string(), "Bob".to_string());
+        values.insert("date".to_string(), "2025-05-12".to_string());
+
+        let (subject, body) = template.render(&values);
+        assert_eq!(subject, "Meeting on 2025-05-12");
+        assert_eq!(body, "Hi Bob,\n\nCould we meet on 2025-05-12 at {time}?");
+    }
+
+    #[test]
+    fn test_fill_placeholders_handles_stray_braces() {
+        let values = HashMap::from([("name".to_string(), "Ann".to_string())]);
+        assert_eq!(fill_placeholders("a { b {name}", &values), "a { b Ann");
+        assert_eq!(fill_placeholders("unclosed {name", &values), "unclosed {name");
+    }
+}
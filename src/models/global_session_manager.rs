@@ -1,16 +1,20 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use tokio::sync::OwnedMutexGuard;
 use crate::models::user_session::UserSession;
 
 #[derive(Clone)]
 pub struct GlobalSessionManager {
     sessions: Arc<Mutex<HashMap<String, UserSession>>>,
+    /// One async lock per session id, held for the length of a chat request
+    request_locks: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
 }
 
 impl GlobalSessionManager {
     pub fn new() -> Self {
         GlobalSessionManager {
             sessions: Arc::new(Mutex::new(HashMap::new())),
+            request_locks: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -20,9 +24,106 @@ impl GlobalSessionManager {
         sessions.insert(session_id, session);
     }
 
+    /// Returns the number of sessions currently held in memory
+    pub fn session_count(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    /// Returns true if `session_id` belongs to a stored session
+    pub fn contains(&self, session_id: &str) -> bool {
+        self.sessions.lock().unwrap().contains_key(session_id)
+    }
+
     /// Retrieves a session if it exists
     pub fn get(&self, session_id: &str) -> Option<UserSession> {
         let sessions = self.sessions.lock().unwrap();
        sessions.get(session_id).cloned()
     }
-}
\ No newline at end of file
+
+    /// Applies `f` to the stored session while holding the sessions lock, so the change can't
+    /// interleave with another request's. Returns None if there is no such session.
+    pub fn with_session<F, R>(&self, session_id: &str, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut UserSession) -> R,
+    {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.get_mut(session_id).map(f)
+    }
+
+    /// Waits until no other request holds `session_id`, then holds it until the guard is dropped.
+    /// Requests that get, update and insert a session should hold this so that concurrent
+    /// requests for the same session (e.g. a double-clicked send) queue instead of
+    /// overwriting each other's history.
+    pub async fn lock_session(&self, session_id: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.request_locks.lock().unwrap();
+            locks.entry(session_id.to_string()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::email_db::EmailDB;
+    use ollama_rs::generation::chat::ChatMessage;
+    use std::time::Duration;
+
+    // Mimics a chat request: read the session, take a while processing, write it back
+    async fn handle_request(manager: GlobalSessionManager, session_id: &str, message: &str) {
+        let _guard = manager.lock_session(session_id).await;
+        let mut session = manager.get(session_id).unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        session.history.push(ChatMessage::user(message.to_string()));
+        manager.insert(session_id.to_string(), session);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_for_one_session_queue() {
+        let manager = GlobalSessionManager::new();
+        manager.insert("session".to_string(), UserSession::new(EmailDB::unconnected("emails")));
+
+        tokio::join!(
+            handle_request(manager.clone(), "session", "first"),
+            handle_request(manager.clone(), "session", "second"),
+        );
+
+        let history = manager.get("session").unwrap().history;
+        assert_eq!(history.len(), 2, "neither request's history update should be lost");
+    }
+
+    #[tokio::test]
+    async fn test_with_session_merges_concurrent_updates() {
+        let manager = GlobalSessionManager::new();
+        manager.insert("session".to_string(), UserSession::new(EmailDB::unconnected("emails")));
+
+        // Both requests start from the same snapshot, without the request lock
+        let update = |message: &'static str| {
+            let manager = manager.clone();
+            async move {
+                let mut session = manager.get("session").unwrap();
+                let history_len = session.history.len();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                session.history.push(ChatMessage::user(message.to_string()));
+                manager.with_session("session", |stored| {
+                    stored.history.extend(session.history.drain(history_len..));
+                })
+            }
+        };
+        let (first, second) = tokio::join!(update("first"), update("second"));
+        assert!(first.is_some() && second.is_some());
+
+        let history = manager.get("session").unwrap().history;
+        assert_eq!(history.len(), 2);
+        assert!(manager.with_session("missing", |_| ()).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_different_sessions_do_not_block_each_other() {
+        let manager = GlobalSessionManager::new();
+        let _held = manager.lock_session("a").await;
+        let other = tokio::time::timeout(Duration::from_millis(100), manager.lock_session("b")).await;
+        assert!(other.is_ok());
+    }
+}
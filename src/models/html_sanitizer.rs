@@ -0,0 +1,88 @@
+use regex::Regex;
+
+/// HTML with trackers removed, and how many were removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SanitizedHtml {
+    pub html: String,
+    pub trackers_removed: usize,
+}
+
+/// Strips what marketing mail uses to track its readers: scripts, 1x1 (or hidden) tracking
+/// pixels, and tags that load external resources (iframes, stylesheets, embeds). Remaining
+/// images keep their alt text but lose remote `src` URLs.
+pub fn sanitize_html(html: &str) -> SanitizedHtml {
+    let mut trackers_removed = 0;
+    let mut remove = |html: String, pattern: &str| -> String {
+        let re = Regex::new(pattern).unwrap();
+        trackers_removed += re.find_iter(&html).count();
+        re.replace_all(&html, "").into_owned()
+    };
+
+    let html = remove(html.to_string(), r"(?is)<script\b.*?</script\s*>|<script\b[^>]*/?>");
+    let html = remove(html, r"(?is)<iframe\b.*?</iframe\s*>|<object\b.*?</object\s*>|<(?:iframe|object|embed)\b[^>]*>");
+    let html = remove(html, r#"(?is)<link\b[^>]*\bhref\s*=\s*["']?(?:https?:)?//[^>]*>"#);
+
+    let img = Regex::new(r"(?is)<img\b[^>]*>").unwrap();
+    let remote_src = Regex::new(r#"(?is)\s(?:src|srcset)\s*=\s*(?:"(?:https?:)?//[^"]*"|'(?:https?:)?//[^']*'|(?:https?:)?//[^\s>]*)"#).unwrap();
+    let html = img.replace_all(&html, |caps: &regex::Captures| {
+        let tag = &caps[0];
+        if is_tracking_pixel(tag) {
+            trackers_removed += 1;
+            return String::new();
+        }
+        if remote_src.is_match(tag) {
+            trackers_removed += 1;
+            return remote_src.replace_all(tag, "").into_owned();
+        }
+        tag.to_string()
+    }).into_owned();
+
+    SanitizedHtml { html, trackers_removed }
+}
+
+// Tracking pixels are 0 or 1 pixel images, or images hidden with CSS
+fn is_tracking_pixel(tag: &str) -> bool {
+    let tiny = |attribute: &str| {
+        let pattern = format!(r#"(?i)\b{}\s*=\s*["']?\s*[01](?:px)?\s*["']?(?:[\s/>]|$)"#, attribute);
+        Regex::new(&pattern).unwrap().is_match(tag)
+    };
+    let hidden = Regex::new(r"(?i)display\s*:\s*none|visibility\s*:\s*hidden|(?:width|height)\s*:\s*[01]px").unwrap();
+    (tiny("width") && tiny("height")) || hidden.is_match(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_html_removes_tracking_pixel_and_script() {
+        let html = r#"<html><body>
+            <p>Our spring sale starts today!</p>
+            <script type="text/javascript">track("open");</script>
+            <img src="https://shop.example.com/logo.png" alt="Shop logo">
+            <img src="https://t.example.com/open?id=42" width="1" height="1" alt="">
+            <img src="https://t.example.com/o.gif" style="display:none">
+        </body></html>"#;
+
+        let sanitized = sanitize_html(html);
+        assert_eq!(sanitized.trackers_removed, 4);
+        assert!(sanitized.html.contains("Our spring sale starts today!"));
+        assert!(!sanitized.html.contains("track(\"open\")"));
+        assert!(!sanitized.html.contains("t.example.com"));
+        assert!(!sanitized.html.contains("shop.example.com"));
+        assert!(sanitized.html.contains(r#"alt="Shop logo""#), "{}", sanitized.html);
+    }
+
+    #[test]
+    fn test_sanitize_html_removes_external_resources() {
+        let html = r#"<link rel="stylesheet" href="https://cdn.example.com/mail.css"><p>Hi</p><iframe src="https://ads.example.com/"></iframe><img src="cid:logo@mail" alt="logo">"#;
+
+        let sanitized = sanitize_html(html);
+        assert_eq!(sanitized.html, r#"<p>Hi</p><img src="cid:logo@mail" alt="logo">"#);
+        assert_eq!(sanitized.trackers_removed, 2);
+
+        // Nothing to remove leaves the HTML as it was
+        let clean = "<p>Hello <b>there</b></p>";
+        assert_eq!(sanitize_html(clean), SanitizedHtml { html: clean.to_string(), trackers_removed: 0 });
+    }
+}
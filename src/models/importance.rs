@@ -0,0 +1,149 @@
+use std::collections::{HashMap, HashSet};
+use crate::models::email::{Email, extract_address, split_address_list};
+use crate::models::urgency::{classify_urgency, Urgency};
+
+// Relative weight of each signal; they sum to 1.0 so scores stay in 0.0..=1.0
+const SENDER_FREQUENCY_WEIGHT: f32 = 0.4;
+const REPLIED_WEIGHT: f32 = 0.3;
+const URGENCY_WEIGHT: f32 = 0.3;
+
+/// What is known about the mailbox as a whole when scoring a single email.
+#[derive(Debug, Default)]
+pub struct ImportanceSignals {
+    /// Emails received per sender address
+    pub sender_counts: HashMap<String, usize>,
+    /// Addresses the user has written to
+    pub replied_to: HashSet<String>,
+}
+
+impl ImportanceSignals {
+    /// Gathers sender frequencies from `emails`, and the addresses the user has written to from
+    /// `sent` plus any of `emails` sent by the user. A mailbox loaded from the inbox alone holds
+    /// little of the user's own mail, so `sent` should come from their sent folder.
+    pub fn from_emails(emails: &[Email], sent: &[Email], user_address: Option<&str>) -> Self {
+        let user_address = user_address.map(extract_address);
+        let mut signals = ImportanceSignals::default();
+
+        for email in sent {
+            signals.add_recipients(email);
+        }
+        for email in emails {
+            let sender = match email.from.as_deref() {
+                Some(from) => extract_address(from),
+                None => continue,
+            };
+
+            if user_address.as_deref() == Some(sender.as_str()) {
+                signals.add_recipients(email);
+            } else {
+                *signals.sender_counts.entry(sender).or_insert(0) += 1;
+            }
+        }
+        signals
+    }
+
+    // Mail the user sent tells us who they reply to
+    fn add_recipients(&mut self, email: &Email) {
+        let recipients = [&email.to, &email.cc];
+        for header in recipients.into_iter().flatten() {
+            self.replied_to.extend(split_address_list(header).iter().map(|entry| extract_address(entry)));
+        }
+    }
+}
+
+/// Scores how much an email likely matters to the user, from 0.0 to 1.0, combining how often
+/// its sender writes, whether the user has written back to them, and urgency keywords.
+pub fn score_importance(email: &Email, signals: &ImportanceSignals) -> f32 {
+    let sender = email.from.as_deref().map(extract_address).unwrap_or_default();
+
+    let max_count = signals.sender_counts.values().copied().max().unwrap_or(0);
+    let frequency = match signals.sender_counts.get(&sender) {
+        Some(&count) if max_count > 0 => count as f32 / max_count as f32,
+        _ => 0.0,
+    };
+    let replied = if signals.replied_to.contains(&sender) { 1.0 } else { 0.0 };
+    let urgency = match classify_urgency(email) {
+        Urgency::High => 1.0,
+        Urgency::Medium => 0.5,
+        Urgency::Low => 0.0,
+    };
+
+    SENDER_FREQUENCY_WEIGHT * frequency + REPLIED_WEIGHT * replied + URGENCY_WEIGHT * urgency
+}
+
+/// Sets `importance` on every email, using the whole batch and the user's `sent` mail as the
+/// signal source.
+pub fn score_mailbox(emails: &mut [Email], sent: &[Email], user_address: Option<&str>) {
+    let signals = ImportanceSignals::from_emails(emails, sent, user_address);
+    for email in emails.iter_mut() {
+        email.importance = Some(score_importance(email, &signals));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(from: &str, to: &str, subject: &str) -> Email {
+        Email {
+            from: Some(from.to_string()),
+            to: Some(to.to_string()),
+            subject: Some(subject.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_score_importance_signals() {
+        let emails = vec![
+            email("Boss <boss@work.example>", "me@example.com", "Weekly sync"),
+            email("Boss <boss@work.example>", "me@example.com", "Budget"),
+            email("me@example.com", "boss@work.example", "Re: Budget"),
+            email("news@shop.example", "me@example.com", "Spring sale"),
+            email("ops@work.example", "me@example.com", "URGENT: server down"),
+        ];
+        let signals = ImportanceSignals::from_emails(&emails, &[], Some("Me <me@example.com>"));
+
+        assert_eq!(signals.sender_counts.get("boss@work.example"), Some(&2));
+        assert!(signals.replied_to.contains("boss@work.example"));
+        assert!(!signals.sender_counts.contains_key("me@example.com"));
+
+        let boss = score_importance(&emails[0], &signals);
+        let newsletter = score_importance(&emails[3], &signals);
+        let urgent = score_importance(&emails[4], &signals);
+
+        // Frequent sender the user replies to: 0.4 + 0.3
+        assert!((boss - 0.7).abs() < 1e-6);
+        // One-off sender, no reply, no urgency: 0.4 * 1/2
+        assert!((newsletter - 0.2).abs() < 1e-6);
+        // Urgency lifts a one-off sender above the newsletter
+        assert!(urgent > newsletter && urgent < boss);
+    }
+
+    #[test]
+    fn test_sent_mail_supplies_replied_to() {
+        let inbox = vec![email("ops@work.example", "me@example.com", "Deploy")];
+        let sent = vec![Email {
+            cc: Some("Lead <lead@work.example>".to_string()),
+            ..email("me@example.com", "Ops <ops@work.example>", "Re: Deploy")
+        }];
+        let signals = ImportanceSignals::from_emails(&inbox, &sent, None);
+
+        assert!(signals.replied_to.contains("ops@work.example"));
+        assert!(signals.replied_to.contains("lead@work.example"));
+        // Sent mail isn't counted as received
+        assert!(!signals.sender_counts.contains_key("me@example.com"));
+        assert!(ImportanceSignals::from_emails(&inbox, &[], None).replied_to.is_empty());
+    }
+
+    #[test]
+    fn test_score_mailbox_sets_every_email() {
+        let mut emails = vec![
+            email("a@example.com", "me@example.com", "Hi"),
+            Email::default(),
+        ];
+        score_mailbox(&mut emails, &[], None);
+        assert!(emails.iter().all(|e| e.importance.is_some()));
+        assert_eq!(emails[1].importance, Some(0.0));
+    }
+}
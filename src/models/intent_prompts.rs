@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, thiserror::Error)]
+pub enum IntentPromptError {
+    #[error("Failed to read intent prompts: {0}")]
+    IoError(String),
+
+    #[error("Invalid intent prompts file: {0}")]
+    ParseError(String),
+}
+
+/// Per-intent instructions that replace the built-in ones, loaded from a TOML file keyed by
+/// intent name, e.g. `list = "Be brief: one line per email."`.
+#[derive(Debug, Clone, Default)]
+pub struct IntentPrompts {
+    prompts: HashMap<String, String>,
+}
+
+impl IntentPrompts {
+    /// Loads overrides from `path`. A missing file means no overrides.
+    pub fn load(path: &str) -> Result<Self, IntentPromptError> {
+        if !Path::new(path).exists() {
+            return Ok(IntentPrompts::default());
+        }
+        let contents = fs::read_to_string(path)
+            .map_err(|e| IntentPromptError::IoError(format!("{}: {}", path, e)))?;
+        Self::from_toml(&contents)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, IntentPromptError> {
+        let prompts: HashMap<String, String> = toml::from_str(toml)
+            .map_err(|e| IntentPromptError::ParseError(e.to_string()))?;
+        let prompts = prompts.into_iter()
+            .map(|(intent, prompt)| (intent.trim().to_lowercase(), prompt))
+            .collect();
+        Ok(IntentPrompts { prompts })
+    }
+
+    /// The configured instructions for `intent` (e.g. "compose"), if any. Blank entries don't count.
+    pub fn get(&self, intent: &str) -> Option<&str> {
+        self.prompts.get(&intent.to_lowercase())
+            .map(|prompt| prompt.trim())
+            .filter(|prompt| !prompt.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_intent_prompts() {
+        let prompts = IntentPrompts::from_toml(r#"
+            list = "Be concise: one line per email."
+            Compose = "Use a formal tone."
+            explain = "  "
+        "#).unwrap();
+        assert_eq!(prompts.get("list"), Some("Be concise: one line per email."));
+        assert_eq!(prompts.get("compose"), Some("Use a formal tone."));
+        assert_eq!(prompts.get("explain"), None);
+        assert_eq!(prompts.get("reply"), None);
+
+        assert!(IntentPrompts::load("./does/not/exist.toml").unwrap().get("list").is_none());
+        assert!(IntentPrompts::from_toml("list = [").is_err());
+    }
+}
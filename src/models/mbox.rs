@@ -0,0 +1,139 @@
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use crate::models::email::{Email, extract_address, parse_email_date};
+
+// Envelope sender used when an email has no From header
+const UNKNOWN_SENDER: &str = "MAILER-DAEMON";
+
+/// Serializes emails into mboxrd format: a `From ` separator line, the headers that are
+/// present, a blank line and the body, with body lines starting `From ` quoted as `>From `.
+pub fn to_mbox(emails: &[Email]) -> String {
+    let mut mbox = String::new();
+    for email in emails {
+        write_message(&mut mbox, email);
+    }
+    mbox
+}
+
+fn write_message(mbox: &mut String, email: &Email) {
+    let sender = email.from.as_deref()
+        .map(extract_address)
+        .filter(|address| !address.is_empty())
+        .unwrap_or_else(|| UNKNOWN_SENDER.to_string());
+    // The separator's date is asctime-style; fall back to the epoch when the Date is unparseable
+    let date = email.date.as_deref()
+        .and_then(parse_email_date)
+        .unwrap_or(DateTime::<Utc>::UNIX_EPOCH);
+    mbox.push_str(&format!("From {} {}\n", sender, date.format("%a %b %e %H:%M:%S %Y")));
+
+    let headers = [
+        ("From", &email.from),
+        ("To", &email.to),
+        ("Cc", &email.cc),
+        ("Date", &email.date),
+        ("Subject", &email.subject),
+        ("Message-ID", &email.message_id),
+    ];
+    for (name, value) in headers {
+        if let Some(value) = value {
+            // Header values must stay on one line
+            mbox.push_str(&format!("{}: {}\n", name, value.replace(['\r', '\n'], " ")));
+        }
+    }
+    mbox.push('\n');
+
+    let from_line = Regex::new(r"^>*From ").unwrap();
+    if let Some(body) = &email.body {
+        for line in body.lines() {
+            if from_line.is_match(line) {
+                mbox.push('>');
+            }
+            mbox.push_str(line);
+            mbox.push('\n');
+        }
+    }
+    // Messages are separated by a blank line
+    mbox.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Splits an mboxrd file back into (headers, body) pairs
+    fn parse_mbox(mbox: &str) -> Vec<(Vec<(String, String)>, String)> {
+        let mut messages = Vec::new();
+        let mut previous_blank = true;
+        let mut current: Option<Vec<&str>> = None;
+
+        for line in mbox.lines() {
+            if previous_blank && line.starts_with("From ") {
+                if let Some(lines) = current.take() {
+                    messages.push(lines);
+                }
+                current = Some(Vec::new());
+            } else if let Some(lines) = current.as_mut() {
+                lines.push(line);
+            }
+            previous_blank = line.is_empty();
+        }
+        messages.extend(current);
+
+        messages.into_iter().map(|lines| {
+            let split = lines.iter().position(|line| line.is_empty()).unwrap_or(lines.len());
+            let headers = lines[..split].iter()
+                .filter_map(|line| line.split_once(": "))
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            let body = lines[(split + 1).min(lines.len())..].iter()
+                .map(|line| Regex::new(r"^>(>*From )").unwrap().replace(line, "$1").into_owned())
+                .collect::<Vec<_>>()
+                .join("\n");
+            (headers, body.trim_end().to_string())
+        }).collect()
+    }
+
+    fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_mbox_round_trip() {
+        let emails = vec![
+            Email {
+                from: Some("Alice <alice@example.com>".to_string()),
+                to: Some("bob@example.com".to_string()),
+                date: Some("Tue, 06 May 2025 09:15:00 +0000".to_string()),
+                subject: Some("Quarterly report".to_string()),
+                body: Some("Hi Bob,\n\nFrom the numbers, Q2 looks good.\n\nAlice".to_string()),
+                message_id: Some("msg-1".to_string()),
+                ..Default::default()
+            },
+            Email {
+                subject: Some("No sender".to_string()),
+                body: Some("Just a body".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        let mbox = to_mbox(&emails);
+        assert!(mbox.starts_with("From alice@example.com Tue May  6 09:15:00 2025\n"));
+        assert!(mbox.contains("\n>From the numbers"));
+
+        let messages = parse_mbox(&mbox);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(header(&messages[0].0, "Subject"), Some("Quarterly report"));
+        assert_eq!(header(&messages[0].0, "Message-ID"), Some("msg-1"));
+        assert_eq!(messages[0].1, emails[0].body.clone().unwrap());
+        assert_eq!(header(&messages[1].0, "Subject"), Some("No sender"));
+
+        // Missing fields have no header at all
+        assert_eq!(header(&messages[1].0, "From"), None);
+        assert_eq!(header(&messages[1].0, "Date"), None);
+    }
+
+    #[test]
+    fn test_mbox_of_no_emails_is_empty() {
+        assert_eq!(to_mbox(&[]), "");
+    }
+}
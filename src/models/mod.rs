@@ -3,4 +3,21 @@ pub mod email_db;
 pub mod email_query;
 pub mod global_session_manager;
 pub mod user_session;
-pub mod query_builder;
\ No newline at end of file
+pub mod query_builder;
+pub mod email_template;
+pub mod rate_limiter;
+pub mod urgency;
+pub mod address_book;
+pub mod importance;
+pub mod session_store;
+pub mod automated;
+pub mod mbox;
+pub mod snippets;
+pub mod intent_prompts;
+pub mod annotation;
+pub mod calendar;
+pub mod redaction;
+pub mod reminder;
+pub mod persona;
+pub mod similarity;
+pub mod html_sanitizer;
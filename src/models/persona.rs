@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// The tone the assistant answers in, set for everyone by `PERSONA` or per session from chat
+/// ("be more concise").
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Persona {
+    Terse,
+    Friendly,
+    Professional,
+}
+
+impl Persona {
+    /// Parses a persona name, accepting a few synonyms ("concise", "formal")
+    pub fn from_name(name: &str) -> Option<Persona> {
+        match name.trim().to_lowercase().as_str() {
+            "terse" | "concise" | "brief" => Some(Persona::Terse),
+            "friendly" | "warm" | "casual" => Some(Persona::Friendly),
+            "professional" | "formal" => Some(Persona::Professional),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Persona::Terse => "terse",
+            Persona::Friendly => "friendly",
+            Persona::Professional => "professional",
+        }
+    }
+
+    /// The style instruction added to the system prompt
+    pub fn instruction(&self) -> &'static str {
+        match self {
+            Persona::Terse => "Answer as briefly as possible: short sentences, no pleasantries, no repetition of the question.",
+            Persona::Friendly => "Use a warm, friendly and conversational tone.",
+            Persona::Professional => "Use a polished, professional tone, as you would with a colleague or client.",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_persona_from_name() {
+        assert_eq!(Persona::from_name("Terse"), Some(Persona::Terse));
+        assert_eq!(Persona::from_name(" concise "), Some(Persona::Terse));
+        assert_eq!(Persona::from_name("formal"), Some(Persona::Professional));
+        assert_eq!(Persona::from_name("friendly"), Some(Persona::Friendly));
+        assert_eq!(Persona::from_name("pirate"), None);
+        for persona in [Persona::Terse, Persona::Friendly, Persona::Professional] {
+            assert_eq!(Persona::from_name(persona.name()), Some(persona));
+        }
+    }
+}
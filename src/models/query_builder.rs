@@ -1,4 +1,6 @@
+use crate::models::email_db::FILTERABLE_ATTRIBUTES;
 use crate::models::email_query::QueryCriteria;
+use log::warn;
 
 pub struct EmailQueryBuilder {
     pub criteria: QueryCriteria,
@@ -16,6 +18,10 @@ impl EmailQueryBuilder {
         if !self.criteria.keywords.is_empty() {
             query_terms.push(self.criteria.keywords.join(" "));
         }
+        // Quoted phrases become Meilisearch phrase searches, which match the exact word sequence
+        for phrase in &self.criteria.phrases {
+            query_terms.push(format!("\"{}\"", phrase.replace('"', "")));
+        }
 
         // Create filters for structured search
         let mut filters = Vec::new();
@@ -24,26 +30,45 @@ impl EmailQueryBuilder {
         if let Some(ref from) = self.criteria.from {
             if from.contains("@") {
                 // For actual email addresses, use filter
-                filters.push(format!("from = \"{}\"", from));
+                filters.extend(filter_condition("from", "=", from));
             } else {
                 // For names, add a search prefix targeting only the from field
                 // Use a case-insensitive search by using wildcards around the name
-                query_terms.push(format!("from:\"*{}*\"", from));
+                query_terms.push(format!("from:\"*{}*\"", from.replace('"', "")));
             }
         }
         
-        // Handle other structured fields normally
+        // Handle 'to' the same way: exact filter for addresses, search term for names
         if let Some(ref to) = self.criteria.to {
-            filters.push(format!("to = \"{}\"", to));
+            if to.contains("@") {
+                filters.extend(filter_condition("to", "=", to));
+            } else {
+                query_terms.push(format!("to:\"*{}*\"", to.replace('"', "")));
+            }
         }
+
+        // A full subject gets an exact filter; a fragment like "the roadmap" becomes a search
+        // term on the subject field, as with sender names
         if let Some(ref subject) = self.criteria.subject {
-            filters.push(format!("subject = \"{}\"", subject));
+            if is_exact_subject(subject, &self.criteria.raw_query) {
+                filters.extend(filter_condition("subject", "=", subject));
+            } else {
+                query_terms.push(format!("subject:\"*{}*\"", subject.replace('"', "")));
+            }
+        }
+
+        // Dates are stored as header strings, which Meilisearch can't compare, so the date
+        // range is left to `EmailDB` to apply in code
+        if let Some(ref language) = self.criteria.language {
+            filters.extend(filter_condition("language", "=", language));
         }
-        if let Some(ref date_from) = self.criteria.date_from {
-            filters.push(format!("date >= \"{}\"", date_from));
+        match self.criteria.has_attachment {
+            Some(true) => filters.push("attachment_count > 0".to_string()),
+            Some(false) => filters.push("attachment_count = 0".to_string()),
+            None => {}
         }
-        if let Some(ref date_to) = self.criteria.date_to {
-            filters.push(format!("date <= \"{}\"", date_to));
+        if let Some(ref attachment_type) = self.criteria.attachment_type {
+            filters.extend(filter_condition("attachment_types", "=", attachment_type));
         }
         
         // Build the final query string and filter
@@ -63,6 +88,44 @@ impl EmailQueryBuilder {
     }
 }
 
+/// `field op "value"` for a Meilisearch filter, or None if `field` isn't filterable in the index.
+/// Values come from user queries, so they are quoted with `quote_filter_value`.
+fn filter_condition(field: &str, op: &str, value: &str) -> Option<String> {
+    if !FILTERABLE_ATTRIBUTES.contains(&field) {
+        warn!("Ignoring filter on non-filterable field {:?}", field);
+        return None;
+    }
+    Some(format!("{} {} {}", field, op, quote_filter_value(value)))
+}
+
+/// Double-quotes `value` for a filter expression, escaping backslashes and quotes so a value
+/// like `"Smith, Bob" <bob@example.com>` can't end the string early and inject conditions.
+/// Control characters are dropped.
+pub fn quote_filter_value(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars().filter(|c| !c.is_control()) {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+const QUOTES: &[char] = &['"', '\'', '\u{201c}', '\u{201d}', '\u{2018}', '\u{2019}'];
+
+/// Whether `subject` looks like a complete subject line: quoted verbatim in the user's query,
+/// or a capitalised phrase of at least three words. Short or all-lowercase fragments don't.
+pub fn is_exact_subject(subject: &str, raw_query: &str) -> bool {
+    let subject = subject.trim();
+    let quoted = raw_query.match_indices(subject).any(|(start, _)| {
+        raw_query[..start].ends_with(QUOTES) && raw_query[start + subject.len()..].starts_with(QUOTES)
+    });
+    quoted || (subject.chars().any(char::is_uppercase) && subject.split_whitespace().count() >= 3)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -72,16 +135,11 @@ mod tests {
         // This test demonstrates why "explain the email from alice" now matches correctly
         
         // Create search criteria where from="alice" (no @ symbol)
-        let criteria = QueryCriteria {
-            keywords: vec![],
-            from: Some("alice".to_string()),
-            to: None,
-            subject: None,
-            date_from: None,
-            date_to: None,
-            raw_query: "explain the email from alice".to_string(),
-            llm_confidence: 0.9,
-        };
+        let criteria = QueryCriteria::builder()
+            .from("alice")
+            .raw_query("explain the email from alice")
+            .llm_confidence(0.9)
+            .build();
         
         let builder = EmailQueryBuilder::new(criteria);
         let (query, filter) = builder.build_meili_query();
@@ -97,16 +155,11 @@ mod tests {
     fn test_build_meili_query_email_address_becomes_filter() {
         // In contrast, an email address is handled correctly
         
-        let criteria = QueryCriteria {
-            keywords: vec![],
-            from: Some("alice@example.com".to_string()),
-            to: None,
-            subject: None,
-            date_from: None,
-            date_to: None,
-            raw_query: "explain the email from alice@example.com".to_string(),
-            llm_confidence: 0.9,
-        };
+        let criteria = QueryCriteria::builder()
+            .from("alice@example.com")
+            .raw_query("explain the email from alice@example.com")
+            .llm_confidence(0.9)
+            .build();
         
         let builder = EmailQueryBuilder::new(criteria);
         let (query, filter) = builder.build_meili_query();
@@ -115,4 +168,151 @@ mod tests {
         assert_eq!(query, None);
         assert_eq!(filter, Some("from = \"alice@example.com\"".to_string()));
     }
+
+    #[test]
+    fn test_build_meili_query_simple_to_name_becomes_query_term() {
+        let criteria = QueryCriteria::builder()
+            .to("bob")
+            .raw_query("emails I sent to bob")
+            .llm_confidence(0.9)
+            .build();
+
+        let builder = EmailQueryBuilder::new(criteria);
+        let (query, filter) = builder.build_meili_query();
+
+        assert_eq!(query, Some("to:\"*bob*\"".to_string()));
+        assert_eq!(filter, None);
+    }
+
+    #[test]
+    fn test_build_meili_query_to_address_becomes_filter() {
+        let criteria = QueryCriteria::builder()
+            .to("bob@example.com")
+            .raw_query("emails I sent to bob@example.com")
+            .llm_confidence(0.9)
+            .build();
+
+        let builder = EmailQueryBuilder::new(criteria);
+        let (query, filter) = builder.build_meili_query();
+
+        assert_eq!(query, None);
+        assert_eq!(filter, Some("to = \"bob@example.com\"".to_string()));
+    }
+
+    #[test]
+    fn test_build_meili_query_language_becomes_filter() {
+        let criteria = QueryCriteria::new("show my English emails");
+        assert_eq!(criteria.language.as_deref(), Some("en"));
+
+        let builder = EmailQueryBuilder::new(criteria);
+        let (_, filter) = builder.build_meili_query();
+        assert_eq!(filter, Some("language = \"en\"".to_string()));
+    }
+
+    #[test]
+    fn test_build_meili_query_leaves_date_range_out_of_filter() {
+        let criteria = QueryCriteria::builder()
+            .from("bob@example.com")
+            .date_range("2025-05-05T00:00:00Z".parse().unwrap(), "2025-05-11T23:59:59Z".parse().unwrap())
+            .raw_query("emails from bob@example.com last week")
+            .llm_confidence(0.9)
+            .build();
+        let (_, filter) = EmailQueryBuilder::new(criteria).build_meili_query();
+        assert_eq!(filter, Some("from = \"bob@example.com\"".to_string()));
+    }
+
+    #[test]
+    fn test_build_meili_query_quoted_phrase_becomes_phrase_search() {
+        let builder = EmailQueryBuilder::new(QueryCriteria::new("\"double billing\" refund"));
+        let (query, _) = builder.build_meili_query();
+        assert_eq!(query, Some("refund \"double billing\"".to_string()));
+    }
+
+    fn subject_criteria(subject: &str, raw_query: &str) -> QueryCriteria {
+        QueryCriteria::builder()
+            .subject(subject)
+            .raw_query(raw_query)
+            .llm_confidence(0.9)
+            .build()
+    }
+
+    #[test]
+    fn test_build_meili_query_partial_subject_becomes_query_term() {
+        let builder = EmailQueryBuilder::new(subject_criteria("roadmap", "the email about the roadmap"));
+        let (query, filter) = builder.build_meili_query();
+        assert_eq!(query, Some("subject:\"*roadmap*\"".to_string()));
+        assert_eq!(filter, None);
+
+        let builder = EmailQueryBuilder::new(subject_criteria("quarterly roadmap", "find the quarterly roadmap email"));
+        assert_eq!(builder.build_meili_query().0, Some("subject:\"*quarterly roadmap*\"".to_string()));
+    }
+
+    #[test]
+    fn test_build_meili_query_exact_subject_stays_a_filter() {
+        // Quoted verbatim, even if short
+        let builder = EmailQueryBuilder::new(subject_criteria("Roadmap", "the email titled \"Roadmap\""));
+        let (query, filter) = builder.build_meili_query();
+        assert_eq!(query, None);
+        assert_eq!(filter, Some("subject = \"Roadmap\"".to_string()));
+
+        // A full, capitalised subject line
+        let builder = EmailQueryBuilder::new(subject_criteria("Quarterly Product Roadmap", "explain the Quarterly Product Roadmap email"));
+        assert_eq!(builder.build_meili_query().1, Some("subject = \"Quarterly Product Roadmap\"".to_string()));
+    }
+
+    #[test]
+    fn test_build_meili_query_attachment_filters() {
+        let builder = EmailQueryBuilder::new(QueryCriteria::new("show me emails with attachments"));
+        assert_eq!(builder.build_meili_query().1, Some("attachment_count > 0".to_string()));
+
+        let builder = EmailQueryBuilder::new(QueryCriteria::new("which emails have PDFs"));
+        assert_eq!(
+            builder.build_meili_query().1,
+            Some("attachment_count > 0 AND attachment_types = \"pdf\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_quote_filter_value_escapes_quotes_and_backslashes() {
+        assert_eq!(quote_filter_value("alice@example.com"), "\"alice@example.com\"");
+        assert_eq!(quote_filter_value("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(quote_filter_value("line\nbreak"), "\"linebreak\"");
+    }
+
+    #[test]
+    fn test_build_meili_query_sender_with_quotes_is_escaped() {
+        let criteria = QueryCriteria::builder()
+            .from("\"Smith, Bob\" <bob@example.com>")
+            .raw_query("emails from bob@example.com")
+            .llm_confidence(0.9)
+            .build();
+        let (_, filter) = EmailQueryBuilder::new(criteria).build_meili_query();
+        assert_eq!(filter, Some(r#"from = "\"Smith, Bob\" <bob@example.com>""#.to_string()));
+
+        // A value trying to close the string and add its own condition stays inside the string
+        let criteria = QueryCriteria::builder()
+            .from("x@example.com\" OR from EXISTS OR from = \"\\")
+            .raw_query("emails from x@example.com")
+            .llm_confidence(0.9)
+            .build();
+        let (_, filter) = EmailQueryBuilder::new(criteria).build_meili_query();
+        assert_eq!(filter, Some(r#"from = "x@example.com\" OR from EXISTS OR from = \"\\""#.to_string()));
+    }
+
+    #[test]
+    fn test_build_meili_query_name_with_quotes_stays_one_term() {
+        let criteria = QueryCriteria::builder()
+            .from("o\"brien")
+            .raw_query("emails from o\"brien")
+            .llm_confidence(0.9)
+            .build();
+        let (query, _) = EmailQueryBuilder::new(criteria).build_meili_query();
+        assert_eq!(query, Some("from:\"*obrien*\"".to_string()));
+    }
+
+    #[test]
+    fn test_filter_condition_rejects_unknown_fields() {
+        assert_eq!(filter_condition("language", "=", "en"), Some("language = \"en\"".to_string()));
+        assert_eq!(filter_condition("body", "=", "secret"), None);
+    }
 }
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// A token-bucket rate limiter keyed by session id, shared across workers. Callers should only
+/// check ids of sessions that exist, so made-up ids can't get fresh buckets.
+///
+/// Each session may burst up to `requests_per_minute` requests, after which tokens
+/// refill continuously at that rate. A bucket left idle long enough to refill completely is
+/// the same as a new one, so it's dropped rather than kept forever.
+#[derive(Clone)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<HashMap<String, TokenBucket>>>,
+    capacity: f64,
+    refill_per_sec: f64,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `requests_per_minute` per key. Zero disables limiting.
+    pub fn new(requests_per_minute: u32) -> Self {
+        RateLimiter {
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            capacity: requests_per_minute as f64,
+            refill_per_sec: requests_per_minute as f64 / 60.0,
+        }
+    }
+
+    /// Records a request for `key`, returning false if it exceeds the limit
+    pub fn check(&self, key: &str) -> bool {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> bool {
+        if self.capacity <= 0.0 {
+            return true;
+        }
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if !buckets.contains_key(key) {
+            self.evict_idle(&mut buckets, now);
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(TokenBucket {
+            tokens: self.capacity,
+            last_refill: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Drops the buckets that have refilled to capacity since their last request
+    fn evict_idle(&self, buckets: &mut HashMap<String, TokenBucket>, now: Instant) {
+        buckets.retain(|_, bucket| {
+            let idle = now.saturating_duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens + idle * self.refill_per_sec < self.capacity
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rejects_request_over_limit() {
+        let limiter = RateLimiter::new(5);
+        let now = Instant::now();
+
+        for i in 0..5 {
+            assert!(limiter.check_at("session-a", now), "request {} should be allowed", i + 1);
+        }
+        assert!(!limiter.check_at("session-a", now), "6th rapid request should be rejected");
+
+        // Other sessions have their own bucket
+        assert!(limiter.check_at("session-b", now));
+    }
+
+    #[test]
+    fn test_tokens_refill_over_time() {
+        let limiter = RateLimiter::new(60); // one token per second
+        let now = Instant::now();
+
+        for _ in 0..60 {
+            assert!(limiter.check_at("session", now));
+        }
+        assert!(!limiter.check_at("session", now));
+        assert!(limiter.check_at("session", now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_idle_buckets_are_dropped() {
+        let limiter = RateLimiter::new(60);
+        let now = Instant::now();
+
+        for i in 0..100 {
+            assert!(limiter.check_at(&format!("made-up-{}", i), now));
+        }
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 100);
+
+        // A minute later every bucket is full again, so the next new key sweeps them away
+        let later = now + Duration::from_secs(60);
+        assert!(limiter.check_at("session", later));
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+
+        // A bucket that's still refilling is kept, along with its limit
+        for _ in 0..59 {
+            assert!(limiter.check_at("session", later));
+        }
+        assert!(limiter.check_at("other", later));
+        assert!(!limiter.check_at("session", later));
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_zero_disables_limiting() {
+        let limiter = RateLimiter::new(0);
+        let now = Instant::now();
+        assert!((0..1000).all(|_| limiter.check_at("session", now)));
+    }
+}
@@ -0,0 +1,102 @@
+use regex::Regex;
+use crate::config;
+use crate::models::email::{Email, extract_address, sender_header};
+
+// How much of a subject, body or message is kept in logs when redaction is on
+const REDACTED_TEXT_CHARS: usize = 20;
+
+/// Describes `email` for a log line. With `REDACT_LOGS` on, addresses are masked, display names
+/// dropped and the subject truncated.
+pub fn redact(email: &Email) -> String {
+    redact_with(email, config::redact_logs())
+}
+
+fn redact_with(email: &Email, enabled: bool) -> String {
+    let from = sender_header(email).unwrap_or("unknown sender");
+    let subject = email.subject.as_deref().unwrap_or("No Subject");
+    let (from, subject) = if enabled {
+        (mask_address(&extract_address(from)), truncate(subject))
+    } else {
+        (from.to_string(), subject.to_string())
+    };
+    format!("From: {}, Subject: {}, Date: {}", from, subject, email.date.as_deref().unwrap_or("Unknown"))
+}
+
+/// Free text (user messages, queries, body excerpts) for a log line. With `REDACT_LOGS` on, any
+/// addresses in it are masked and it's truncated.
+pub fn redact_text(text: &str) -> String {
+    redact_text_with(text, config::redact_logs())
+}
+
+fn redact_text_with(text: &str, enabled: bool) -> String {
+    if !enabled {
+        return text.to_string();
+    }
+    let address = Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap();
+    let masked = address.replace_all(text, |caps: &regex::Captures| mask_address(&caps[0]));
+    truncate(&masked)
+}
+
+/// Masks an address down to the first letter of its local part and domain, keeping the
+/// top-level domain: "alice.smith@example.com" becomes "a***@e***.com".
+pub fn mask_address(address: &str) -> String {
+    let Some((local, domain)) = address.split_once('@') else {
+        return mask_word(address);
+    };
+    match domain.rsplit_once('.') {
+        Some((name, tld)) => format!("{}@{}.{}", mask_word(local), mask_word(name), tld),
+        None => format!("{}@{}", mask_word(local), mask_word(domain)),
+    }
+}
+
+fn mask_word(word: &str) -> String {
+    match word.chars().next() {
+        Some(first) => format!("{}***", first),
+        None => "***".to_string(),
+    }
+}
+
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= REDACTED_TEXT_CHARS {
+        return text.to_string();
+    }
+    format!("{}\u{2026}", text.chars().take(REDACTED_TEXT_CHARS).collect::<String>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_address_hides_local_part_and_domain() {
+        assert_eq!(mask_address("alice.smith@example.com"), "a***@e***.com");
+        assert_eq!(mask_address("bob@mail.corp.co.uk"), "b***@m***.uk");
+        assert_eq!(mask_address("root@localhost"), "r***@l***");
+        assert_eq!(mask_address("not an address"), "n***");
+    }
+
+    #[test]
+    fn test_redact_email() {
+        let email = Email {
+            from: Some("Alice Smith <alice.smith@example.com>".to_string()),
+            subject: Some("Salary review for the platform team".to_string()),
+            date: Some("Mon, 12 May 2025 09:00:00 +0000".to_string()),
+            body: Some("Confidential".to_string()),
+            ..Default::default()
+        };
+
+        let redacted = redact_with(&email, true);
+        assert_eq!(redacted, "From: a***@e***.com, Subject: Salary review for th\u{2026}, Date: Mon, 12 May 2025 09:00:00 +0000");
+        assert!(!redacted.contains("alice") && !redacted.contains("example") && !redacted.contains("Confidential"));
+
+        assert_eq!(redact_with(&email, false),
+            "From: Alice Smith <alice.smith@example.com>, Subject: Salary review for the platform team, Date: Mon, 12 May 2025 09:00:00 +0000");
+    }
+
+    #[test]
+    fn test_redact_text() {
+        assert_eq!(redact_text_with("to bob@example.com", true), "to b***@e***.com");
+        assert_eq!(redact_text_with("what did the auditors say about the Q3 numbers?", true), "what did the auditor\u{2026}");
+        assert_eq!(redact_text_with("reply to bob@example.com", false), "reply to bob@example.com");
+    }
+}
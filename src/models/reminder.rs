@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A request to bring an email back to the user's attention at `remind_at`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reminder {
+    pub message_id: String,
+    pub remind_at: DateTime<Utc>,
+}
+
+impl Reminder {
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.remind_at <= now
+    }
+}
+
+/// The reminders due at `now`, earliest first.
+pub fn due_reminders(reminders: &[Reminder], now: DateTime<Utc>) -> Vec<Reminder> {
+    let mut due: Vec<Reminder> = reminders.iter().filter(|reminder| reminder.is_due(now)).cloned().collect();
+    due.sort_by_key(|reminder| reminder.remind_at);
+    due
+}
+
+/// Removes and returns the reminders due at `now`, earliest first, leaving the rest in place.
+pub fn take_due_reminders(reminders: &mut Vec<Reminder>, now: DateTime<Utc>) -> Vec<Reminder> {
+    let due = due_reminders(reminders, now);
+    reminders.retain(|reminder| !reminder.is_due(now));
+    due
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn reminder(message_id: &str, day: u32) -> Reminder {
+        Reminder {
+            message_id: message_id.to_string(),
+            remind_at: Utc.with_ymd_and_hms(2025, 5, day, 9, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_due_reminders_are_matched_by_time() {
+        let mut reminders = vec![reminder("later", 20), reminder("second", 13), reminder("first", 12)];
+        let now = Utc.with_ymd_and_hms(2025, 5, 13, 9, 0, 0).unwrap();
+
+        let due: Vec<String> = due_reminders(&reminders, now).into_iter().map(|r| r.message_id).collect();
+        assert_eq!(due, vec!["first", "second"]);
+        assert_eq!(reminders.len(), 3);
+
+        let taken: Vec<String> = take_due_reminders(&mut reminders, now).into_iter().map(|r| r.message_id).collect();
+        assert_eq!(taken, vec!["first", "second"]);
+        assert_eq!(reminders, vec![reminder("later", 20)]);
+        assert!(take_due_reminders(&mut reminders, now).is_empty());
+    }
+}
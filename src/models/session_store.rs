@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use actix_session::storage::{
+    generate_session_key, CookieSessionStore, LoadError, SaveError, SessionKey, SessionStore, UpdateError,
+};
+use actix_web::cookie::time::Duration;
+
+type SessionState = HashMap<String, String>;
+
+struct StoredSession {
+    state: SessionState,
+    expires_at: Instant,
+}
+
+/// A server-side session store that keeps session state in memory and only puts an opaque
+/// key in the cookie. Sessions are shared across workers but lost on restart.
+#[derive(Clone, Default)]
+pub struct MemorySessionStore {
+    sessions: Arc<Mutex<HashMap<String, StoredSession>>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of unexpired sessions currently stored
+    pub fn session_count(&self) -> usize {
+        let now = Instant::now();
+        self.sessions.lock().unwrap().values().filter(|s| s.expires_at > now).count()
+    }
+
+    fn expiry(ttl: &Duration) -> Instant {
+        Instant::now() + std::time::Duration::from_secs(ttl.whole_seconds().max(0) as u64)
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get(session_key.as_ref()) {
+            Some(stored) if stored.expires_at > Instant::now() => Ok(Some(stored.state.clone())),
+            Some(_) => {
+                sessions.remove(session_key.as_ref());
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, session_state: SessionState, ttl: &Duration) -> Result<SessionKey, SaveError> {
+        let session_key = generate_session_key();
+        let mut sessions = self.sessions.lock().unwrap();
+        // Drop expired sessions as we go so the map doesn't grow without bound
+        let now = Instant::now();
+        sessions.retain(|_, stored| stored.expires_at > now);
+        sessions.insert(
+            session_key.as_ref().to_string(),
+            StoredSession { state: session_state, expires_at: Self::expiry(ttl) },
+        );
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.insert(
+            session_key.as_ref().to_string(),
+            StoredSession { state: session_state, expires_at: Self::expiry(ttl) },
+        );
+        Ok(session_key)
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> Result<(), anyhow::Error> {
+        if let Some(stored) = self.sessions.lock().unwrap().get_mut(session_key.as_ref()) {
+            stored.expires_at = Self::expiry(ttl);
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        self.sessions.lock().unwrap().remove(session_key.as_ref());
+        Ok(())
+    }
+}
+
+/// Which session store the server runs with, chosen by `SESSION_STORE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionStoreKind {
+    Cookie,
+    Memory,
+}
+
+/// The configured session store, so `SessionMiddleware` has one concrete type either way.
+pub enum AppSessionStore {
+    Cookie(CookieSessionStore),
+    Memory(MemorySessionStore),
+}
+
+impl SessionStore for AppSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        match self {
+            AppSessionStore::Cookie(store) => store.load(session_key).await,
+            AppSessionStore::Memory(store) => store.load(session_key).await,
+        }
+    }
+
+    async fn save(&self, session_state: SessionState, ttl: &Duration) -> Result<SessionKey, SaveError> {
+        match self {
+            AppSessionStore::Cookie(store) => store.save(session_state, ttl).await,
+            AppSessionStore::Memory(store) => store.save(session_state, ttl).await,
+        }
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        match self {
+            AppSessionStore::Cookie(store) => store.update(session_key, session_state, ttl).await,
+            AppSessionStore::Memory(store) => store.update(session_key, session_state, ttl).await,
+        }
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> Result<(), anyhow::Error> {
+        match self {
+            AppSessionStore::Cookie(store) => store.update_ttl(session_key, ttl).await,
+            AppSessionStore::Memory(store) => store.update_ttl(session_key, ttl).await,
+        }
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        match self {
+            AppSessionStore::Cookie(store) => store.delete(session_key).await,
+            AppSessionStore::Memory(store) => store.delete(session_key).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(value: &str) -> SessionState {
+        HashMap::from([("session_id".to_string(), value.to_string())])
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_round_trip() {
+        let store = MemorySessionStore::new();
+        let ttl = Duration::minutes(10);
+
+        let key = store.save(state("a"), &ttl).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), Some(state("a")));
+
+        let key = store.update(key, state("b"), &ttl).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), Some(state("b")));
+        assert_eq!(store.session_count(), 1);
+
+        store.delete(&key).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), None);
+        assert_eq!(store.session_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_expires_sessions() {
+        let store = MemorySessionStore::new();
+        let key = store.save(state("a"), &Duration::ZERO).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), None);
+
+        // Refreshing the TTL of a live session keeps it loadable
+        let key = store.save(state("b"), &Duration::ZERO).await.unwrap();
+        store.update_ttl(&key, &Duration::minutes(5)).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), Some(state("b")));
+    }
+}
@@ -0,0 +1,72 @@
+use crate::models::email::{Email, body_text};
+use crate::models::snippets::content_words;
+
+// Emails sharing fewer content words than this with the reference aren't considered similar
+const MIN_SHARED_WORDS: usize = 2;
+
+/// Ranks `candidates` by the share of content words (from subject and body) they have in common
+/// with `reference`, returning the best `top_n`. The reference itself and emails with little in
+/// common with it are left out.
+pub fn most_similar(reference: &Email, candidates: Vec<Email>, top_n: usize) -> Vec<Email> {
+    let reference_words = email_words(reference);
+    let mut scored: Vec<(f32, Email)> = candidates.into_iter()
+        .filter(|email| !is_same_email(email, reference))
+        .filter_map(|email| {
+            let words = email_words(&email);
+            let shared = words.intersection(&reference_words).count();
+            let all = words.union(&reference_words).count();
+            (shared >= MIN_SHARED_WORDS).then(|| (shared as f32 / all as f32, email))
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    scored.into_iter().take(top_n).map(|(_, email)| email).collect()
+}
+
+fn email_words(email: &Email) -> std::collections::HashSet<String> {
+    let body = body_text(email).unwrap_or_default();
+    content_words(&format!("{}\n{}", email.subject.as_deref().unwrap_or(""), body))
+}
+
+fn is_same_email(email: &Email, reference: &Email) -> bool {
+    match (&email.message_id, &reference.message_id) {
+        (Some(id), Some(reference_id)) => id == reference_id,
+        _ => email == reference,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(id: &str, subject: &str, body: &str) -> Email {
+        Email {
+            message_id: Some(id.to_string()),
+            subject: Some(subject.to_string()),
+            body: Some(body.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_most_similar_ranks_related_emails_first() {
+        let reference = email("ref", "Q3 budget review", "Please review the Q3 marketing budget before Friday's planning meeting.");
+        let candidates = vec![
+            email("lunch", "Lunch on Thursday?", "Want to grab lunch on Thursday at the new taco place?"),
+            email("budget-followup", "Re: Q3 budget review", "I reviewed the Q3 marketing budget and cut travel by 10%."),
+            email("planning", "Planning meeting agenda", "Agenda for Friday's planning meeting: budget, hiring, roadmap."),
+            email("newsletter", "This week in gardening", "Tomatoes, peppers and the best mulch for summer."),
+            reference.clone(),
+        ];
+
+        let mut ids: Vec<String> = most_similar(&reference, candidates.clone(), 5)
+            .into_iter()
+            .filter_map(|email| email.message_id)
+            .collect();
+        // The unrelated emails and the reference itself are left out
+        ids.sort();
+        assert_eq!(ids, vec!["budget-followup", "planning"]);
+
+        assert_eq!(most_similar(&reference, candidates, 1).len(), 1);
+    }
+}
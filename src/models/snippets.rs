@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+
+// Paragraphs longer than this are split further at sentence boundaries
+const MAX_CHUNK_CHARS: usize = 800;
+
+// Words that say nothing about which part of an email the user means
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "with", "what", "did", "does", "say", "said", "about", "regarding",
+    "that", "this", "from", "email", "mail", "her", "his", "their", "our", "your", "you",
+    "are", "was", "were", "has", "have", "had", "any", "all", "new", "there", "they", "she",
+];
+
+/// Splits an email body into paragraph-sized chunks, breaking long paragraphs between sentences.
+pub fn chunk_body(body: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    for paragraph in body.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        if paragraph.chars().count() <= MAX_CHUNK_CHARS {
+            chunks.push(paragraph.to_string());
+            continue;
+        }
+        let mut current = String::new();
+        for sentence in paragraph.split_inclusive(['.', '!', '?']) {
+            if !current.is_empty() && current.chars().count() + sentence.chars().count() > MAX_CHUNK_CHARS {
+                chunks.push(current.trim().to_string());
+                current.clear();
+            }
+            current.push_str(sentence);
+        }
+        if !current.trim().is_empty() {
+            chunks.push(current.trim().to_string());
+        }
+    }
+    chunks
+}
+
+/// Picks the (up to `max_snippets`) chunks of `body` sharing the most words with `topic`,
+/// in their original order and joined with "[...]" markers. Returns None when no chunk matches,
+/// so callers can fall back to the whole body.
+pub fn relevant_snippets(body: &str, topic: &str, max_snippets: usize) -> Option<String> {
+    let terms = content_words(topic);
+    if terms.is_empty() {
+        return None;
+    }
+
+    let chunks = chunk_body(body);
+    let mut scored: Vec<(usize, usize)> = chunks.iter()
+        .enumerate()
+        .map(|(i, chunk)| (i, content_words(chunk).intersection(&terms).count()))
+        .filter(|(_, score)| *score > 0)
+        .collect();
+    if scored.is_empty() {
+        return None;
+    }
+
+    // Best matches first, then back into reading order
+    scored.sort_by_key(|(i, score)| (std::cmp::Reverse(*score), *i));
+    scored.truncate(max_snippets);
+    scored.sort_by_key(|(i, _)| *i);
+
+    Some(scored.iter()
+        .map(|(i, _)| chunks[*i].as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n[...]\n\n"))
+}
+
+/// Sentences of `source` that `explanation` appears to draw on, judged by how many content
+/// words they share, in their original order. At most `max_quotes` are returned.
+pub fn grounding_quotes(explanation: &str, source: &str, max_quotes: usize) -> Vec<String> {
+    let explained = content_words(explanation);
+    let mut scored: Vec<(usize, usize, String)> = split_sentences(source)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, sentence)| {
+            let words = content_words(&sentence);
+            let shared = words.intersection(&explained).count();
+            // Very short sentences only need all of their words echoed
+            let needed = MIN_SHARED_WORDS.min(words.len()).max(1);
+            (shared >= needed).then_some((i, shared, sentence))
+        })
+        .collect();
+
+    scored.sort_by_key(|(i, shared, _)| (std::cmp::Reverse(*shared), *i));
+    scored.truncate(max_quotes);
+    scored.sort_by_key(|(i, _, _)| *i);
+    scored.into_iter().map(|(_, _, sentence)| sentence).collect()
+}
+
+// Content words a sentence must share with an explanation to count as its source
+const MIN_SHARED_WORDS: usize = 3;
+
+fn split_sentences(text: &str) -> Vec<String> {
+    text.split_inclusive(['.', '!', '?', '\n'])
+        .map(str::trim)
+        .filter(|sentence| sentence.split_whitespace().count() >= 3)
+        .map(str::to_string)
+        .collect()
+}
+
+pub(crate) fn content_words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| word.chars().count() > 2 && !STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROADMAP: &str = "Dear Team,\n\n\
+        1. Product Launch Timeline:\nThe TechPro X500 will launch on August 15th, 2025.\n\n\
+        2. Budget Allocation Changes:\nWe're reallocating 30% of our marketing budget to R&D.\n\n\
+        3. New Office Opening:\nOur new Singapore office will officially open on September 5th, 2025.\n\n\
+        4. Compliance Requirements:\nAll staff must complete the updated compliance training by September 15th.\n\n\
+        Best regards,\nSarah Chen";
+
+    #[test]
+    fn test_relevant_snippets_picks_matching_section() {
+        let snippets = relevant_snippets(ROADMAP, "what did Sarah say about the Singapore office?", 1).unwrap();
+        assert!(snippets.contains("September 5th"));
+        assert!(!snippets.contains("TechPro"));
+    }
+
+    #[test]
+    fn test_relevant_snippets_keeps_reading_order() {
+        let snippets = relevant_snippets(ROADMAP, "compliance training and the launch timeline", 2).unwrap();
+        let launch = snippets.find("TechPro").unwrap();
+        let compliance = snippets.find("compliance training").unwrap();
+        assert!(launch < compliance);
+        assert!(snippets.contains("[...]"));
+    }
+
+    #[test]
+    fn test_relevant_snippets_without_match() {
+        assert_eq!(relevant_snippets(ROADMAP, "the Tokyo warehouse", 3), None);
+        assert_eq!(relevant_snippets(ROADMAP, "what did she say about it", 3), None);
+    }
+
+    #[test]
+    fn test_grounding_quotes_finds_referenced_sentences() {
+        let explanation = "Sarah says the Singapore office opens on September 5th, 2025, and all staff \
+            must finish compliance training by September 15th.";
+        let quotes = grounding_quotes(explanation, ROADMAP, 3);
+        assert_eq!(quotes, vec![
+            "Our new Singapore office will officially open on September 5th, 2025.",
+            "All staff must complete the updated compliance training by September 15th.",
+        ]);
+    }
+
+    #[test]
+    fn test_grounding_quotes_ignores_unrelated_text() {
+        assert!(grounding_quotes("This is a friendly greeting.", ROADMAP, 3).is_empty());
+        assert_eq!(grounding_quotes("Singapore office September compliance training", ROADMAP, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_chunk_body_splits_long_paragraphs() {
+        let sentence = "This sentence is part of a very long paragraph. ";
+        let body = sentence.repeat(40);
+        let chunks = chunk_body(&body);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= MAX_CHUNK_CHARS));
+    }
+}
@@ -0,0 +1,89 @@
+use std::fmt;
+use regex::Regex;
+use crate::models::email::Email;
+
+/// How urgently an email seems to need attention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Urgency {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Urgency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Urgency::Low => write!(f, "low"),
+            Urgency::Medium => write!(f, "medium"),
+            Urgency::High => write!(f, "high"),
+        }
+    }
+}
+
+const HIGH_URGENCY_TERMS: &[&str] = &[
+    "urgent", "asap", "immediately", "emergency", "critical", "action required",
+    "right away", "end of day", "eod", "time-sensitive", "time sensitive",
+];
+
+const MEDIUM_URGENCY_TERMS: &[&str] = &[
+    "deadline", "due", "reminder", "important", "soon", "follow up", "follow-up",
+    "tomorrow", "this week", "overdue", "please respond",
+];
+
+/// Classifies an email's urgency with a keyword heuristic over its subject and body,
+/// so it works without the LLM.
+pub fn classify_urgency(email: &Email) -> Urgency {
+    let text = format!(
+        "{} {}",
+        email.subject.as_deref().unwrap_or(""),
+        email.body.as_deref().unwrap_or("")
+    ).to_lowercase();
+
+    if contains_any_term(&text, HIGH_URGENCY_TERMS) {
+        Urgency::High
+    } else if contains_any_term(&text, MEDIUM_URGENCY_TERMS) {
+        Urgency::Medium
+    } else {
+        Urgency::Low
+    }
+}
+
+// Matches whole words/phrases only, so "due" doesn't fire on "residue"
+fn contains_any_term(text: &str, terms: &[&str]) -> bool {
+    terms.iter().any(|term| {
+        let pattern = format!(r"\b{}\b", regex::escape(term));
+        Regex::new(&pattern).map(|re| re.is_match(text)).unwrap_or(false)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(subject: &str, body: &str) -> Email {
+        Email {
+            subject: Some(subject.to_string()),
+            body: Some(body.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_classify_urgency_keywords() {
+        assert_eq!(classify_urgency(&email("Urgent: Report submission", "Need it today")), Urgency::High);
+        assert_eq!(classify_urgency(&email("Report", "Please send it ASAP.")), Urgency::High);
+        assert_eq!(classify_urgency(&email("Invoice", "Payment deadline is next month.")), Urgency::Medium);
+        assert_eq!(classify_urgency(&email("Newsletter", "Our weekly roundup of news.")), Urgency::Low);
+    }
+
+    #[test]
+    fn test_classify_urgency_matches_whole_words() {
+        // "residue" contains "due" and "geodesic" contains "eod", neither should count
+        assert_eq!(classify_urgency(&email("Lab results", "No residue found in the geodesic sample.")), Urgency::Low);
+    }
+
+    #[test]
+    fn test_classify_urgency_without_content() {
+        assert_eq!(classify_urgency(&Email::default()), Urgency::Low);
+    }
+}
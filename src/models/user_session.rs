@@ -1,9 +1,115 @@
-use crate::models::email_db::EmailDB;
+use crate::config;
+use crate::models::email::{DraftEmail, Email};
+use crate::models::email_db::{EmailDB, EmailDBError};
+use crate::models::email_query::{QueryCriteria, Relaxation};
+use crate::models::persona::Persona;
+use crate::models::reminder::Reminder;
 use ollama_rs::generation::chat::ChatMessage;
+use serde::Serialize;
+
+/// How far along loading the user's inbox into the mailbox is. Sessions are created before
+/// their emails are loaded, so a large inbox doesn't hold up the request that starts them.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LoadStatus {
+    /// Fetching the inbox from Gmail
+    Fetching,
+    /// Storing the fetched emails in the search index
+    Indexing { email_count: usize },
+    Ready,
+    Failed { error: String },
+}
 
 #[derive(Clone)]
 pub struct UserSession {
     pub history: Vec<ChatMessage>,
     pub mailbox: EmailDB,
+    /// The most recently deleted email, kept so the deletion can be undone
+    pub last_deleted: Option<Email>,
+    /// Senders (addresses or names) whose emails should rank first, set from chat
+    pub priority_senders: Vec<String>,
+    /// The last Reply or Compose draft, so follow-ups like "make it shorter" can revise it
+    pub last_draft: Option<DraftEmail>,
+    /// A bulk delete waiting for the user to confirm it
+    pub pending_bulk_delete: Option<QueryCriteria>,
+    /// Whether the mailbox has finished loading
+    pub load_status: LoadStatus,
+    /// Emails the user asked to be reminded about, set from chat
+    pub reminders: Vec<Reminder>,
+    /// The tone the user asked for in chat, overriding `PERSONA`
+    pub persona: Option<Persona>,
+    /// The email last explained, displayed or replied to, for follow-ups like "find emails
+    /// similar to this one"
+    pub last_email: Option<Email>,
 }
 
+impl UserSession {
+    pub fn new(mailbox: EmailDB) -> Self {
+        UserSession {
+            history: Vec::new(),
+            mailbox,
+            last_deleted: None,
+            priority_senders: Vec::new(),
+            last_draft: None,
+            pending_bulk_delete: None,
+            load_status: LoadStatus::Ready,
+            reminders: Vec::new(),
+            persona: None,
+            last_email: None,
+        }
+    }
+
+    /// Searches the mailbox, ranking this session's priority senders first. Vague criteria only
+    /// return the few best matches.
+    pub async fn search(&self, mut criteria: QueryCriteria) -> Result<Vec<Email>, EmailDBError> {
+        criteria.priority_senders = self.priority_senders.clone();
+        let bounds = criteria.clone();
+        let matches = self.mailbox.search_emails_by_criteria(criteria).await?;
+        Ok(bounds.cap_weak_matches(matches, config::weak_query_context_emails()))
+    }
+
+    /// Like `search`, but criteria that match nothing are broadened step by step (see
+    /// `QueryCriteria::relaxations`). Also returns the constraints that were dropped. The last step
+    /// drops the sender, so this is for finding emails to read, never ones to reply to or delete.
+    pub async fn search_relaxed(&self, mut criteria: QueryCriteria) -> Result<(Vec<Email>, Vec<Relaxation>), EmailDBError> {
+        criteria.priority_senders = self.priority_senders.clone();
+        let (matches, dropped) = self.mailbox.search_with_relaxation(criteria.clone()).await?;
+        // Cap by the criteria that actually matched, which may be much vaguer than the original
+        let bounds = match dropped.len() {
+            0 => criteria,
+            steps => criteria.relaxations().swap_remove(steps - 1).1,
+        };
+        Ok((bounds.cap_weak_matches(matches, config::weak_query_context_emails()), dropped))
+    }
+
+    /// Drops everything the session remembers about an email, so nothing can bring it back:
+    /// the undo copy, the current email and its reminders.
+    pub fn forget(&mut self, message_id: &str) {
+        let is_forgotten = |email: &Option<Email>| email.as_ref().and_then(|e| e.message_id.as_deref()) == Some(message_id);
+        if is_forgotten(&self.last_deleted) {
+            self.last_deleted = None;
+        }
+        if is_forgotten(&self.last_email) {
+            self.last_email = None;
+        }
+        self.reminders.retain(|reminder| reminder.message_id != message_id);
+    }
+
+    /// Adds a priority sender, returning false if it was already there.
+    pub fn add_priority_sender(&mut self, sender: &str) -> bool {
+        let sender = sender.trim().to_lowercase();
+        if self.priority_senders.contains(&sender) {
+            return false;
+        }
+        self.priority_senders.push(sender);
+        true
+    }
+
+    /// Removes a priority sender, returning false if it wasn't there.
+    pub fn remove_priority_sender(&mut self, sender: &str) -> bool {
+        let sender = sender.trim().to_lowercase();
+        let before = self.priority_senders.len();
+        self.priority_senders.retain(|existing| *existing != sender);
+        self.priority_senders.len() != before
+    }
+}
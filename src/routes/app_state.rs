@@ -1,7 +1,36 @@
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use meilisearch_sdk::client::Client;
+use crate::config::Config;
 use crate::models::global_session_manager::GlobalSessionManager;
+use crate::models::rate_limiter::RateLimiter;
 
 #[derive(Clone)]
 pub struct AppState {
     pub session_manager: GlobalSessionManager,
+    pub rate_limiter: RateLimiter,
+    /// Limits how many sessions load their inboxes at the same time
+    pub load_slots: Arc<Semaphore>,
+    /// The settings loaded at startup
+    pub config: Config,
+    /// MeiliSearch client shared by every session's mailbox
+    pub meilisearch: Arc<Client>,
 }
 
+/// Local development settings, for tests that need an `AppState` but never connect.
+#[cfg(test)]
+pub(crate) fn test_config() -> Config {
+    Config {
+        meilisearch_url: "http://localhost:7700".to_string(),
+        meilisearch_search_key: "test".to_string(),
+        meilisearch_admin_key: "test".to_string(),
+        meilisearch_index: "emails".to_string(),
+        ollama_url: "http://localhost:11434".to_string(),
+    }
+}
+
+/// A client for `test_config()`'s MeiliSearch that is never used to connect.
+#[cfg(test)]
+pub(crate) fn test_client() -> Arc<Client> {
+    Arc::new(Client::new("http://localhost:7700", Some("test")).unwrap())
+}
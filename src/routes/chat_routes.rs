@@ -1,16 +1,35 @@
 use actix_web::{post, web, Responder};
 use actix_session::Session;
-use serde_json::Value;
+use crate::handlers::chat_handler::{chat_request_json_config, ChatRequest, DraftRequest};
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(stream_greeting);
+    // The chat body error handler is scoped to the routes that take a ChatRequest
+    cfg.service(web::resource("/stream").app_data(chat_request_json_config()).route(web::post().to(stream_greeting)))
+        .service(web::resource("/classify").app_data(chat_request_json_config()).route(web::post().to(classify)))
+        .service(draft);
 }
 
-#[post("/stream")]
 async fn stream_greeting(
     data: web::Data<crate::routes::app_state::AppState>,
     session: Session,
-    req_body: web::Json<Value>
+    req_body: web::Json<ChatRequest>
 ) -> impl Responder {
     crate::handlers::chat_handler::handle_chat_request(data, session, req_body).await
-}
\ No newline at end of file
+}
+
+async fn classify(
+    data: web::Data<crate::routes::app_state::AppState>,
+    session: Session,
+    req_body: web::Json<ChatRequest>
+) -> impl Responder {
+    crate::handlers::chat_handler::handle_classify_request(data, session, req_body).await
+}
+
+#[post("/draft")]
+async fn draft(
+    data: web::Data<crate::routes::app_state::AppState>,
+    session: Session,
+    req_body: web::Json<DraftRequest>
+) -> impl Responder {
+    crate::handlers::chat_handler::handle_draft_request(data, session, req_body).await
+}
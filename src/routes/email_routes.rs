@@ -0,0 +1,69 @@
+use actix_web::{get, post, web, Responder};
+use actix_session::Session;
+use serde::Deserialize;
+use crate::routes::app_state::AppState;
+
+#[derive(Deserialize)]
+pub struct EmailPageQuery {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_emails)
+        .service(get_email)
+        .service(export_emails)
+        .service(get_digest)
+        .service(reconfigure_index)
+        .service(list_reminders);
+}
+
+#[get("/emails")]
+async fn list_emails(
+    data: web::Data<AppState>,
+    session: Session,
+    query: web::Query<EmailPageQuery>,
+) -> impl Responder {
+    crate::handlers::email_handler::list_emails(data, session, query.limit, query.offset).await
+}
+
+#[get("/email/{message_id}")]
+async fn get_email(
+    data: web::Data<AppState>,
+    session: Session,
+    path: web::Path<String>,
+) -> impl Responder {
+    crate::handlers::email_handler::get_email(data, session, path.into_inner()).await
+}
+
+#[get("/export")]
+async fn export_emails(
+    data: web::Data<AppState>,
+    session: Session,
+) -> impl Responder {
+    crate::handlers::email_handler::export_emails(data, session).await
+}
+
+#[get("/digest")]
+async fn get_digest(
+    data: web::Data<AppState>,
+    session: Session,
+) -> impl Responder {
+    crate::handlers::email_handler::get_digest(data, session).await
+}
+
+#[post("/admin/reindex")]
+async fn reconfigure_index(
+    data: web::Data<AppState>,
+    session: Session,
+) -> impl Responder {
+    crate::handlers::email_handler::reconfigure_index(data, session).await
+}
+
+#[get("/reminders")]
+async fn list_reminders(
+    data: web::Data<AppState>,
+    session: Session,
+) -> impl Responder {
+    crate::handlers::email_handler::list_due_reminders(data, session).await
+}
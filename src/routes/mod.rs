@@ -1,4 +1,5 @@
 pub mod app_state;
 pub mod session_routes;
 pub mod oauth_routes;
-pub mod chat_routes;
\ No newline at end of file
+pub mod chat_routes;
+pub mod email_routes;
\ No newline at end of file
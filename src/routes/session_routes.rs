@@ -5,7 +5,7 @@ use serde_json::json;
 use log::{error};
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(init_session);
+    cfg.service(init_session).service(session_status);
 }
 
 
@@ -18,4 +18,9 @@ async fn init_session(data: web::Data<AppState>, session: Session) -> impl Respo
             HttpResponse::InternalServerError().json(json!({"error": e.to_string()}))
         }
     }
-}
\ No newline at end of file
+}
+
+#[get("/session_status")]
+async fn session_status(data: web::Data<AppState>, session: Session) -> impl Responder {
+    crate::handlers::session_handler::session_status(data, session).await
+}
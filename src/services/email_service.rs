@@ -1,7 +1,12 @@
 use log::{info, warn};
 use crate::services::gmail_service;
 use crate::models::email::Email;
-use crate::models::email_db::EmailDB;
+use crate::models::email_db::{EmailDB, EmailDBError};
+use crate::models::importance::score_mailbox;
+use crate::config;
+
+/// How many recent sent messages are read to learn who the user writes to
+const SENT_SAMPLE_SIZE: usize = 100;
 
 pub async fn load_emails() -> Result<Vec<Email>, Box<dyn std::error::Error>> {
     info!("Load email Handler Called...");
@@ -19,12 +24,18 @@ pub async fn load_emails() -> Result<Vec<Email>, Box<dyn std::error::Error>> {
     
     // Fetch new emails from Gmail
     info!("Fetching emails from Gmail...");
-    let emails = gmail_service::get_inbox_messages().await?;
+    let mut emails = gmail_service::get_inbox_messages(&config::gmail_query(), config::max_emails()).await?;
+    // Who the user writes to is an importance signal, and the inbox holds little of their own mail
+    let sent = gmail_service::get_inbox_messages("in:sent", SENT_SAMPLE_SIZE).await.unwrap_or_else(|e| {
+        warn!("Couldn't fetch sent mail for importance scoring: {}", e);
+        Vec::new()
+    });
+    score_mailbox(&mut emails, &sent, config::user_email().as_deref());
     
     // Store the new emails in the database
     if !emails.is_empty() {
         info!("Storing {} new emails in database...", emails.len());
-        if let Err(e) = email_db.store_emails(&emails).await {
+        if let Err(e) = store_in_batches(&email_db, &emails, config::email_batch_size()).await {
             warn!("Failed to store emails in database: {}", e);
             // Continue even if storing fails
         }
@@ -35,6 +46,17 @@ pub async fn load_emails() -> Result<Vec<Email>, Box<dyn std::error::Error>> {
     Ok(emails)
 }
 
+/// Stores `emails` in `email_db` `batch_size` at a time, so a large inbox is indexed in several
+/// smaller requests rather than one.
+pub async fn store_in_batches(email_db: &EmailDB, emails: &[Email], batch_size: usize) -> Result<(), EmailDBError> {
+    let batches = emails.len().div_ceil(batch_size.max(1));
+    for (i, batch) in emails.chunks(batch_size.max(1)).enumerate() {
+        info!("Storing batch {}/{} ({} emails)", i + 1, batches, batch.len());
+        email_db.store_emails(batch).await?;
+    }
+    Ok(())
+}
+
 // Creates a new session manager instance.
 pub fn create_session_manager() -> crate::models::global_session_manager::GlobalSessionManager {
     crate::models::global_session_manager::GlobalSessionManager::new()
@@ -1,15 +1,19 @@
-use log::{info, error, debug};
+use log::{info, error, debug, warn};
 use reqwest;
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
 use std::fs;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use futures::stream::{self, StreamExt};
 use base64::{engine::general_purpose::URL_SAFE, Engine as _};
-use oauth2::TokenResponse;
-use crate::models::email::Email;
+use oauth2::{RequestTokenError, TokenResponse};
+use oauth2::basic::{BasicErrorResponse, BasicErrorResponseType};
+use crate::config;
+use crate::models::email::{Email, detect_language};
+use crate::models::calendar::{CalendarEvent, parse_ics};
 
 const TOKEN_CACHE_FILE: &str = "tokencache.json";
-const GMAIL_API_URL: &str = "https://gmail.googleapis.com/gmail/v1/users/me/messages?q=is:inbox";
+const GMAIL_MESSAGES_URL: &str = "https://gmail.googleapis.com/gmail/v1/users/me/messages";
 
 #[derive(Serialize, Deserialize)]
 pub struct TokenCache {
@@ -20,35 +24,6 @@ pub struct TokenCache {
     pub scope: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Message {
-    #[serde(default)]
-    pub thread_id: Option<String>,
-    #[serde(default)]
-    pub id: String,
-    pub payload: Option<MessagePayload>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct MessagePayload {
-    pub headers: Vec<Header>,
-    pub body: Option<MessageBody>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Header {
-    pub name: String,
-    pub value: String,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct MessageBody {
-    pub data: Option<String>,
-}
-
-/// A simplified Email struct.
-
-
 /// Reads the access token from the cache file.
 pub fn read_access_token() -> Result<String, Box<dyn std::error::Error>> {
     let file_content = fs::read_to_string(TOKEN_CACHE_FILE)?;
@@ -56,82 +31,100 @@ pub fn read_access_token() -> Result<String, Box<dyn std::error::Error>> {
     Ok(token_cache.access_token)
 }
 
-/// Fetches inbox messages from the Gmail API.
-pub async fn get_inbox_messages() -> Result<Vec<Email>, Box<dyn std::error::Error>> {
-    info!("Getting Inbox messages");
+/// The Gmail message-list URL for a search `query` such as "is:inbox" or "label:work newer_than:7d",
+/// listing at most `max_results` messages.
+fn messages_list_url(query: &str, max_results: usize) -> String {
+    let mut url = url::Url::parse(GMAIL_MESSAGES_URL).expect("GMAIL_MESSAGES_URL is a valid URL");
+    url.query_pairs_mut()
+        .append_pair("q", query)
+        .append_pair("maxResults", &max_results.to_string());
+    url.to_string()
+}
+
+/// The first `max_emails` message ids in a message-list response, which Gmail returns newest
+/// first. Since the list asked for at most `max_emails`, a `nextPageToken` is what shows that
+/// more messages matched than were loaded.
+fn newest_message_ids(list_response: &Value, max_emails: usize) -> Vec<String> {
+    let mut message_ids: Vec<String> = list_response["messages"]
+        .as_array()
+        .map(|messages| messages.iter()
+            .filter_map(|m| m.get("id").and_then(|id| id.as_str()).map(|s| s.to_string()))
+            .collect())
+        .unwrap_or_default();
+    message_ids.truncate(max_emails);
+
+    if list_response.get("nextPageToken").is_some() {
+        match list_response["resultSizeEstimate"].as_u64() {
+            Some(estimate) => info!("About {} messages match; loading only the {} most recent (MAX_EMAILS)", estimate, message_ids.len()),
+            None => info!("More messages match; loading only the {} most recent (MAX_EMAILS)", message_ids.len()),
+        }
+    }
+    message_ids
+}
+
+/// Fetches up to `max_emails` of the most recent messages matching the Gmail search `query`
+/// (see `config::gmail_query` and `config::max_emails`).
+pub async fn get_inbox_messages(query: &str, max_emails: usize) -> Result<Vec<Email>, Box<dyn std::error::Error>> {
+    info!("Getting messages matching '{}'", query);
+    let list_url = messages_list_url(query, max_emails);
     let access_token = read_access_token()?;
     let client = reqwest::Client::builder()
         .timeout(Duration::from_secs(10))
         .build()?;
 
     info!("Fetching inbox messages from Gmail API...");
-    let response = client
-        .get(GMAIL_API_URL)
-        .bearer_auth(&access_token)
-        .send()
-        .await?;
+    let retry_policy = RetryPolicy::from_config();
+    let response = send_with_retry(&retry_policy, "inbox list", || {
+        client.get(&list_url).bearer_auth(&access_token).send()
+    }).await?;
 
     if response.status().is_success() {
         info!("Successfully fetched inbox ID list");
         let messages_response: Value = response.json().await?;
-        let message_ids: Vec<String> = messages_response["messages"]
-            .as_array()
-            .unwrap_or(&vec![])
-            .iter()
-            .filter_map(|m| m.get("id").and_then(|id| id.as_str()).map(|s| s.to_string()))
-            .collect();
-
-        let mut emails = Vec::new();
-        info!("Loading email details");
-        for message_id in message_ids.iter() {
-            let message_url = format!(
-                "https://gmail.googleapis.com/gmail/v1/users/me/messages/{}",
-                message_id
-            );
-            debug!("Fetching message details for ID: {}", message_id);
-            let message_response = client
-                .get(&message_url)
-                .bearer_auth(&access_token)
-                .send()
-                .await?;
-
-            if message_response.status().is_success() {
-                debug!("Successfully fetched message details for ID: {}", message_id);
-                let message: Value = message_response.json().await?;
-                let headers: &[Value] = message["payload"]["headers"]
-                    .as_array()
-                    .map(|arr| &arr[..])
-                    .unwrap_or(&[]);
-
-                let from = get_header(headers, "From");
-                let to = get_header(headers, "To");
-                let date = get_header(headers, "Date");
-                let subject = get_header(headers, "Subject");
-                let body_data = extract_plain_text_body(&message["payload"]);
-
-                // Decode the base64url-encoded body.
-                let decoded_body = if let Some(data) = body_data {
-                    match URL_SAFE.decode(data) {
-                        Ok(bytes) => String::from_utf8(bytes).ok(),
-                        Err(e) => {
-                            error!("Failed to decode base64 body for message {}: {}", message_id, e);
-                            None
-                        }
-                    }
-                } else {
-                    None
-                };
-
-                emails.push(Email {
-                    from,
-                    to,
-                    date,
-                    subject,
-                    body: decoded_body,
-                    message_id: Some(message_id.to_string()),
-                });
+        let message_ids = newest_message_ids(&messages_response, max_emails);
+
+        let concurrency = config::gmail_fetch_concurrency();
+        info!("Loading details for {} emails ({} at a time)", message_ids.len(), concurrency);
+        let started = Instant::now();
+
+        // Fetch the details concurrently, tagging each result with its position so the
+        // inbox order survives buffer_unordered
+        let mut results: Vec<(usize, Result<Option<Email>, String>)> = stream::iter(message_ids.iter().enumerate())
+            .map(|(position, message_id)| {
+                let client = &client;
+                let access_token = &access_token;
+                let retry_policy = &retry_policy;
+                async move {
+                    let result = fetch_message(client, access_token, message_id, retry_policy)
+                        .await
+                        .map_err(|e| e.to_string());
+                    (position, result)
+                }
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+        results.sort_by_key(|(position, _)| *position);
+
+        let keep_partial = config::gmail_partial_results();
+        let mut emails = Vec::with_capacity(results.len());
+        let mut failed = 0;
+        for (_, result) in results {
+            match result {
+                Ok(Some(email)) => emails.push(email),
+                Ok(None) => {}
+                // Keep what was fetched rather than discarding the whole inbox load
+                Err(e) if keep_partial => {
+                    warn!("Skipping a message that could not be fetched: {}", e);
+                    failed += 1;
+                }
+                Err(e) => return Err(e.into()),
             }
         }
+        if failed > 0 {
+            warn!("Returning a partial inbox: {} message(s) could not be fetched", failed);
+        }
+        info!("Loaded {} emails in {:?}", emails.len(), started.elapsed());
         Ok(emails)
     } else {
         error!("Failed to fetch inbox: {}", response.status());
@@ -139,39 +132,364 @@ pub async fn get_inbox_messages() -> Result<Vec<Email>, Box<dyn std::error::Erro
     }
 }
 
-/// Refreshes the OAuth token using the provided OAuth client.
+/// How rate-limited Gmail requests are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Delay before the first retry; doubles on each further attempt
+    pub base_delay: Duration,
+    /// Upper bound on any single wait, including one requested by `Retry-After`
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config() -> Self {
+        RetryPolicy {
+            max_retries: config::gmail_max_retries(),
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+
+    /// How long to wait before retry number `attempt` (0-based): the server's `Retry-After` if
+    /// given, otherwise exponential backoff, capped at `max_delay`, plus up to `base_delay` of
+    /// jitter so concurrent fetches don't retry in lockstep.
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = retry_after.unwrap_or_else(|| self.base_delay.saturating_mul(2u32.saturating_pow(attempt)));
+        let jitter_range = self.base_delay.as_millis().max(1) as u64;
+        let jitter = Duration::from_millis(jitter_seed() % jitter_range);
+        backoff.min(self.max_delay) + jitter
+    }
+}
+
+fn jitter_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// Gmail signals quota exhaustion with 429, or with 403 and one of these reasons in the body.
+fn is_rate_limit_reason(body: &str) -> bool {
+    body.contains("rateLimitExceeded") || body.contains("userRateLimitExceeded")
+}
+
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Sends the request built by `send`, retrying with backoff while Gmail reports it is rate
+/// limited. Any other response is returned as-is for the caller to handle.
+async fn send_with_retry<F, Fut>(
+    policy: &RetryPolicy,
+    what: &str,
+    mut send: F,
+) -> Result<reqwest::Response, Box<dyn std::error::Error>>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<reqwest::Response, reqwest::Error>>,
+{
+    let mut attempt = 0;
+    loop {
+        let response = send().await?;
+        let status = response.status();
+        let wait = retry_after(&response);
+
+        if status == reqwest::StatusCode::FORBIDDEN {
+            let body = response.text().await?;
+            if !is_rate_limit_reason(&body) {
+                return Err(format!("Gmail refused {}: {} {}", what, status, body).into());
+            }
+        } else if status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Ok(response);
+        }
+
+        if attempt >= policy.max_retries {
+            error!("Gmail still rate limiting {} after {} retries", what, attempt);
+            return Err(format!("Gmail rate limit exceeded for {} after {} retries", what, attempt).into());
+        }
+        let delay = policy.delay(attempt, wait);
+        warn!("Gmail rate limited {} ({}); retrying in {:?}", what, status, delay);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Fetches and decodes a single message, returning None if Gmail doesn't return it.
+async fn fetch_message(
+    client: &reqwest::Client,
+    access_token: &str,
+    message_id: &str,
+    retry_policy: &RetryPolicy,
+) -> Result<Option<Email>, Box<dyn std::error::Error>> {
+    let message_url = format!(
+        "https://gmail.googleapis.com/gmail/v1/users/me/messages/{}",
+        message_id
+    );
+    debug!("Fetching message details for ID: {}", message_id);
+    let message_response = send_with_retry(retry_policy, message_id, || {
+        client.get(&message_url).bearer_auth(access_token).send()
+    }).await?;
+
+    if !message_response.status().is_success() {
+        error!("Failed to fetch message {}: {}", message_id, message_response.status());
+        return Ok(None);
+    }
+
+    debug!("Successfully fetched message details for ID: {}", message_id);
+    let message: Value = message_response.json().await?;
+    let headers: &[Value] = message["payload"]["headers"]
+        .as_array()
+        .map(|arr| &arr[..])
+        .unwrap_or(&[]);
+
+    let from = get_header(headers, "From");
+    let to = get_header(headers, "To");
+    let cc = get_header(headers, "Cc");
+    let date = get_header(headers, "Date");
+    let subject = get_header(headers, "Subject");
+    let list_unsubscribe = get_header(headers, "List-Unsubscribe");
+    let precedence = get_header(headers, "Precedence");
+    let reply_to = get_header(headers, "Reply-To");
+    let sender = get_header(headers, "Sender");
+    let (body_data, content_type) = match extract_plain_text_body(&message["payload"]) {
+        Some((data, content_type)) => (Some(data), content_type),
+        None => (None, None),
+    };
+    let attachment_types = attachment_types(&message["payload"]);
+
+    // Decode the base64url-encoded body.
+    let decoded_body = if let Some(data) = body_data {
+        match URL_SAFE.decode(data) {
+            Ok(bytes) => String::from_utf8(bytes).ok(),
+            Err(e) => {
+                error!("Failed to decode base64 body for message {}: {}", message_id, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let language = decoded_body.as_deref().and_then(|body| detect_language(body, content_type.as_deref()));
+    let event = match calendar_part(&message["payload"]) {
+        Some(part) => calendar_event(client, access_token, message_id, part, retry_policy).await,
+        None => None,
+    };
+
+    Ok(Some(Email {
+        from,
+        to,
+        cc,
+        date,
+        subject,
+        body: decoded_body,
+        message_id: Some(message_id.to_string()),
+        language,
+        // Scored against the whole mailbox once it has been fetched
+        importance: None,
+        list_unsubscribe,
+        precedence,
+        attachment_count: attachment_types.len(),
+        attachment_types,
+        reply_to,
+        sender,
+        event,
+        content_type,
+        thread_id: message["threadId"].as_str().map(str::to_string),
+    }))
+}
+
+/// Reads and parses a message's calendar part, fetching it from the attachments endpoint when
+/// Gmail doesn't include its data inline. Failures are logged and treated as no event.
+async fn calendar_event(
+    client: &reqwest::Client,
+    access_token: &str,
+    message_id: &str,
+    part: &Value,
+    retry_policy: &RetryPolicy,
+) -> Option<CalendarEvent> {
+    let data = match part["body"]["data"].as_str() {
+        Some(data) => data.to_string(),
+        None => {
+            let attachment_id = part["body"]["attachmentId"].as_str()?;
+            let url = format!("{}/{}/attachments/{}", GMAIL_MESSAGES_URL, message_id, attachment_id);
+            let response = send_with_retry(retry_policy, message_id, || {
+                client.get(&url).bearer_auth(access_token).send()
+            }).await;
+            let attachment: Value = match response {
+                Ok(response) if response.status().is_success() => response.json().await.ok()?,
+                Ok(response) => {
+                    warn!("Couldn't fetch the invitation for message {}: {}", message_id, response.status());
+                    return None;
+                }
+                Err(e) => {
+                    warn!("Couldn't fetch the invitation for message {}: {}", message_id, e);
+                    return None;
+                }
+            };
+            attachment["data"].as_str()?.to_string()
+        }
+    };
+    let ics = URL_SAFE.decode(data).ok().and_then(|bytes| String::from_utf8(bytes).ok())?;
+    parse_ics(&ics)
+}
+
+/// Why a token refresh failed.
+#[derive(Debug, thiserror::Error)]
+pub enum RefreshError {
+    /// Google rejected the refresh token (`invalid_grant`): consent was revoked or the token expired
+    #[error("Your Gmail access was revoked or has expired. Please sign in again.")]
+    Revoked,
+
+    /// Google doesn't recognise the OAuth client (`invalid_client`)
+    #[error("The OAuth client was rejected ({0}); check cfg/client_secret.json and sign in again.")]
+    InvalidClient(String),
+
+    #[error("No refresh token available. Please re-authenticate.")]
+    NoRefreshToken,
+
+    /// Google couldn't be reached or answered with something unreadable; trying later may work
+    #[error("Couldn't refresh the Gmail token: {0}")]
+    Transient(String),
+
+    #[error("Token refresh failed: {0}")]
+    Other(String),
+}
+
+impl RefreshError {
+    /// Whether the user has to sign in again, rather than retry later. Only transient failures
+    /// are worth retrying; anything else won't fix itself.
+    pub fn needs_reauthentication(&self) -> bool {
+        !matches!(self, RefreshError::Transient(_))
+    }
+}
+
+/// Sorts a failed refresh exchange into the errors the caller treats differently.
+fn classify_refresh_error<RE: std::error::Error + 'static>(
+    error: RequestTokenError<RE, BasicErrorResponse>,
+) -> RefreshError {
+    match error {
+        RequestTokenError::ServerResponse(response) => match response.error() {
+            BasicErrorResponseType::InvalidGrant => RefreshError::Revoked,
+            BasicErrorResponseType::InvalidClient | BasicErrorResponseType::UnauthorizedClient => {
+                RefreshError::InvalidClient(response.to_string())
+            }
+            _ => RefreshError::Other(response.to_string()),
+        },
+        RequestTokenError::Request(e) => RefreshError::Transient(e.to_string()),
+        // Outages tend to come back as HTML error pages rather than an OAuth error body
+        RequestTokenError::Parse(e, _) => RefreshError::Transient(format!("unreadable response from Google: {}", e)),
+        RequestTokenError::Other(message) => RefreshError::Other(message),
+    }
+}
+
+/// How many times a refresh that failed for a transient reason is retried
+const REFRESH_RETRIES: u32 = 2;
+
+/// Refreshes the OAuth token using the provided OAuth client, retrying transient failures.
 ///
 /// Note: This function now requires you to supply an OAuth2 BasicClient
 /// (constructed in your HTTP handler) because token refreshing is a business
 /// logic operation that should not build HTTP responses itself.
+///
+/// If Google reports the refresh token revoked (`invalid_grant`) the stale token cache is
+/// deleted, so the next auth check sends the user to sign in.
 pub async fn refresh_token(
     oauth_client: &oauth2::basic::BasicClient,
-) -> Result<String, Box<dyn std::error::Error>> {
+) -> Result<String, RefreshError> {
+    refresh_token_with(TOKEN_CACHE_FILE, |refresh_token| async move {
+        oauth_client
+            .exchange_refresh_token(&refresh_token)
+            .request_async(oauth2::reqwest::async_http_client)
+            .await
+            .map_err(classify_refresh_error)
+    }).await
+}
+
+// `refresh_token` with the token cache at `cache_file` and the exchange with Google done by `exchange`
+async fn refresh_token_with<F, Fut>(cache_file: &str, mut exchange: F) -> Result<String, RefreshError>
+where
+    F: FnMut(oauth2::RefreshToken) -> Fut,
+    Fut: std::future::Future<Output = Result<oauth2::basic::BasicTokenResponse, RefreshError>>,
+{
     // Read the current token cache.
-    let file_content = fs::read_to_string(TOKEN_CACHE_FILE)?;
-    let token_cache: TokenCache = serde_json::from_str(&file_content)?;
+    let file_content = fs::read_to_string(cache_file).map_err(|e| RefreshError::Other(e.to_string()))?;
+    let token_cache: TokenCache = serde_json::from_str(&file_content).map_err(|e| RefreshError::Other(e.to_string()))?;
 
     // Ensure we have a refresh token.
-    let current_refresh_token = match token_cache.refresh_token {
-        Some(rt) => rt,
-        None => return Err("No refresh token available. Please re-authenticate.".into())
-    };
+    let current_refresh_token = oauth2::RefreshToken::new(token_cache.refresh_token.ok_or(RefreshError::NoRefreshToken)?);
 
     // Perform the refresh token exchange.
-    let new_token = oauth_client
-        .exchange_refresh_token(&oauth2::RefreshToken::new(current_refresh_token))
-        .request_async(oauth2::reqwest::async_http_client)
-        .await?;
+    let retry_policy = RetryPolicy::from_config();
+    let mut attempt = 0;
+    let new_token = loop {
+        match exchange(current_refresh_token.clone()).await {
+            Ok(token) => break token,
+            Err(RefreshError::Transient(reason)) if attempt < REFRESH_RETRIES => {
+                let delay = retry_policy.delay(attempt, None);
+                warn!("Token refresh failed ({}); retrying in {:?}", reason, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(RefreshError::Revoked) => {
+                warn!("Refresh token was revoked; removing the token cache");
+                if let Err(e) = fs::remove_file(cache_file) {
+                    error!("Failed to remove {}: {}", cache_file, e);
+                }
+                return Err(RefreshError::Revoked);
+            }
+            Err(e) => return Err(e),
+        }
+    };
 
     // Overwrite the token cache with the new token information.
-    let token_json = serde_json::to_string(&new_token)?;
-    fs::write(TOKEN_CACHE_FILE, token_json)?;
+    let token_json = serde_json::to_string(&new_token).map_err(|e| RefreshError::Other(e.to_string()))?;
+    fs::write(cache_file, token_json).map_err(|e| RefreshError::Other(e.to_string()))?;
     info!("Token successfully refreshed.");
 
     // Return the new access token.
     Ok(new_token.access_token().secret().to_string())
 }
 
+/// Lowercase file extensions of every attachment in a message payload, one per attachment.
+/// Parts without an extension are recorded by their MIME subtype (e.g. "pdf" for application/pdf).
+fn attachment_types(payload: &Value) -> Vec<String> {
+    let mut types = Vec::new();
+    let filename = payload.get("filename").and_then(|f| f.as_str()).unwrap_or("");
+    if !filename.is_empty() {
+        let extension = filename.rsplit_once('.')
+            .map(|(_, ext)| ext.to_string())
+            .or_else(|| payload.get("mimeType").and_then(|m| m.as_str())
+                .and_then(|m| m.rsplit('/').next())
+                .map(str::to_string))
+            .unwrap_or_default()
+            .to_lowercase();
+        types.push(extension);
+    }
+    if let Some(parts) = payload.get("parts").and_then(|p| p.as_array()) {
+        for part in parts {
+            types.extend(attachment_types(part));
+        }
+    }
+    types
+}
+
+/// The first `text/calendar` part or `.ics` attachment in a message payload, as sent with
+/// meeting invitations.
+fn calendar_part(payload: &Value) -> Option<&Value> {
+    let mime_type = payload["mimeType"].as_str().unwrap_or("").to_lowercase();
+    let filename = payload["filename"].as_str().unwrap_or("").to_lowercase();
+    if mime_type == "text/calendar" || mime_type == "application/ics" || filename.ends_with(".ics") {
+        return Some(payload);
+    }
+    payload["parts"].as_array()?.iter().find_map(calendar_part)
+}
+
 /// Helper: find a header value (case insensitive) from a slice of headers.
 fn get_header(headers: &[Value], name: &str) -> Option<String> {
     headers.iter().find(|h| {
@@ -183,12 +501,13 @@ fn get_header(headers: &[Value], name: &str) -> Option<String> {
         .and_then(|h| h.get("value").and_then(|v| v.as_str()).map(String::from))
 }
 
-/// Helper: extract the plain text body from a message payload.
-fn extract_plain_text_body(payload: &Value) -> Option<String> {
+/// Helper: extract the plain text body from a message payload, with the MIME type of the part
+/// it came from.
+fn extract_plain_text_body(payload: &Value) -> Option<(String, Option<String>)> {
     // First try direct body for simple emails
     if let Some(body_data) = payload.get("body").and_then(|b| b.get("data")).and_then(|d| d.as_str()) {
         debug!("Found direct body data");
-        return Some(body_data.to_string());
+        return Some((body_data.to_string(), part_mime_type(payload)));
     }
 
     if let Some(mime_type) = payload.get("mimeType").and_then(|m| m.as_str()) {
@@ -198,7 +517,7 @@ fn extract_plain_text_body(payload: &Value) -> Option<String> {
             if let Some(body_data) = payload.get("body")
                 .and_then(|b| b.get("data"))
                 .and_then(|d| d.as_str()) {
-                return Some(body_data.to_string());
+                return Some((body_data.to_string(), part_mime_type(payload)));
             }
         }
         
@@ -214,7 +533,7 @@ fn extract_plain_text_body(payload: &Value) -> Option<String> {
                             if let Some(body_data) = part.get("body")
                                 .and_then(|b| b.get("data"))
                                 .and_then(|d| d.as_str()) {
-                                return Some(body_data.to_string());
+                                return Some((body_data.to_string(), part_mime_type(part)));
                             }
                         }
                         
@@ -234,7 +553,7 @@ fn extract_plain_text_body(payload: &Value) -> Option<String> {
                         .and_then(|b| b.get("data"))
                         .and_then(|d| d.as_str()) {
                         debug!("Found fallback body data in part");
-                        return Some(body_data.to_string());
+                        return Some((body_data.to_string(), part_mime_type(part)));
                     }
                     
                     // Check if there's a nested body we can extract
@@ -244,7 +563,7 @@ fn extract_plain_text_body(payload: &Value) -> Option<String> {
                                 .and_then(|b| b.get("data"))
                                 .and_then(|d| d.as_str()) {
                                 debug!("Found nested body data");
-                                return Some(body_data.to_string());
+                                return Some((body_data.to_string(), part_mime_type(nested_part)));
                             }
                         }
                     }
@@ -259,11 +578,253 @@ fn extract_plain_text_body(payload: &Value) -> Option<String> {
         if parent.contains_key("snippet") {
             if let Some(snippet) = parent.get("snippet").and_then(|s| s.as_str()) {
                 debug!("Using snippet as body");
-                return Some(format!("(Snippet only) {}", snippet));
+                return Some((format!("(Snippet only) {}", snippet), None));
             }
         }
     }
 
     debug!("No body content found");
     None
-}
\ No newline at end of file
+}
+
+fn part_mime_type(part: &Value) -> Option<String> {
+    part.get("mimeType").and_then(|m| m.as_str()).map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn response(status: u16, retry_after: Option<&str>, body: &str) -> reqwest::Response {
+        let mut builder = http::Response::builder().status(status);
+        if let Some(secs) = retry_after {
+            builder = builder.header("Retry-After", secs);
+        }
+        reqwest::Response::from(builder.body(body.to_string()).unwrap())
+    }
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy { max_retries, base_delay: Duration::from_millis(1), max_delay: Duration::from_millis(5) }
+    }
+
+    #[tokio::test]
+    async fn test_retries_after_429_then_succeeds() {
+        let calls = AtomicU32::new(0);
+        let result = send_with_retry(&fast_policy(3), "test", || {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                Ok(match call {
+                    0 => response(429, Some("0"), ""),
+                    1 => response(403, None, r#"{"error":{"errors":[{"reason":"userRateLimitExceeded"}]}}"#),
+                    _ => response(200, None, "{}"),
+                })
+            }
+        }).await;
+
+        assert_eq!(result.unwrap().status(), 200);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let calls = AtomicU32::new(0);
+        let result = send_with_retry(&fast_policy(2), "test", || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async { Ok(response(429, None, "")) }
+        }).await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_other_403_is_not_retried() {
+        let calls = AtomicU32::new(0);
+        let result = send_with_retry(&fast_policy(3), "test", || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async { Ok(response(403, None, r#"{"error":{"errors":[{"reason":"insufficientPermissions"}]}}"#)) }
+        }).await;
+
+        assert!(result.unwrap_err().to_string().contains("insufficientPermissions"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_retry_delay_honors_retry_after_and_cap() {
+        let policy = RetryPolicy { max_retries: 5, base_delay: Duration::from_millis(100), max_delay: Duration::from_secs(10) };
+        let jitter = Duration::from_millis(100);
+
+        let delay = policy.delay(0, Some(Duration::from_secs(3)));
+        assert!(delay >= Duration::from_secs(3) && delay < Duration::from_secs(3) + jitter);
+
+        let delay = policy.delay(2, None);
+        assert!(delay >= Duration::from_millis(400) && delay < Duration::from_millis(400) + jitter);
+
+        let delay = policy.delay(0, Some(Duration::from_secs(120)));
+        assert!(delay < Duration::from_secs(10) + jitter);
+    }
+
+    fn encode(text: &str) -> String {
+        URL_SAFE.encode(text)
+    }
+
+    #[test]
+    fn test_attachment_types_from_payload() {
+        let payload = json!({
+            "mimeType": "multipart/mixed",
+            "filename": "",
+            "parts": [
+                { "mimeType": "text/plain", "filename": "", "body": { "data": "SGk=" } },
+                { "mimeType": "application/pdf", "filename": "Invoice.PDF", "body": { "attachmentId": "a1" } },
+                { "mimeType": "multipart/related", "filename": "", "parts": [
+                    { "mimeType": "image/png", "filename": "logo", "body": { "attachmentId": "a2" } }
+                ]}
+            ]
+        });
+        assert_eq!(attachment_types(&payload), vec!["pdf", "png"]);
+        assert!(attachment_types(&json!({ "mimeType": "text/plain", "body": { "data": "SGk=" } })).is_empty());
+    }
+
+    #[test]
+    fn test_calendar_part_finds_invites() {
+        let payload = json!({
+            "mimeType": "multipart/mixed",
+            "parts": [
+                { "mimeType": "multipart/alternative", "parts": [
+                    { "mimeType": "text/plain", "body": { "data": "" } },
+                    { "mimeType": "text/calendar", "body": { "data": "QkVHSU4" } }
+                ]},
+                { "mimeType": "application/ics", "filename": "invite.ics", "body": { "attachmentId": "a1" } }
+            ]
+        });
+        assert_eq!(calendar_part(&payload).unwrap()["mimeType"], "text/calendar");
+
+        let attachment_only = json!({ "mimeType": "multipart/mixed", "parts": [
+            { "mimeType": "application/octet-stream", "filename": "Invite.ICS", "body": { "attachmentId": "a1" } }
+        ]});
+        assert_eq!(calendar_part(&attachment_only).unwrap()["filename"], "Invite.ICS");
+        assert!(calendar_part(&json!({ "mimeType": "text/plain" })).is_none());
+    }
+
+    #[test]
+    fn test_messages_list_url_encodes_query() {
+        assert_eq!(
+            messages_list_url("is:inbox", 100),
+            "https://gmail.googleapis.com/gmail/v1/users/me/messages?q=is%3Ainbox&maxResults=100"
+        );
+        assert_eq!(
+            messages_list_url("label:work newer_than:7d", 20),
+            "https://gmail.googleapis.com/gmail/v1/users/me/messages?q=label%3Awork+newer_than%3A7d&maxResults=20"
+        );
+    }
+
+    #[test]
+    fn test_newest_message_ids_honours_the_cap() {
+        let ids: Vec<String> = (1..=8).map(|n| format!("msg-{}", n)).collect();
+        let listed = json!({
+            "messages": ids.iter().map(|id| json!({ "id": id, "threadId": "t" })).collect::<Vec<_>>(),
+            "nextPageToken": "next",
+            "resultSizeEstimate": 250,
+        });
+        assert_eq!(newest_message_ids(&listed, 3), vec!["msg-1", "msg-2", "msg-3"]);
+        assert_eq!(newest_message_ids(&listed, 8), ids);
+        assert_eq!(newest_message_ids(&listed, 100), ids);
+        assert!(newest_message_ids(&json!({ "resultSizeEstimate": 0 }), 100).is_empty());
+    }
+
+    #[test]
+    fn test_get_header_is_case_insensitive() {
+        let headers = vec![
+            json!({ "name": "From", "value": "Alice <alice@example.com>" }),
+            json!({ "name": "subject", "value": "Hello" }),
+        ];
+        assert_eq!(get_header(&headers, "from").as_deref(), Some("Alice <alice@example.com>"));
+        assert_eq!(get_header(&headers, "Subject").as_deref(), Some("Hello"));
+        assert_eq!(get_header(&headers, "Cc"), None);
+    }
+
+    #[test]
+    fn test_extract_plain_text_body_prefers_text_plain_in_nested_multipart() {
+        let payload = json!({
+            "mimeType": "multipart/mixed",
+            "parts": [
+                {
+                    "mimeType": "multipart/alternative",
+                    "parts": [
+                        { "mimeType": "text/html", "body": { "data": encode("<p>Hi</p>") } },
+                        { "mimeType": "text/plain", "body": { "data": encode("Hi") } }
+                    ]
+                },
+                { "mimeType": "application/pdf", "body": { "attachmentId": "abc" } }
+            ]
+        });
+        assert_eq!(extract_plain_text_body(&payload), Some((encode("Hi"), Some("text/plain".to_string()))));
+    }
+
+    #[test]
+    fn test_extract_plain_text_body_falls_back() {
+        // A simple message carries its body directly
+        let simple = json!({ "mimeType": "text/plain", "body": { "data": encode("Direct") } });
+        assert_eq!(extract_plain_text_body(&simple), Some((encode("Direct"), Some("text/plain".to_string()))));
+
+        // HTML-only multipart uses whatever part has data, and says it's HTML
+        let html_only = json!({
+            "mimeType": "multipart/alternative",
+            "parts": [{ "mimeType": "text/html", "body": { "data": encode("<b>Only HTML</b>") } }]
+        });
+        assert_eq!(extract_plain_text_body(&html_only), Some((encode("<b>Only HTML</b>"), Some("text/html".to_string()))));
+
+        let empty = json!({ "mimeType": "multipart/mixed", "parts": [] });
+        assert_eq!(extract_plain_text_body(&empty), None);
+    }
+
+    #[test]
+    fn test_classify_refresh_error_kinds() {
+        let server = |kind: BasicErrorResponseType| -> RequestTokenError<std::io::Error, BasicErrorResponse> {
+            RequestTokenError::ServerResponse(BasicErrorResponse::new(kind, Some("Token has been expired or revoked.".to_string()), None))
+        };
+
+        let revoked = classify_refresh_error(server(BasicErrorResponseType::InvalidGrant));
+        assert!(matches!(revoked, RefreshError::Revoked));
+        assert!(revoked.needs_reauthentication());
+        assert!(revoked.to_string().contains("sign in again"), "{}", revoked);
+
+        let client = classify_refresh_error(server(BasicErrorResponseType::InvalidClient));
+        assert!(matches!(client, RefreshError::InvalidClient(ref reason) if reason.contains("invalid_client")), "{:?}", client);
+        assert!(client.needs_reauthentication());
+
+        let network = classify_refresh_error::<std::io::Error>(RequestTokenError::Request(
+            std::io::Error::new(std::io::ErrorKind::TimedOut, "connection timed out"),
+        ));
+        assert!(matches!(network, RefreshError::Transient(ref reason) if reason.contains("timed out")), "{:?}", network);
+        assert!(!network.needs_reauthentication());
+
+        let other = classify_refresh_error(server(BasicErrorResponseType::InvalidRequest));
+        assert!(matches!(other, RefreshError::Other(_)), "{:?}", other);
+        assert!(other.needs_reauthentication());
+        assert!(RefreshError::Other("odd".to_string()).needs_reauthentication());
+        assert!(RefreshError::NoRefreshToken.needs_reauthentication());
+    }
+
+    #[tokio::test]
+    async fn test_revoked_refresh_token_removes_the_cache() {
+        let cache_file = std::env::temp_dir().join("gmail_service_revoked_tokencache.json");
+        let cache_file = cache_file.to_str().unwrap();
+        let cache = r#"{"access_token": "old", "token_type": "Bearer", "expires_in": 3600, "refresh_token": "refresh", "scope": null}"#;
+
+        // Other failures leave the cache for the next attempt
+        fs::write(cache_file, cache).unwrap();
+        let result = refresh_token_with(cache_file, |_| async { Err(RefreshError::Other("odd".to_string())) }).await;
+        assert!(matches!(result, Err(RefreshError::Other(_))), "{:?}", result);
+        assert!(fs::metadata(cache_file).is_ok());
+
+        let result = refresh_token_with(cache_file, |refresh_token| async move {
+            assert_eq!(refresh_token.secret(), "refresh");
+            Err(RefreshError::Revoked)
+        }).await;
+        assert!(matches!(result, Err(RefreshError::Revoked)), "{:?}", result);
+        assert!(fs::metadata(cache_file).is_err(), "a revoked token's cache should be removed");
+    }
+}
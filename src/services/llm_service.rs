@@ -1,7 +1,57 @@
-use crate::models::email_query::QueryCriteria;
+use crate::config;
+use crate::models::email_query::{QueryCriteria, date_range_for_query};
 use crate::services::chat_service::Intent;
 
 /// Enhance a user query into QueryCriteria using the LLM (stub for now)
 pub async fn refine_query(query: &str, _intent: Intent) -> Result<QueryCriteria, Box<dyn std::error::Error>> {
-    Ok(QueryCriteria::new(query))
+    let mut criteria = QueryCriteria::new(query);
+    // Relative dates like "last week" become a concrete range
+    (criteria.date_from, criteria.date_to) = date_range_for_query(query);
+    Ok(criteria)
+}
+
+/// The models in `required` that aren't in Ollama's `available` list. A name without a tag
+/// matches its `:latest` tag, so "llama3.2" is found as "llama3.2:latest".
+pub fn missing_models(available: &[String], required: &[&str]) -> Vec<String> {
+    let with_tag = |name: &str| if name.contains(':') { name.to_string() } else { format!("{}:latest", name) };
+    required.iter()
+        .filter(|model| !available.iter().any(|name| with_tag(name) == with_tag(model)))
+        .map(|model| model.to_string())
+        .collect()
+}
+
+/// Checks that Ollama is reachable and has the classifier and generation models pulled,
+/// returning a message explaining how to fix it if not.
+pub async fn check_models_available() -> Result<(), String> {
+    let ollama = config::create_ollama().map_err(|e| e.to_string())?;
+    let models = ollama.list_local_models().await
+        .map_err(|e| format!("Couldn't list Ollama models at {}: {}", config::ollama_host().unwrap_or_default(), e))?;
+    let available: Vec<String> = models.into_iter().map(|model| model.name).collect();
+
+    let (classifier, generation) = (config::classifier_model(), config::generation_model());
+    let mut required = vec![classifier.as_str(), generation.as_str()];
+    required.dedup();
+    let missing = missing_models(&available, &required);
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let pulls = missing.iter()
+        .map(|model| format!("`ollama pull {}`", model))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(format!("Ollama is missing model(s) {}; run {}", missing.join(", "), pulls))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_models() {
+        let available = vec!["llama3.2:latest".to_string(), "nomic-embed-text:v1.5".to_string()];
+        assert!(missing_models(&available, &["llama3.2"]).is_empty());
+        assert!(missing_models(&available, &["nomic-embed-text:v1.5"]).is_empty());
+        assert_eq!(missing_models(&available, &["llama3.2", "mistral", "nomic-embed-text"]), vec!["mistral", "nomic-embed-text"]);
+        assert_eq!(missing_models(&[], &["llama3.2"]), vec!["llama3.2"]);
+    }
 }
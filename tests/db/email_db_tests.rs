@@ -6,6 +6,7 @@ use AdukiChatAgent::config;
 use AdukiChatAgent::models::email::{Email};
 use AdukiChatAgent::models::email_db::{EmailDB, EmailDBError};
 use AdukiChatAgent::models::email_query::QueryCriteria;
+use super::{delete_test_index, test_email, unique_test_db};
 
 #[tokio::test]
 async fn test_alice_name_matching_issue() -> Result<(), Box<dyn std::error::Error>> {
@@ -18,16 +19,11 @@ async fn test_alice_name_matching_issue() -> Result<(), Box<dyn std::error::Erro
     // Database preloaded with baseline emails: alice-email-1 and parallels-email-1
 
     // Create criteria that represents "explain the email from alice"
-    let criteria = QueryCriteria {
-        keywords: vec![],
-        from: Some("alice".to_string()),  // This is what gets extracted from the query
-        to: None,
-        subject: None,
-        date_from: None,
-        date_to: None,
-        raw_query: "explain the email from alice".to_string(),
-        llm_confidence: 0.9,
-    };
+    let criteria = QueryCriteria::builder()
+        .from("alice") // This is what gets extracted from the query
+        .raw_query("explain the email from alice")
+        .llm_confidence(0.9)
+        .build();
 
     // Execute search by criteria
     let results = db.search_emails_by_criteria(criteria).await?;
@@ -63,8 +59,8 @@ async fn test_first_name_search_issue() -> Result<(), Box<dyn std::error::Error>
     let _ = env_logger::builder().is_test(true).try_init();
 
     // Set up a clean test database with a unique index name to ensure isolation
-    let url = config::meilisearch_url();
-    let admin_key = config::meilisearch_admin_key();
+    let url = config::load()?.meilisearch_url;
+    let admin_key = config::load()?.meilisearch_admin_key;
     // Use a unique index name to prevent conflicts with other tests
     let unique_index = format!("test_phil_search_{}", std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -81,6 +77,7 @@ async fn test_first_name_search_issue() -> Result<(), Box<dyn std::error::Error>
         date: Some("2025-03-04T12:00:00Z".to_string()),
         subject: Some("Important meeting".to_string()),
         body: Some("This is a test email from Phil.".to_string()),
+        ..Default::default()
     };
     
     // Store the test email and wait for indexing
@@ -100,16 +97,11 @@ async fn test_first_name_search_issue() -> Result<(), Box<dyn std::error::Error>
         "Test email not found in database");
     
     // Now proceed with the actual test
-    let criteria = QueryCriteria {
-        keywords: vec![],
-        from: Some("Phil".to_string()),
-        to: None,
-        subject: None,
-        date_from: None,
-        date_to: None,
-        raw_query: "find the email from Phil".to_string(),
-        llm_confidence: 0.9,
-    };
+    let criteria = QueryCriteria::builder()
+        .from("Phil")
+        .raw_query("find the email from Phil")
+        .llm_confidence(0.9)
+        .build();
     
     // Execute search by criteria
     let results = db.search_emails_by_criteria(criteria).await?;
@@ -137,7 +129,110 @@ async fn test_first_name_search_issue() -> Result<(), Box<dyn std::error::Error>
     }
     
     // Clean up by deleting the unique index
-    db.clear().await?;
+    delete_test_index(&unique_index).await?;
     
     Ok(())
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_sender_search_applies_date_bounds() -> Result<(), Box<dyn std::error::Error>> {
+    let (db, unique_index) = unique_test_db("test_sender_dates").await?;
+
+    // Two invoices from the same sender that differ only by date
+    let invoice = |id: &str, date: &str| Email {
+        date: Some(date.to_string()),
+        subject: Some("Invoice".to_string()),
+        ..test_email(id, "Dana Weber <dana@example.com>")
+    };
+    db.store_emails(&[
+        invoice("dana-invoice-1", "Tue, 06 May 2025 09:15:00 +0000"),
+        invoice("dana-invoice-2", "Tue, 13 May 2025 09:15:00 +0000"),
+    ]).await?;
+
+    let criteria = QueryCriteria::builder()
+        .keywords(["invoice"])
+        .from("Dana")
+        .date_range("2025-05-06T00:00:00Z".parse()?, "2025-05-06T23:59:59Z".parse()?)
+        .raw_query("the invoice Dana sent me on 2025-05-06")
+        .llm_confidence(0.9)
+        .build();
+
+    let results = db.search_emails_by_criteria(criteria).await?;
+    let ids: Vec<_> = results.iter().filter_map(|e| e.message_id.as_deref()).collect();
+    assert_eq!(ids, vec!["dana-invoice-1"]);
+
+    delete_test_index(&unique_index).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_priority_sender_outranks_equal_match() -> Result<(), Box<dyn std::error::Error>> {
+    let (db, unique_index) = unique_test_db("test_priority_sender").await?;
+
+    // Two Bobs whose emails match the query equally well
+    let report = |id: &str, from: &str| Email { subject: Some("Quarterly report".to_string()), ..test_email(id, from) };
+    db.store_emails(&[
+        report("bob-other", "Bob Smith <bob@other.com>"),
+        report("bob-boss", "Bob Jones <bob@corp.com>"),
+    ]).await?;
+
+    let criteria = QueryCriteria::builder()
+        .keywords(["report"])
+        .from("Bob")
+        .priority_senders(["bob@corp.com"])
+        .raw_query("the report Bob sent")
+        .llm_confidence(0.9)
+        .build();
+
+    let results = db.search_emails_by_criteria(criteria).await?;
+    assert_eq!(results.first().and_then(|e| e.message_id.as_deref()), Some("bob-boss"));
+
+    delete_test_index(&unique_index).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reconfigure_updates_existing_index_settings() -> Result<(), Box<dyn std::error::Error>> {
+    let (db, unique_index) = unique_test_db("test_reconfigure").await?;
+
+    // Simulate an index created before "language" became filterable
+    let config = config::load()?;
+    let client = meilisearch_sdk::client::Client::new(&config.meilisearch_url, Some(&config.meilisearch_admin_key))?;
+    let index = client.index(&unique_index);
+    index.set_filterable_attributes(["from"]).await?
+        .wait_for_completion(&client, None, None).await?;
+
+    db.reconfigure().await?;
+
+    let filterable = index.get_filterable_attributes().await?;
+    assert!(filterable.contains(&"language".to_string()), "filterable attributes: {:?}", filterable);
+    assert!(index.get_sortable_attributes().await?.contains(&"importance".to_string()));
+
+    delete_test_index(&unique_index).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unaccented_name_matches_accented_sender() -> Result<(), Box<dyn std::error::Error>> {
+    let (db, unique_index) = unique_test_db("test_accented_senders").await?;
+
+    db.store_emails(&[
+        test_email("jose-1", "José García <jgarcia@example.com>"),
+        test_email("muller-1", "Anna Müller <anna@example.de>"),
+        test_email("bob-1", "Bob <bob@example.com>"),
+    ]).await?;
+
+    for (name, expected) in [("Jose", "jose-1"), ("Muller", "muller-1"), ("José", "jose-1")] {
+        let criteria = QueryCriteria::builder()
+            .from(name)
+            .raw_query(format!("find the email from {}", name))
+            .llm_confidence(0.9)
+            .build();
+        let results = db.search_emails_by_criteria(criteria).await?;
+        let ids: Vec<&str> = results.iter().filter_map(|e| e.message_id.as_deref()).collect();
+        assert_eq!(ids, vec![expected], "searching for {}", name);
+    }
+
+    delete_test_index(&unique_index).await?;
+    Ok(())
+}
@@ -1,15 +1,17 @@
 // Integration tests use shared baseline setup
 use AdukiChatAgent::config;
-use AdukiChatAgent::models::email::{Email};
+use AdukiChatAgent::models::email::{Email, synthetic_message_id};
 use AdukiChatAgent::models::email_db::{EmailDB, EmailDBError};
-use AdukiChatAgent::models::email_query::QueryCriteria;
+use AdukiChatAgent::models::email_query::{QueryCriteria, Relaxation};
 use super::setup_test_db_all;
+use super::{delete_test_index, test_email, unique_test_db};
+use std::sync::Arc;
 
 #[tokio::test]
 async fn test_store_and_search_email() -> Result<(), Box<dyn std::error::Error>> {
     // Create a direct instance of EmailDB for integration testing
-    let url = config::meilisearch_url();
-    let admin_key = config::meilisearch_admin_key();
+    let url = config::load()?.meilisearch_url;
+    let admin_key = config::load()?.meilisearch_admin_key;
     let db = EmailDB::new(&url, Some(&admin_key), "emails_test").await?;
     db.clear().await?;  // Start with a clean database
     
@@ -21,6 +23,7 @@ async fn test_store_and_search_email() -> Result<(), Box<dyn std::error::Error>>
         date: Some("2025-03-04T12:00:00Z".to_string()),
         subject: Some("Test Email Store".to_string()),
         body: Some("This is a test email.".to_string()),
+        ..Default::default()
     };
     
     // Store the email directly
@@ -55,8 +58,8 @@ async fn test_store_and_search_email() -> Result<(), Box<dyn std::error::Error>>
 #[tokio::test]
 async fn test_store_emails() -> Result<(), Box<dyn std::error::Error>> {
     // Create a direct instance of EmailDB for integration testing
-    let url = config::meilisearch_url();
-    let admin_key = config::meilisearch_admin_key();
+    let url = config::load()?.meilisearch_url;
+    let admin_key = config::load()?.meilisearch_admin_key;
     // Use a unique index name to prevent conflicts with other tests
     let unique_index = format!("test_store_emails_{}", std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -74,6 +77,7 @@ async fn test_store_emails() -> Result<(), Box<dyn std::error::Error>> {
             date: Some("2025-03-04T12:00:00Z".to_string()),
             subject: Some("Bulk Email 1".to_string()),
             body: Some("This is a test email.".to_string()),
+            ..Default::default()
         },
         Email {
             message_id: Some("test-3".to_string()),
@@ -82,6 +86,7 @@ async fn test_store_emails() -> Result<(), Box<dyn std::error::Error>> {
             date: Some("2025-03-04T12:05:00Z".to_string()),
             subject: Some("Bulk Email 2".to_string()),
             body: Some("This is another test email.".to_string()),
+            ..Default::default()
         },
         Email {
             message_id: Some("test-4".to_string()),
@@ -90,6 +95,7 @@ async fn test_store_emails() -> Result<(), Box<dyn std::error::Error>> {
             date: Some("2025-03-04T12:10:00Z".to_string()),
             subject: Some("Bulk Email 3".to_string()),
             body: Some("This is yet another test email.".to_string()),
+            ..Default::default()
         },
     ];
     
@@ -132,7 +138,7 @@ async fn test_store_emails() -> Result<(), Box<dyn std::error::Error>> {
     assert!(ids.contains(&"test-4".to_string()), "Bulk Email 3 not found in search results");
     
     // Clean up
-    db.clear().await?;
+    delete_test_index(&unique_index).await?;
     
     Ok(())
 }
@@ -140,8 +146,8 @@ async fn test_store_emails() -> Result<(), Box<dyn std::error::Error>> {
 #[tokio::test]
 async fn test_search_emails_by_criteria() -> Result<(), Box<dyn std::error::Error>> {
     // Create a direct instance of EmailDB for integration testing
-    let url = config::meilisearch_url();
-    let admin_key = config::meilisearch_admin_key();
+    let url = config::load()?.meilisearch_url;
+    let admin_key = config::load()?.meilisearch_admin_key;
     let db = EmailDB::new(&url, Some(&admin_key), "emails_test").await?;
     db.clear().await?;  // Start with a clean database
     
@@ -153,6 +159,7 @@ async fn test_search_emails_by_criteria() -> Result<(), Box<dyn std::error::Erro
         date: Some("2025-03-04T12:00:00Z".to_string()),
         subject: Some("Advanced Search Test".to_string()),
         body: Some("This is a test email for advanced search.".to_string()),
+        ..Default::default()
     };
     
     // Store the email directly
@@ -174,16 +181,13 @@ async fn test_search_emails_by_criteria() -> Result<(), Box<dyn std::error::Erro
         "Test email not found in all_emails result");
     
     // Create the search criteria
-    let criteria = QueryCriteria { 
-        keywords: vec!["Advanced".to_string()], 
-        from: Some("charlie@example.com".to_string()), 
-        to: None, 
-        subject: Some("Advanced Search Test".to_string()), 
-        date_from: None, 
-        date_to: None, 
-        raw_query: "Perform an Advanced Search".to_string(), 
-        llm_confidence: 1.0 
-    };
+    let criteria = QueryCriteria::builder()
+        .keywords(["Advanced"])
+        .from("charlie@example.com")
+        .subject("Advanced Search Test")
+        .raw_query("Perform an Advanced Search")
+        .llm_confidence(1.0)
+        .build();
     
     // Search for emails matching the criteria
     let results = db.search_emails_by_criteria(criteria).await?;
@@ -206,8 +210,8 @@ async fn test_search_emails_by_criteria() -> Result<(), Box<dyn std::error::Erro
 #[tokio::test]
 async fn test_search_from_field_matching() -> Result<(), Box<dyn std::error::Error>> {
     // Create a direct instance of EmailDB for integration testing
-    let url = config::meilisearch_url();
-    let admin_key = config::meilisearch_admin_key();
+    let url = config::load()?.meilisearch_url;
+    let admin_key = config::load()?.meilisearch_admin_key;
     let db = EmailDB::new(&url, Some(&admin_key), "emails_test").await?;
     db.clear().await?;  // Start with a clean database
     
@@ -219,6 +223,7 @@ async fn test_search_from_field_matching() -> Result<(), Box<dyn std::error::Err
         date: Some("2025-03-04T12:00:00Z".to_string()),
         subject: Some("Email from Bob".to_string()),
         body: Some("Test email content.".to_string()),
+        ..Default::default()
     };
 
     let email2 = Email {
@@ -228,6 +233,7 @@ async fn test_search_from_field_matching() -> Result<(), Box<dyn std::error::Err
         date: Some("2025-03-04T12:05:00Z".to_string()),
         subject: Some("Another email from Bob".to_string()),
         body: Some("Another test email.".to_string()),
+        ..Default::default()
     };
 
     let email3 = Email {
@@ -237,6 +243,7 @@ async fn test_search_from_field_matching() -> Result<(), Box<dyn std::error::Err
         date: Some("2025-03-04T12:10:00Z".to_string()),
         subject: Some("Email from Alice".to_string()),
         body: Some("Control email content.".to_string()),
+        ..Default::default()
     };
     
     // Store emails directly
@@ -260,16 +267,10 @@ async fn test_search_from_field_matching() -> Result<(), Box<dyn std::error::Err
         "Bob's second email not found in all_emails result");
     
     // Create a query criteria with the 'from' field set to 'Bob'
-    let criteria = QueryCriteria { 
-        keywords: vec![], 
-        from: Some("Bob".to_string()), 
-        to: None, 
-        subject: None, 
-        date_from: None, 
-        date_to: None, 
-        raw_query: "emails from Bob".to_string(),
-        llm_confidence: 0.0 
-    };
+    let criteria = QueryCriteria::builder()
+        .from("Bob")
+        .raw_query("emails from Bob")
+        .build();
     
     // Search for emails matching the criteria
     let results = db.search_emails_by_criteria(criteria).await?;
@@ -291,4 +292,229 @@ async fn test_search_from_field_matching() -> Result<(), Box<dyn std::error::Err
     db.clear().await?;
     
     Ok(())
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_search_to_field_matching() -> Result<(), Box<dyn std::error::Error>> {
+    // Use a unique index so the shared test index's contents don't interfere
+    let (db, unique_index) = unique_test_db("test_to_field").await?;
+
+    // Emails the user sent to Bob (by address and by display name) and to Alice
+    let sent = |id: &str, to: &str| Email { to: Some(to.to_string()), ..test_email(id, "user@example.com") };
+    db.store_emails(&[
+        sent("test-to-1", "bob@example.com"),
+        sent("test-to-2", "Bob <robert@foo.com>, carol@example.com"),
+        sent("test-to-3", "alice@example.com"),
+    ]).await?;
+
+    let criteria = QueryCriteria::builder()
+        .to("Bob")
+        .raw_query("emails I sent to Bob")
+        .build();
+
+    let results = db.search_emails_by_criteria(criteria).await?;
+    let found_ids: Vec<_> = results.iter().filter_map(|e| e.message_id.clone()).collect();
+
+    assert!(found_ids.contains(&"test-to-1".to_string()), "Email to bob@example.com not found");
+    assert!(found_ids.contains(&"test-to-2".to_string()), "Email to 'Bob <robert@foo.com>' not found");
+    assert!(!found_ids.contains(&"test-to-3".to_string()), "Email to Alice should not be returned");
+
+    delete_test_index(&unique_index).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_store_email_without_message_id() -> Result<(), Box<dyn std::error::Error>> {
+    let (db, unique_index) = unique_test_db("test_missing_id").await?;
+
+    let email = Email {
+        message_id: None,
+        from: Some("noid@example.com".to_string()),
+        to: Some("recipient@example.com".to_string()),
+        date: Some("2025-03-04T12:00:00Z".to_string()),
+        subject: Some("Email without an id".to_string()),
+        body: Some("This email has no message id.".to_string()),
+        ..Default::default()
+    };
+    db.store_email(&email).await?;
+    // Storing it again updates the same document rather than adding a copy
+    db.store_emails(std::slice::from_ref(&email)).await?;
+
+    let id = synthetic_message_id(&email);
+    let stored = db.get_email(&id).await?;
+    assert_eq!(stored.and_then(|e| e.subject).as_deref(), Some("Email without an id"));
+    assert_eq!(db.count_emails().await?, 1);
+
+    delete_test_index(&unique_index).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delete_by_criteria_removes_only_matches() -> Result<(), Box<dyn std::error::Error>> {
+    let (db, unique_index) = unique_test_db("test_bulk_delete").await?;
+
+    let email = |id: &str, from: &str, subject: &str| Email { subject: Some(subject.to_string()), ..test_email(id, from) };
+    db.store_emails(&[
+        email("bulk-1", "Marketing <marketing@x.com>", "Weekly newsletter"),
+        email("bulk-2", "marketing@x.com", "Monthly newsletter"),
+        email("bulk-3", "marketing@x.com", "Your invoice"),
+        email("bulk-4", "friend@example.com", "Newsletter I liked"),
+    ]).await?;
+
+    let criteria = QueryCriteria::builder()
+        .from("marketing@x.com")
+        .keywords(["newsletters"])
+        .raw_query("delete all newsletters from marketing@x.com")
+        .build();
+
+    assert_eq!(db.delete_by_criteria(criteria).await?, 2);
+    assert!(db.get_email("bulk-1").await?.is_none());
+    assert!(db.get_email("bulk-2").await?.is_none());
+    assert!(db.get_email("bulk-3").await?.is_some(), "Non-newsletter from the sender should remain");
+    assert!(db.get_email("bulk-4").await?.is_some(), "Newsletter from another sender should remain");
+
+    delete_test_index(&unique_index).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_count_by_criteria_matches_stored_emails() -> Result<(), Box<dyn std::error::Error>> {
+    let (db, unique_index) = unique_test_db("test_count").await?;
+
+    let email = |id: &str, from: &str, subject: &str| Email { subject: Some(subject.to_string()), ..test_email(id, from) };
+    db.store_emails(&[
+        email("count-1", "Bob <bob@example.com>", "Invoice for March"),
+        email("count-2", "bob@example.com", "Lunch?"),
+        email("count-3", "Bob Smith <bob@example.com>", "Invoice for April"),
+        email("count-4", "Alice <alice@example.com>", "Invoice reminder"),
+    ]).await?;
+
+    let from_bob = QueryCriteria::builder().from("bob").raw_query("how many emails from bob").build();
+    assert_eq!(db.count_by_criteria(&from_bob).await?, 3);
+    assert_eq!(db.count_by_criteria(&from_bob).await?, db.find_by_criteria(&from_bob).await?.len());
+
+    let bob_invoices = QueryCriteria::builder()
+        .from("bob")
+        .keywords(["invoice"])
+        .raw_query("how many invoice emails from bob")
+        .build();
+    assert_eq!(db.count_by_criteria(&bob_invoices).await?, 2);
+
+    delete_test_index(&unique_index).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mailbox_reads_past_search_hit_limit() -> Result<(), Box<dyn std::error::Error>> {
+    let url = config::load()?.meilisearch_url;
+    let admin_key = config::load()?.meilisearch_admin_key;
+    let index_name = "test_past_hit_limit";
+    let db = EmailDB::new(&url, Some(&admin_key), index_name).await?;
+    db.clear().await?;
+
+    // More emails than a search can page through (maxTotalHits defaults to 1000)
+    let emails: Vec<Email> = (0..1050).map(|i| Email {
+        message_id: Some(format!("many-{}", i)),
+        from: Some("bob@example.com".to_string()),
+        subject: Some(format!("Update {}", i)),
+        ..Default::default()
+    }).collect();
+    db.store_emails(&emails).await?;
+
+    assert_eq!(db.get_emails_page(1000, 100).await?.len(), 50);
+    let from_bob = QueryCriteria::builder().from("bob").raw_query("how many emails from bob").build();
+    assert_eq!(db.count_by_criteria(&from_bob).await?, 1050);
+
+    delete_test_index(index_name).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_from_config_uses_configured_index() -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::Config {
+        meilisearch_index: "emails_test_configured".to_string(),
+        ..config::load()?
+    };
+    let db = EmailDB::from_config(&config).await?;
+    assert_eq!(db.index_name(), "emails_test_configured");
+
+    db.store_email(&Email { message_id: Some("configured-1".to_string()), ..Default::default() }).await?;
+    assert!(db.get_email("configured-1").await?.is_some());
+
+    delete_test_index("emails_test_configured").await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_email_dbs_share_one_client_across_indexes() -> Result<(), Box<dyn std::error::Error>> {
+    let client = Arc::new(EmailDB::client_from_config(&config::load()?)?);
+    let alice = EmailDB::with_client(client.clone(), "emails_test_shared_alice").await?;
+    let bob = EmailDB::with_client(client.clone(), "emails_test_shared_bob").await?;
+    alice.clear().await?;
+    bob.clear().await?;
+    assert_eq!(Arc::strong_count(&client), 3);
+
+    alice.store_email(&Email { message_id: Some("shared-alice".to_string()), ..Default::default() }).await?;
+    bob.store_email(&Email { message_id: Some("shared-bob".to_string()), ..Default::default() }).await?;
+
+    // Each handle only sees its own index
+    assert_eq!(alice.index_name(), "emails_test_shared_alice");
+    assert!(alice.get_email("shared-alice").await?.is_some());
+    assert!(alice.get_email("shared-bob").await?.is_none());
+    assert!(bob.get_email("shared-bob").await?.is_some());
+    assert!(bob.get_email("shared-alice").await?.is_none());
+
+    delete_test_index("emails_test_shared_alice").await?;
+    delete_test_index("emails_test_shared_bob").await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_relaxed_search_drops_constraints_until_something_matches() -> Result<(), Box<dyn std::error::Error>> {
+    let db = EmailDB::new(&config::load()?.meilisearch_url, Some(&config::load()?.meilisearch_admin_key), "emails_test_relaxed").await?;
+    db.clear().await?;
+    db.store_email(&Email {
+        message_id: Some("relaxed-1".to_string()),
+        from: Some("carol@example.com".to_string()),
+        subject: Some("Quarterly budget review".to_string()),
+        body: Some("Here is the budget for next quarter.".to_string()),
+        date: Some("2024-03-05T10:00:00Z".to_string()),
+        ..Default::default()
+    }).await?;
+
+    // Nothing from Dave mentions the budget, so the strict search finds nothing
+    let strict = QueryCriteria::builder()
+        .keywords(["budget"])
+        .from("dave@example.com")
+        .raw_query("the budget email from dave@example.com")
+        .build();
+    assert!(db.search_emails_by_criteria(strict.clone()).await?.is_empty());
+
+    let (emails, dropped) = db.search_with_relaxation(strict).await?;
+    assert_eq!(emails.first().and_then(|e| e.message_id.as_deref()), Some("relaxed-1"));
+    assert_eq!(dropped, vec![Relaxation::KeywordsOnly]);
+
+    // A search that matches as given drops nothing
+    let exact = QueryCriteria::builder().keywords(["budget"]).build();
+    let (emails, dropped) = db.search_with_relaxation(exact).await?;
+    assert_eq!(emails.len(), 1);
+    assert!(dropped.is_empty());
+
+    delete_test_index("emails_test_relaxed").await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_missing_email_is_not_found() -> Result<(), Box<dyn std::error::Error>> {
+    let url = config::load()?.meilisearch_url;
+    let admin_key = config::load()?.meilisearch_admin_key;
+    let db = EmailDB::new(&url, Some(&admin_key), "emails_test_not_found").await?;
+    db.clear().await?;
+
+    // Reading a missing email is None, deleting one is NotFound rather than an operation error
+    assert!(db.get_email("no-such-email").await?.is_none());
+    assert!(matches!(db.delete_email("no-such-email").await, Err(EmailDBError::NotFound(_))));
+
+    delete_test_index("emails_test_not_found").await?;
+    Ok(())
+}
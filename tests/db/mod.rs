@@ -6,11 +6,44 @@ use AdukiChatAgent::config;
 use AdukiChatAgent::models::email::{Email};
 use AdukiChatAgent::models::email_db::{EmailDB, EmailDBError};
 
+/// Creates an index of its own for a test, named `<prefix>_<timestamp>`. Returns the name too, so
+/// the test can pass it to `delete_test_index` when it's done.
+pub async fn unique_test_db(prefix: &str) -> Result<(EmailDB, String), Box<dyn std::error::Error>> {
+    let config = config::load()?;
+    let unique_index = format!("{}_{}", prefix, std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs());
+    let db = EmailDB::new(&config.meilisearch_url, Some(&config.meilisearch_admin_key), &unique_index).await?;
+    Ok((db, unique_index))
+}
+
+/// An email from `from` to user@example.com with a fixed date, subject and body. Tests override
+/// the fields they care about with struct update syntax.
+pub fn test_email(id: &str, from: &str) -> Email {
+    Email {
+        message_id: Some(id.to_string()),
+        from: Some(from.to_string()),
+        to: Some("user@example.com".to_string()),
+        date: Some("2025-03-04T12:00:00Z".to_string()),
+        subject: Some("Test email".to_string()),
+        body: Some("Test email content.".to_string()),
+        ..Default::default()
+    }
+}
+
+/// Deletes an index a test created for itself, so test runs don't leave indexes behind
+pub async fn delete_test_index(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load()?;
+    let client = EmailDB::admin_client(&config.meilisearch_url, Some(&config.meilisearch_admin_key))?;
+    client.delete_index(name).await?.wait_for_completion(&client, None, None).await?;
+    Ok(())
+}
+
 /// Shared setup: clear test index and insert baseline emails for all tests
 pub async fn setup_test_db_all() -> Result<EmailDB, EmailDBError> {
     let _ = env_logger::builder().is_test(true).try_init();
-    let url = config::meilisearch_url();
-    let admin_key = config::meilisearch_admin_key();
+    let url = config::load()?.meilisearch_url;
+    let admin_key = config::load()?.meilisearch_admin_key;
     let db = EmailDB::new(&url, Some(&admin_key), "emails_test").await?;
     // clean slate
     db.clear().await?;
@@ -24,6 +57,7 @@ pub async fn setup_test_db_all() -> Result<EmailDB, EmailDBError> {
             date: Some("2025-03-04T12:00:00Z".to_string()),
             subject: Some("Meeting tomorrow".to_string()),
             body: Some("Hi Alice's meeting request".to_string()),
+            ..Default::default()
         },
         Email {
             message_id: Some("parallels-email-1".to_string()),
@@ -32,6 +66,7 @@ pub async fn setup_test_db_all() -> Result<EmailDB, EmailDBError> {
             date: Some("2025-03-05T09:00:00Z".to_string()),
             subject: Some("Activate your Parallels account".to_string()),
             body: Some("Account name is alice, please activate.".to_string()),
+            ..Default::default()
         },
         // Operations tests baseline
         Email {
@@ -41,6 +76,7 @@ pub async fn setup_test_db_all() -> Result<EmailDB, EmailDBError> {
             date: Some("2025-03-04T12:00:00Z".to_string()),
             subject: Some("Test Email Store".to_string()),
             body: Some("This is a test email.".to_string()),
+            ..Default::default()
         },
         Email {
             message_id: Some("test-2".to_string()),
@@ -49,6 +85,7 @@ pub async fn setup_test_db_all() -> Result<EmailDB, EmailDBError> {
             date: Some("2025-03-04T12:00:00Z".to_string()),
             subject: Some("Bulk Email 1".to_string()),
             body: Some("This is a test email.".to_string()),
+            ..Default::default()
         },
         Email {
             message_id: Some("test-3".to_string()),
@@ -57,6 +94,7 @@ pub async fn setup_test_db_all() -> Result<EmailDB, EmailDBError> {
             date: Some("2025-03-04T12:00:00Z".to_string()),
             subject: Some("Bulk Email 2".to_string()),
             body: Some("This is a test email.".to_string()),
+            ..Default::default()
         },
         Email {
             message_id: Some("test-4".to_string()),
@@ -65,6 +103,7 @@ pub async fn setup_test_db_all() -> Result<EmailDB, EmailDBError> {
             date: Some("2025-03-04T12:00:00Z".to_string()),
             subject: Some("Bulk Email 3".to_string()),
             body: Some("This is a test email.".to_string()),
+            ..Default::default()
         },
         Email {
             message_id: Some("test-5".to_string()),
@@ -73,6 +112,7 @@ pub async fn setup_test_db_all() -> Result<EmailDB, EmailDBError> {
             date: Some("2025-03-04T12:00:00Z".to_string()),
             subject: Some("Advanced Search Test".to_string()),
             body: Some("This is a test email.".to_string()),
+            ..Default::default()
         },
         Email {
             message_id: Some("test-from-1".to_string()),
@@ -81,6 +121,7 @@ pub async fn setup_test_db_all() -> Result<EmailDB, EmailDBError> {
             date: Some("2025-03-04T12:00:00Z".to_string()),
             subject: Some("Email from Bob".to_string()),
             body: Some("Test email content.".to_string()),
+            ..Default::default()
         },
         Email {
             message_id: Some("test-from-2".to_string()),
@@ -89,6 +130,7 @@ pub async fn setup_test_db_all() -> Result<EmailDB, EmailDBError> {
             date: Some("2025-03-04T12:05:00Z".to_string()),
             subject: Some("Another email from Bob".to_string()),
             body: Some("Another test email.".to_string()),
+            ..Default::default()
         },
         Email {
             message_id: Some("test-from-3".to_string()),
@@ -97,6 +139,7 @@ pub async fn setup_test_db_all() -> Result<EmailDB, EmailDBError> {
             date: Some("2025-03-04T12:10:00Z".to_string()),
             subject: Some("Email from Alice".to_string()),
             body: Some("Control email content.".to_string()),
+            ..Default::default()
         },
     ];
     db.store_emails(&baseline).await?;
@@ -108,8 +151,8 @@ pub async fn setup_test_db_all() -> Result<EmailDB, EmailDBError> {
 /// Shared setup for explain-from-email matching tests
 pub async fn setup_email_matching_db() -> Result<EmailDB, EmailDBError> {
     let _ = env_logger::builder().is_test(true).try_init();
-    let url = config::meilisearch_url();
-    let admin_key = config::meilisearch_admin_key();
+    let url = config::load()?.meilisearch_url;
+    let admin_key = config::load()?.meilisearch_admin_key;
     let db = EmailDB::new(&url, Some(&admin_key), "emails_test").await?;
     db.clear().await?;
     let baseline = vec![
@@ -120,6 +163,7 @@ pub async fn setup_email_matching_db() -> Result<EmailDB, EmailDBError> {
             date: Some("2025-03-04T12:00:00Z".to_string()),
             subject: Some("Meeting tomorrow".to_string()),
             body: Some("Hi Alice's meeting request".to_string()),
+            ..Default::default()
         },
         Email {
             message_id: Some("parallels-email-1".to_string()),
@@ -128,6 +172,7 @@ pub async fn setup_email_matching_db() -> Result<EmailDB, EmailDBError> {
             date: Some("2025-03-05T09:00:00Z".to_string()),
             subject: Some("Activate your Parallels account".to_string()),
             body: Some("Account name is alice, please activate.".to_string()),
+            ..Default::default()
         },
     ];
     db.store_emails(&baseline).await?;